@@ -0,0 +1,18 @@
+DROP TABLE paper_orders;
+DROP TABLE paper_balances;
+DELETE FROM binance_accounts WHERE is_simulated = 1;
+CREATE TABLE binance_accounts_old (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  name VARCHAR NOT NULL UNIQUE,
+  selected tinyint NOT NULL default 0,
+  is_paper tinyint NOT NULL default 0,
+  api_key VARCHAR NOT NULL UNIQUE,
+  secret VARCHAR NOT NULL UNIQUE,
+  active_clock_stub INTEGER REFERENCES clock_stubs (id),
+  active_reservation INTEGER REFERENCES reservations (id),
+  active_transaction INTEGER REFERENCES transactions (id)
+);
+INSERT INTO binance_accounts_old (id, name, selected, is_paper, api_key, secret, active_clock_stub, active_reservation, active_transaction)
+SELECT id, name, selected, is_paper, api_key, secret, active_clock_stub, active_reservation, active_transaction FROM binance_accounts;
+DROP TABLE binance_accounts;
+ALTER TABLE binance_accounts_old RENAME TO binance_accounts;
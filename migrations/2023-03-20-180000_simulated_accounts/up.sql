@@ -0,0 +1,41 @@
+-- Simulated accounts have no api keys so they have to be nullable
+CREATE TABLE binance_accounts_new (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  name VARCHAR NOT NULL UNIQUE,
+  selected tinyint NOT NULL default 0,
+  is_paper tinyint NOT NULL default 0,
+  api_key VARCHAR UNIQUE,
+  secret VARCHAR UNIQUE,
+  active_clock_stub INTEGER REFERENCES clock_stubs (id),
+  active_reservation INTEGER REFERENCES reservations (id),
+  active_transaction INTEGER REFERENCES transactions (id),
+  is_simulated tinyint NOT NULL default 0
+);
+INSERT INTO binance_accounts_new (id, name, selected, is_paper, api_key, secret, active_clock_stub, active_reservation, active_transaction)
+SELECT id, name, selected, is_paper, api_key, secret, active_clock_stub, active_reservation, active_transaction FROM binance_accounts;
+DROP TABLE binance_accounts;
+ALTER TABLE binance_accounts_new RENAME TO binance_accounts;
+
+CREATE TABLE paper_balances (
+  account_id INTEGER NOT NULL REFERENCES binance_accounts (id) ON DELETE CASCADE,
+  asset VARCHAR NOT NULL,
+  free DOUBLE NOT NULL DEFAULT 0,
+  locked DOUBLE NOT NULL DEFAULT 0,
+  PRIMARY KEY (account_id, asset)
+);
+
+CREATE TABLE paper_orders (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  account_id INTEGER NOT NULL REFERENCES binance_accounts (id) ON DELETE CASCADE,
+  symbol VARCHAR NOT NULL,
+  base_asset VARCHAR NOT NULL,
+  quote_asset VARCHAR NOT NULL,
+  side VARCHAR NOT NULL,
+  price DOUBLE,
+  orig_qty DOUBLE NOT NULL,
+  executed_qty DOUBLE NOT NULL DEFAULT 0,
+  cummulative_quote_qty DOUBLE NOT NULL DEFAULT 0,
+  commission DOUBLE NOT NULL DEFAULT 0,
+  status VARCHAR NOT NULL,
+  order_time TEXT NOT NULL
+);
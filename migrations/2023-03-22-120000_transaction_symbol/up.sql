@@ -0,0 +1,9 @@
+-- Your SQL goes here
+ALTER TABLE transactions
+ADD COLUMN symbol TEXT NOT NULL DEFAULT 'BTCUSDT';
+
+-- Existing transactions were traded on whatever the global symbol was
+UPDATE transactions SET symbol = COALESCE(
+    (SELECT value FROM configs WHERE section = 'trading' AND key = 'symbol'),
+    'BTCUSDT'
+);
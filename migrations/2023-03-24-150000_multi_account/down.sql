@@ -0,0 +1,21 @@
+CREATE TABLE reservations_old (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  start_time  TEXT NOT NULL,
+  end_time TEXT NOT NULL,
+  alerted tinyint NOT NULL default 0,
+  user_id bigint NOT NULL REFERENCES users (id)
+);
+INSERT INTO reservations_old (id, start_time, end_time, alerted, user_id)
+SELECT id, start_time, end_time, alerted, user_id FROM reservations;
+DROP TABLE reservations;
+ALTER TABLE reservations_old RENAME TO reservations;
+
+ALTER TABLE clock_stubs DROP COLUMN account_id;
+
+-- Only one account can stay selected
+UPDATE binance_accounts SET active = 0 WHERE id != (SELECT MIN(id) FROM binance_accounts WHERE active = 1);
+ALTER TABLE binance_accounts RENAME COLUMN active TO selected;
+
+INSERT INTO configs (section, key, value_type, description, value)
+VALUES ('trading', 'account_name', 0, 'Active Account Name DO NOT SET IN /set_config only /account set command',
+  (SELECT name FROM binance_accounts WHERE selected = 1));
@@ -0,0 +1,24 @@
+-- Any number of accounts can be live at once so selected becomes a per account flag
+ALTER TABLE binance_accounts RENAME COLUMN selected TO active;
+
+-- Locks are per account
+ALTER TABLE clock_stubs ADD COLUMN account_id INTEGER REFERENCES binance_accounts (id);
+UPDATE clock_stubs SET account_id = (SELECT id FROM binance_accounts WHERE active = 1 LIMIT 1);
+
+-- Every account has its own reservation calendar, existing reservations belonged to the selected account
+CREATE TABLE reservations_new (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  start_time  TEXT NOT NULL,
+  end_time TEXT NOT NULL,
+  alerted tinyint NOT NULL default 0,
+  user_id bigint NOT NULL REFERENCES users (id),
+  account_id INTEGER NOT NULL REFERENCES binance_accounts (id) ON DELETE CASCADE
+);
+INSERT INTO reservations_new (id, start_time, end_time, alerted, user_id, account_id)
+SELECT r.id, r.start_time, r.end_time, r.alerted, r.user_id, a.id
+FROM reservations r, (SELECT id FROM binance_accounts WHERE active = 1 LIMIT 1) a;
+DROP TABLE reservations;
+ALTER TABLE reservations_new RENAME TO reservations;
+
+-- Replaced by the active flag
+DELETE FROM configs WHERE section = 'trading' AND key = 'account_name';
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE paper_orders
+DROP COLUMN triggered;
+ALTER TABLE paper_orders
+DROP COLUMN order_list_id;
+ALTER TABLE paper_orders
+DROP COLUMN stop_price;
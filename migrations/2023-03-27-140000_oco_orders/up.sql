@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- Stop-limit legs wait on stop_price and both legs of an OCO share order_list_id
+ALTER TABLE paper_orders
+ADD COLUMN stop_price DOUBLE;
+ALTER TABLE paper_orders
+ADD COLUMN order_list_id INTEGER;
+ALTER TABLE paper_orders
+ADD COLUMN triggered tinyint NOT NULL default 0;
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE transactions
+DROP COLUMN trailingStopLevel;
+ALTER TABLE transactions
+DROP COLUMN trailingStopPercent;
+ALTER TABLE transactions
+DROP COLUMN trailingStopOffset;
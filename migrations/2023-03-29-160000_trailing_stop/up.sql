@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- Offset is a fraction of the price when trailingStopPercent is set otherwise a price distance
+ALTER TABLE transactions
+ADD COLUMN trailingStopOffset DOUBLE;
+ALTER TABLE transactions
+ADD COLUMN trailingStopPercent tinyint NOT NULL default 0;
+ALTER TABLE transactions
+ADD COLUMN trailingStopLevel DOUBLE;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE paper_orders
+DROP COLUMN client_order_id;
+DROP TABLE orders;
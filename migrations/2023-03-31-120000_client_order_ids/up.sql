@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- Orders are recorded under their client order id before they are sent so a request that fails
+-- without an answer can be looked up on the exchange instead of sent twice
+CREATE TABLE orders (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  account_id INTEGER NOT NULL REFERENCES binance_accounts (id),
+  transaction_id INTEGER REFERENCES transactions (id),
+  client_order_id TEXT NOT NULL UNIQUE,
+  symbol TEXT NOT NULL,
+  side TEXT NOT NULL,
+  order_id BIGINT,
+  status TEXT NOT NULL default 'PENDING',
+  created_at TEXT NOT NULL
+);
+ALTER TABLE paper_orders
+ADD COLUMN client_order_id TEXT;
@@ -0,0 +1,30 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE transactions ADD COLUMN buyOrderIds varchar NOT NULL default '';
+ALTER TABLE transactions ADD COLUMN sellOrderIds varchar NOT NULL default '';
+UPDATE transactions SET
+  buyOrderIds = COALESCE((
+    SELECT group_concat(order_id, ',') FROM (
+      SELECT order_id FROM orders
+      WHERE transaction_id = transactions.id AND side = 'BUY' AND order_id IS NOT NULL ORDER BY created_at, id
+    )
+  ), ''),
+  sellOrderIds = COALESCE((
+    SELECT group_concat(order_id, ',') FROM (
+      SELECT order_id FROM orders
+      WHERE transaction_id = transactions.id AND side = 'SELL' AND order_id IS NOT NULL ORDER BY created_at, id
+    )
+  ), '');
+DELETE FROM orders WHERE client_order_id LIKE 'legacy-%';
+UPDATE orders SET status = 'PLACED' WHERE order_id IS NOT NULL AND status <> 'REJECTED';
+
+DROP TABLE fills;
+ALTER TABLE orders DROP COLUMN type_name;
+ALTER TABLE orders DROP COLUMN price;
+ALTER TABLE orders DROP COLUMN orig_qty;
+ALTER TABLE orders DROP COLUMN quote_qty;
+ALTER TABLE orders DROP COLUMN stop_price;
+ALTER TABLE orders DROP COLUMN executed_qty;
+ALTER TABLE orders DROP COLUMN cummulative_quote_qty;
+ALTER TABLE orders DROP COLUMN commission;
+ALTER TABLE orders DROP COLUMN commission_asset;
+ALTER TABLE orders DROP COLUMN updated_at;
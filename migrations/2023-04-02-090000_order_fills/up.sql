@@ -0,0 +1,65 @@
+-- Your SQL goes here
+-- The orders table becomes the record of every order the bot sends, kept up to date by the
+-- order tracker, with the trades that filled them in fills
+ALTER TABLE orders ADD COLUMN type_name TEXT;
+ALTER TABLE orders ADD COLUMN price DOUBLE;
+ALTER TABLE orders ADD COLUMN orig_qty DOUBLE;
+ALTER TABLE orders ADD COLUMN quote_qty DOUBLE;
+ALTER TABLE orders ADD COLUMN stop_price DOUBLE;
+ALTER TABLE orders ADD COLUMN executed_qty DOUBLE NOT NULL default 0;
+ALTER TABLE orders ADD COLUMN cummulative_quote_qty DOUBLE NOT NULL default 0;
+ALTER TABLE orders ADD COLUMN commission DOUBLE NOT NULL default 0;
+ALTER TABLE orders ADD COLUMN commission_asset TEXT;
+ALTER TABLE orders ADD COLUMN updated_at TEXT;
+
+CREATE TABLE fills (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  order_id INTEGER NOT NULL REFERENCES orders (id) ON DELETE CASCADE,
+  trade_id BIGINT,
+  price DOUBLE NOT NULL,
+  qty DOUBLE NOT NULL,
+  commission DOUBLE NOT NULL,
+  commission_asset TEXT NOT NULL,
+  time TEXT NOT NULL
+);
+-- the same trade arrives on the stream and in the order response
+CREATE UNIQUE INDEX fills_trade_id ON fills (order_id, trade_id);
+
+-- PLACED only meant the exchange answered, the tracker fills in the real status
+UPDATE orders SET status = 'UNKNOWN' WHERE status = 'PLACED';
+
+-- Split the comma joined ids into one row per order. They take the transaction's buy time so
+-- sorting by created_at then id keeps them in the order they were placed
+INSERT OR IGNORE INTO orders (account_id, transaction_id, client_order_id, symbol, side, order_id, status, created_at)
+WITH RECURSIVE split (transaction_id, side, position, order_id, rest) AS (
+  SELECT id, 'BUY', 0, NULL, buyOrderIds || ',' FROM transactions
+  UNION ALL
+  SELECT id, 'SELL', 0, NULL, sellOrderIds || ',' FROM transactions
+  UNION ALL
+  SELECT transaction_id, side, position + 1,
+    substr(rest, 1, instr(rest, ',') - 1),
+    substr(rest, instr(rest, ',') + 1)
+  FROM split WHERE rest <> ''
+)
+SELECT
+  COALESCE(c.account_id, (SELECT id FROM binance_accounts ORDER BY id LIMIT 1)),
+  t.id,
+  'legacy-' || t.id || '-' || s.order_id,
+  t.symbol,
+  s.side,
+  CAST(s.order_id AS BIGINT),
+  'UNKNOWN',
+  t.buyOrderTime
+FROM split s
+JOIN transactions t ON t.id = s.transaction_id
+JOIN clock_stubs c ON c.id = t.clock_stub_id
+WHERE s.order_id IS NOT NULL AND s.order_id <> ''
+  AND COALESCE(c.account_id, (SELECT id FROM binance_accounts ORDER BY id LIMIT 1)) IS NOT NULL
+  -- orders sent since client ids were recorded already have a row
+  AND NOT EXISTS (
+    SELECT 1 FROM orders o WHERE o.transaction_id = t.id AND o.order_id = CAST(s.order_id AS BIGINT)
+  )
+ORDER BY t.id, s.side, s.position;
+
+ALTER TABLE transactions DROP COLUMN buyOrderIds;
+ALTER TABLE transactions DROP COLUMN sellOrderIds;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Realized profit in the quote asset after commissions, transactions closed before this was
+-- tracked are left null
+ALTER TABLE transactions ADD COLUMN realizedPnl DOUBLE;
+ALTER TABLE transactions ADD COLUMN commission DOUBLE;
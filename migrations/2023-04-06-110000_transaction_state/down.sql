@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE transactions ADD COLUMN buyReady tinyint NOT NULL DEFAULT 0;
+ALTER TABLE transactions ADD COLUMN sellReady tinyint NOT NULL DEFAULT 0;
+UPDATE transactions SET
+  buyReady = state IN ('IDLE', 'FAILED'),
+  sellReady = state = 'LONG';
+DROP TABLE transaction_events;
+ALTER TABLE transactions DROP COLUMN state;
@@ -0,0 +1,31 @@
+-- Your SQL goes here
+-- One state column replaces the ready flags and every change to it is logged in
+-- transaction_events
+ALTER TABLE transactions ADD COLUMN state TEXT NOT NULL default 'IDLE';
+UPDATE transactions SET state = CASE
+  WHEN sellAvgPrice IS NOT NULL THEN 'CLOSED'
+  WHEN buyAvgPrice IS NOT NULL AND sellReady = 0 THEN 'SELL_PENDING'
+  WHEN buyAvgPrice IS NOT NULL THEN 'LONG'
+  WHEN buyReady = 0 THEN 'BUY_PENDING'
+  ELSE 'IDLE'
+END;
+-- transactions that were never closed out but are no longer any account's active one
+UPDATE transactions SET state = 'CLOSED'
+WHERE state <> 'CLOSED' AND id NOT IN (
+  SELECT active_transaction FROM binance_accounts WHERE active_transaction IS NOT NULL
+);
+
+CREATE TABLE transaction_events (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  transaction_id INTEGER NOT NULL REFERENCES transactions (id),
+  from_state TEXT,
+  to_state TEXT NOT NULL,
+  reason TEXT NOT NULL,
+  created_at TEXT NOT NULL
+);
+CREATE INDEX transaction_events_transaction ON transaction_events (transaction_id);
+INSERT INTO transaction_events (transaction_id, from_state, to_state, reason, created_at)
+SELECT id, NULL, state, 'migrated from ready flags', buyOrderTime FROM transactions ORDER BY id;
+
+ALTER TABLE transactions DROP COLUMN buyReady;
+ALTER TABLE transactions DROP COLUMN sellReady;
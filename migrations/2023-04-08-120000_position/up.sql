@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Base asset a transaction still holds and what each sell realized against the running
+-- average cost, both filled in the next time the transaction's position is worked out
+ALTER TABLE transactions ADD COLUMN positionQty DOUBLE;
+ALTER TABLE orders ADD COLUMN realized_pnl DOUBLE;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- GTC IOC or FOK for limit orders, market and post-only LIMIT_MAKER orders have none
+ALTER TABLE orders ADD COLUMN time_in_force TEXT;
@@ -0,0 +1,27 @@
+-- Your SQL goes here
+-- Orders held by the bot until the price meets their condition, then sent through the normal
+-- buy and sell path for the trader that set them
+CREATE TABLE conditional_orders (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  account_id INTEGER NOT NULL REFERENCES binance_accounts (id),
+  user_id BIGINT NOT NULL,
+  symbol TEXT NOT NULL,
+  side TEXT NOT NULL,
+  -- ABOVE BELOW CROSS_UP or CROSS_DOWN
+  condition TEXT NOT NULL,
+  trigger_price DOUBLE NOT NULL,
+  -- NULL sends a market order
+  limit_price DOUBLE,
+  quantity DOUBLE,
+  unit TEXT,
+  -- price at the last check, crossing conditions need to see it on the other side first
+  last_price DOUBLE,
+  -- ACTIVE until it TRIGGERED, FAILED to send, EXPIRED or was CANCELLED
+  status TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT NOT NULL,
+  triggered_at TEXT,
+  client_order_id TEXT,
+  detail TEXT
+);
+CREATE INDEX conditional_orders_account_status ON conditional_orders (account_id, status);
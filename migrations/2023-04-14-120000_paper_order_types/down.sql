@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE paper_orders DROP COLUMN type_name;
+ALTER TABLE paper_orders DROP COLUMN time_in_force;
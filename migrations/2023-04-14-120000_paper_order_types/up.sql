@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- Paper orders keep the type and time in force they were placed with so they report them the
+-- way binance would
+ALTER TABLE paper_orders ADD COLUMN type_name TEXT NOT NULL default 'LIMIT';
+ALTER TABLE paper_orders ADD COLUMN time_in_force TEXT;
+UPDATE paper_orders SET type_name = CASE
+  WHEN stop_price IS NOT NULL THEN 'STOP_LOSS_LIMIT'
+  WHEN order_list_id IS NOT NULL THEN 'LIMIT_MAKER'
+  WHEN price IS NOT NULL THEN 'LIMIT'
+  ELSE 'MARKET'
+END;
+UPDATE paper_orders SET time_in_force = 'GTC' WHERE type_name IN ('LIMIT', 'STOP_LOSS_LIMIT');
@@ -0,0 +1,161 @@
+use std::{collections::HashMap, sync::Arc};
+
+use arc_swap::ArcSwap;
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use tokio::sync::RwLock;
+use tracing::{debug, error, trace};
+
+use crate::{
+    binance_wrapped::BinanceWrapped,
+    config::Config,
+    db::establish_connection,
+    error::TradingBotError,
+    exchange::governor::RateGovernor,
+    market_data::MarketData,
+    models::BinanceAccount,
+};
+
+//Account currently locked by the user if any
+pub fn locked_account(user_id: i64) -> Result<Option<BinanceAccount>, TradingBotError> {
+    use crate::schema::{binance_accounts, clock_stubs};
+    let mut connection = establish_connection();
+    Ok(binance_accounts::table
+        .inner_join(clock_stubs::table)
+        .filter(binance_accounts::active.eq(true))
+        .filter(clock_stubs::user_id.eq(user_id))
+        .select(binance_accounts::all_columns)
+        .first::<BinanceAccount>(&mut connection)
+        .optional()?)
+}
+
+/// Every active binance account keyed by its id.
+///
+/// Each account has its own lock, reservations and transaction so commands pick one with
+/// `resolve` instead of sharing a single selected account. Accounts are handed out as clones so
+/// the map is only locked long enough to look one up.
+pub struct AccountManager {
+    accounts: RwLock<HashMap<i32, BinanceWrapped>>,
+    config: Arc<ArcSwap<Config>>,
+    market: Arc<MarketData>,
+    //request weight is per ip so every account shares one budget
+    governor: Arc<RateGovernor>,
+}
+
+impl AccountManager {
+    pub fn new(config: Arc<ArcSwap<Config>>, market: Arc<MarketData>) -> Self {
+        Self {
+            accounts: RwLock::new(HashMap::new()),
+            governor: Arc::new(RateGovernor::new(config.clone())),
+            config,
+            market,
+        }
+    }
+
+    //Load every account marked active in the db
+    pub async fn load(&self) -> Result<(), TradingBotError> {
+        use crate::schema::binance_accounts::dsl;
+        let mut connection = establish_connection();
+        let db_accounts = dsl::binance_accounts
+            .filter(dsl::active.eq(true))
+            .load::<BinanceAccount>(&mut connection)?;
+        let mut accounts = self.accounts.write().await;
+        accounts.clear();
+        for db_account in db_accounts {
+            let name = db_account.name.clone();
+            match BinanceWrapped::load(db_account, self.config.clone(), self.market.clone(), self.governor.clone()) {
+                Ok(account) => {
+                    debug!("Loaded account {name}");
+                    accounts.insert(account.id, account);
+                }
+                Err(err) => error!("Failed to load account {name} {err}"),
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn activate(&self, name: &str) -> Result<(), TradingBotError> {
+        use crate::schema::binance_accounts::dsl;
+        let mut connection = establish_connection();
+        let db_account = dsl::binance_accounts
+            .filter(dsl::name.eq(name))
+            .first::<BinanceAccount>(&mut connection)?;
+        let account = BinanceWrapped::load(db_account, self.config.clone(), self.market.clone(), self.governor.clone())?;
+        diesel::update(dsl::binance_accounts.filter(dsl::id.eq(account.id)))
+            .set(dsl::active.eq(true))
+            .execute(&mut connection)?;
+        self.accounts.write().await.insert(account.id, account);
+        Ok(())
+    }
+
+    pub async fn deactivate(&self, name: &str) -> Result<(), TradingBotError> {
+        let account = self.get(name).await?;
+        if account.is_clocked_in()?.is_some() {
+            return Err(TradingBotError::LockingBinanceAccount(format!(
+                "{name} is locked clock out first"
+            )));
+        }
+        use crate::schema::binance_accounts::dsl;
+        let mut connection = establish_connection();
+        diesel::update(dsl::binance_accounts.filter(dsl::id.eq(account.id)))
+            .set(dsl::active.eq(false))
+            .execute(&mut connection)?;
+        self.accounts.write().await.remove(&account.id);
+        //feeds only it was watching would otherwise keep their sockets until the next one is asked for
+        self.market.stop_unused();
+        Ok(())
+    }
+
+    pub fn governor(&self) -> Arc<RateGovernor> {
+        self.governor.clone()
+    }
+
+    pub async fn all(&self) -> Vec<BinanceWrapped> {
+        self.accounts.read().await.values().cloned().collect()
+    }
+
+    pub async fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.all().await.into_iter().map(|account| account.name).collect();
+        names.sort();
+        names
+    }
+
+    pub async fn get(&self, name: &str) -> Result<BinanceWrapped, TradingBotError> {
+        match self.all().await.into_iter().find(|account| account.name == name) {
+            Some(account) => Ok(account),
+            None => Err(TradingBotError::AccountNotFound(name.into())),
+        }
+    }
+
+    pub async fn get_by_id(&self, id: i32) -> Result<BinanceWrapped, TradingBotError> {
+        match self.accounts.read().await.get(&id) {
+            Some(account) => Ok(account.clone()),
+            None => Err(TradingBotError::AccountNotFound(id.to_string())),
+        }
+    }
+
+    /// Pick the account a command acts on.
+    ///
+    /// An explicit name wins, otherwise the account the user is clocked into, otherwise the
+    /// only active account if there is just one.
+    pub async fn resolve(
+        &self,
+        name: Option<String>,
+        user_id: i64,
+    ) -> Result<BinanceWrapped, TradingBotError> {
+        if let Some(name) = name {
+            return self.get(&name).await;
+        }
+        if let Some(locked) = locked_account(user_id)? {
+            trace!("Inferred account {} from lock", locked.name);
+            return self.get_by_id(locked.id).await;
+        }
+        let accounts = self.all().await;
+        match accounts.len() {
+            0 => Err(TradingBotError::BinanceAccountMissing),
+            1 => Ok(accounts[0].clone()),
+            _ => Err(TradingBotError::AccountNotFound(
+                "more than one account is active pick one with the account option".into(),
+            )),
+        }
+    }
+}
@@ -1,109 +1,149 @@
-use std::sync::Arc;
+use std::{collections::{BTreeSet, HashMap}, env, sync::{atomic::{AtomicU32, Ordering}, Arc, Mutex}, thread, time::Duration};
 
 use arc_swap::ArcSwap;
-use binance::{account::Account, api::Binance, model::{Order, Filters, Balance}, market::Market, general::General,model::Transaction};
-use chrono::Utc;
+use binance::model::{Balance, FillInfo, Order, Symbol, Transaction};
+use chrono::{DateTime, TimeZone, Utc};
 use diesel::{QueryDsl, RunQueryDsl};
-use serenity::futures::future::OrElse;
 use tracing::{warn, trace, debug, error, instrument};
 
 use crate::{
-    config::{Config, ValueType},
+    circuit_breaker,
+    conditional::{self, Condition, Outcome},
+    config::Config,
     db::establish_connection,
     error::TradingBotError,
-    models::{BinanceAccount, ClockStub, Reservation, NewClockStub, NewTransaction, DBTransaction},
-    schema::binance_accounts,
+    exchange::{binance_client::BinanceExchange, filters::SymbolFilters, governor::{GovernedExchange, Priority, RateGovernor}, mock::MockExchange, is_final_status, is_oco_leg, oco_leg_ids, order_to_transaction, send_failure, transaction_to_order, OcoOrder, OcoRequest, SendFailure, simulated::SimulatedExchange, user_stream::{StreamCache, StreamedExchange}, Exchange, OrderOptions, OrderQuantity, OrderRequest, OrderSide, TimeInForce},
+    market_data::{Feed, MarketData},
+    transaction_state::{self, TransactionState},
+    sizing::{SizedOrder, Sizing},
+    models::{BinanceAccount, ClockStub, Reservation, NewClockStub, NewTransaction, NewOrder, NewFill, DBOrder, DBTransaction, Fill, ConditionalOrder, NewConditionalOrder},
 };
 
-
+/// Handle to one binance account.
+///
+/// Everything it holds is shared so clones are cheap and callers take their own copy instead of
+/// holding a lock on the account while they work.
+#[derive(Clone)]
 pub struct BinanceWrapped {
     pub id: i32,
-    pub account: Option<Account>,
-    pub market: Market,
-    pub general: Option<General>,
+    pub name: String,
+    pub exchange: Arc<dyn Exchange>,
+    //rest calls before the stream cache, re-prioritised by `at_priority`
+    rest: GovernedExchange,
+    //fills and balances pushed by the user data stream
+    pub stream: Arc<StreamCache>,
+    market: Arc<MarketData>,
     config: Arc<ArcSwap<Config>>,
+    //exchange info is heavy so the tradable symbols are only pulled once per account
+    symbols: Arc<Mutex<Vec<String>>>,
 }
 impl BinanceWrapped {
-    pub fn new(config: Arc<ArcSwap<Config>>) -> Self {
+    pub fn load(db_account: BinanceAccount, config: Arc<ArcSwap<Config>>, market: Arc<MarketData>, governor: Arc<RateGovernor>) -> Result<Self, TradingBotError> {
+        trace!("Loading Account {} isPaper{} isSimulated{}",db_account.name,db_account.is_paper,db_account.is_simulated);
+        //only what reaches binance counts against the request weight
+        let rest = if env::var("MOCK_EXCHANGE").is_ok() {
+            warn!("MOCK_EXCHANGE is set orders will not reach binance");
+            GovernedExchange::new(Arc::new(MockExchange::with_defaults()), None)
+        } else if db_account.is_simulated {
+            let commission_rate = match config.load().get::<f64>("paper", "commission_rate")? {
+                Some(rate) => rate,
+                None => 0.001,
+            };
+            //paper orders stay local but their prices come from binance
+            GovernedExchange::simulated(Arc::new(SimulatedExchange::new(db_account.id, commission_rate)), governor)
+        } else {
+            let (Some(api_key), Some(secret)) = (db_account.api_key, db_account.secret) else {
+                return Err(TradingBotError::ConfigError(format!("Account {} has no api keys",db_account.name)));
+            };
+            GovernedExchange::new(Arc::new(BinanceExchange::new(api_key, secret, db_account.is_paper)), Some(governor))
+        };
+        Ok(Self::on_exchange(db_account.id, db_account.name, rest, config, market))
+    }
+
+    //Account trading through an exchange that is already built, tests hand it their own mock
+    pub(crate) fn on_exchange(id: i32, name: String, rest: GovernedExchange, config: Arc<ArcSwap<Config>>, market: Arc<MarketData>) -> Self {
+        let stream = Arc::new(StreamCache::default());
         Self {
-            id: 0,
-            account: None,
-            market: Binance::new(
-                None,
-                None,
-            ),
-            general:None, 
+            id,
+            name,
+            exchange: Arc::new(StreamedExchange::new(Arc::new(rest.clone()), stream.clone())),
+            rest,
+            stream,
+            market,
             config,
+            symbols: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Run exchange calls on the blocking pool.
+    ///
+    /// The binance crate is synchronous so anything that reaches the exchange goes through here
+    /// instead of stalling a runtime worker.
+    pub async fn blocking<T, F>(&self, task: F) -> Result<T, TradingBotError>
+    where
+        F: FnOnce(BinanceWrapped) -> Result<T, TradingBotError> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.blocking_at(Priority::Normal, task).await
+    }
+
+    /// `blocking` with reads charged at `priority`, views that refresh on a timer use
+    /// `Priority::Low` so they are shed before anything else
+    pub async fn blocking_at<T, F>(&self, priority: Priority, task: F) -> Result<T, TradingBotError>
+    where
+        F: FnOnce(BinanceWrapped) -> Result<T, TradingBotError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let account = self.at_priority(priority);
+        match tokio::task::spawn_blocking(move || task(account)).await {
+            Ok(result) => result,
+            Err(err) => Err(TradingBotError::ExchangeError(format!("Exchange task failed {err}"))),
+        }
+    }
+
+    fn at_priority(&self, priority: Priority) -> BinanceWrapped {
+        let rest = self.rest.with_priority(priority);
+        BinanceWrapped {
+            exchange: Arc::new(StreamedExchange::new(Arc::new(rest.clone()), self.stream.clone())),
+            rest,
+            ..self.clone()
         }
     }
 
+    //Orders sent in the current ten second window
+    pub fn orders_used(&self) -> u32 {
+        self.rest.orders_used()
+    }
+
     //Get for market data or balance nothing with trading
-    pub fn get(&self) -> Result<Account, TradingBotError> {
-        let Some(account) = self.account.clone() else {
-            return Err(TradingBotError::BinanceAccountMissing)
-        };
-        Ok(account)
+    pub fn get(&self) -> Result<Arc<dyn Exchange>, TradingBotError> {
+        Ok(self.exchange.clone())
     }
 
-    pub fn load_account(&mut self) -> Result<(), TradingBotError> { 
-        let config = self.config.load();
-        let Some(account_name) = config.get::<String>("trading", "account_name")? else{
-            return Err(TradingBotError::ConfigError("No account name set".into()));
-        };
-        let mut connection = establish_connection();
-        use crate::schema::binance_accounts::dsl;
-        use diesel::ExpressionMethods;
+    //Symbol used when an order doesn't name one
+    pub fn default_symbol(&self) -> Result<String, TradingBotError> {
+        Ok(match self.config.load().get::<String>("trading", "symbol")? {
+            Some(symbol) => symbol,
+            None => "BTCUSDT".into(),
+        })
+    }
 
-        //unselect previous account if one
-        diesel::update(dsl::binance_accounts.filter(dsl::selected.eq(true)))
-            .set(dsl::selected.eq(false))
-            .execute(&mut connection)?;
+    //Last price from the shared market data stream for this account's environment
+    pub fn price(&self, symbol: &str) -> Result<f64, TradingBotError> {
+        self.market.price(self.exchange.as_ref(), symbol)
+    }
 
-        let db_account = dsl::binance_accounts
-            .filter(dsl::name.eq(account_name.clone()))
-            .first::<BinanceAccount>(&mut connection)?;
-        trace!("Setting Account to {account_name} isPaper{}",db_account.is_paper);
-        let account: Account;
-        let market: Market;
-        let general: General;
-        if db_account.is_paper {
-           
-            account = Binance::new_with_config(
-                Some(db_account.api_key.clone()),
-                Some(db_account.secret.clone()),
-                &binance::config::Config::default()
-                    .set_rest_api_endpoint("https://testnet.binance.vision"),
-            );
-            market = Binance::new_with_config(
-                None,
-                None,
-                &binance::config::Config::default().set_rest_api_endpoint("https://testnet.binance.vision"),
-            );
-            general = General::new_with_config(
-                Some(db_account.api_key),
-                Some(db_account.secret),
-                &binance::config::Config::default().set_rest_api_endpoint("https://testnet.binance.vision"),
-            );
-        } else {
-            account = Binance::new(Some(db_account.api_key.clone()), Some(db_account.secret.clone()));
-            market = Binance::new(
-                None,
-                None,
-            );
-            general = General::new(
-                Some(db_account.api_key),
-                Some(db_account.secret),
-            );
-            
+    pub fn price_feed(&self, symbol: &str) -> Option<Arc<Feed>> {
+        self.market.feed(self.exchange.as_ref(), symbol)
+    }
+
+    pub fn get_symbols(&self) -> Result<Vec<String>, TradingBotError> {
+        let mut symbols = self.symbols.lock().unwrap();
+        if symbols.is_empty() {
+            *symbols = self.get()?.get_symbols()?;
+            debug!("Loaded {} tradable symbols", symbols.len());
         }
-        diesel::update(dsl::binance_accounts.filter(dsl::name.eq(db_account.name)))
-            .set(dsl::selected.eq(true))
-            .execute(&mut connection)?;
-        self.account = Some(account);
-        self.market = market;
-        self.general = Some(general);
-        self.id = db_account.id;
-        Ok(())
+        Ok(symbols.clone())
     }
 }
 
@@ -114,13 +154,13 @@ impl BinanceWrapped{
         use crate::schema::binance_accounts::dsl;
         use diesel::ExpressionMethods;
         let mut connection = establish_connection();
-        return Ok(dsl::binance_accounts.filter(dsl::selected.eq(true)).get_result::<BinanceAccount>(&mut connection)?);
+        return Ok(dsl::binance_accounts.filter(dsl::id.eq(self.id)).get_result::<BinanceAccount>(&mut connection)?);
     }
     pub fn is_clocked_in(&self) -> Result<Option<ClockStub>,TradingBotError>{
         use crate::schema::binance_accounts::dsl;
         use diesel::ExpressionMethods;
         let mut connection = establish_connection();
-        let result = match dsl::binance_accounts.filter(dsl::selected.eq(true)).select(dsl::active_clock_stub).first::<Option<i32>>(&mut connection){
+        let result = match dsl::binance_accounts.filter(dsl::id.eq(self.id)).select(dsl::active_clock_stub).first::<Option<i32>>(&mut connection){
             Ok(a) => a,
             Err(err) => {
                 trace!("Clocking in error {err:?}");
@@ -168,7 +208,7 @@ impl BinanceWrapped{
         use crate::schema::binance_accounts::dsl;
         use diesel::ExpressionMethods;
         let mut connection = establish_connection();
-        let result = dsl::binance_accounts.filter(dsl::selected.eq(true)).select(dsl::active_reservation).first::<Option<i32>>(&mut connection)?;
+        let result = dsl::binance_accounts.filter(dsl::id.eq(self.id)).select(dsl::active_reservation).first::<Option<i32>>(&mut connection)?;
 
         let Some(reservation_id) = result else{
             return Ok(None);
@@ -218,7 +258,7 @@ impl BinanceWrapped{
 
             use crate::schema::binance_accounts::dsl;
             use diesel::ExpressionMethods;
-            diesel::update(dsl::binance_accounts.filter(dsl::selected.eq(true)))
+            diesel::update(dsl::binance_accounts.filter(dsl::id.eq(self.id)))
             .set(dsl::active_clock_stub.eq::<Option<i32>>(None))
             .execute(&mut connection)?;
 
@@ -252,6 +292,12 @@ impl BinanceWrapped{
                 self.unlock(Some(clock_stub.user_id))?;
             }
         }
+        //a trader only holds one account at a time so commands can infer which one they mean
+        if let Some(other) = crate::account_manager::locked_account(user_id)?{
+            if other.id != self.id{
+                return Err(TradingBotError::LockingBinanceAccount(format!("Already clocked into {}",other.name)));
+            }
+        }
         trace!("Locking account for user");
 
         
@@ -268,6 +314,7 @@ impl BinanceWrapped{
                 start_time: Utc::now(),
                 user_id,
                 last_interaction: Utc::now(),
+                account_id: self.id,
             }).get_result::<ClockStub>(&mut connection)?.clone();
             trace!("Clock stub created");
         }
@@ -276,7 +323,7 @@ impl BinanceWrapped{
 
             use crate::schema::binance_accounts::dsl;
             use diesel::ExpressionMethods;
-            diesel::update(dsl::binance_accounts.filter(dsl::selected.eq(true)))
+            diesel::update(dsl::binance_accounts.filter(dsl::id.eq(self.id)))
             .set(dsl::active_clock_stub.eq(Some(clock_stub.id)))
             .execute(&mut connection)?;
             debug!("Clock stub Active and account is locked");
@@ -290,111 +337,959 @@ impl BinanceWrapped{
 
 
 
-//Buying Selling Order etc
+//Disambiguates client order ids made in the same millisecond
+static ORDER_SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+//Order placement
 impl BinanceWrapped{
-    pub fn get_balance(&self) -> Result<(Balance,Balance),TradingBotError>{
-        let Some(account) = self.account.as_ref()  else{
-            return Err(TradingBotError::BinanceAccountMissing);
-        };
-        let symbol = match self.config.load().get::<String>("trading", "symbol")? {
-            Some(symbol) => symbol,
-            None => "BTCUSDT".into(),
+    //binance allows up to 36 characters
+    fn new_client_order_id(&self) -> String {
+        let sequence = ORDER_SEQUENCE.fetch_add(1, Ordering::Relaxed) % 1000;
+        format!("tb{}-{}-{sequence}", self.id, Utc::now().timestamp_millis())
+    }
+
+    //Written before the order is sent so an unanswered request can still be looked up
+    fn record_order(&self,order: NewOrder) -> Result<(),TradingBotError>{
+        use crate::schema::orders::dsl;
+        let mut connection = establish_connection();
+        diesel::insert_into(dsl::orders).values(order).execute(&mut connection)?;
+        Ok(())
+    }
+
+    //Only moves orders still PENDING, the user stream may have got to them first
+    fn settle_order(&self,client_order_id: &str,status: &str,order_id: Option<u64>) -> Result<(),TradingBotError>{
+        use crate::schema::orders::dsl;
+        use diesel::ExpressionMethods;
+        let mut connection = establish_connection();
+        diesel::update(dsl::orders.filter(dsl::client_order_id.eq(client_order_id)).filter(dsl::status.eq("PENDING")))
+            .set((dsl::status.eq(status),dsl::order_id.eq(order_id.map(|id| id as i64)),dsl::updated_at.eq(Some(Utc::now()))))
+            .execute(&mut connection)?;
+        trace!("Order {client_order_id} {status}");
+        Ok(())
+    }
+
+    //Move the transaction on once its order is sent, an order that may or may not exist fails it
+    fn settle_transaction<T>(&self,transaction: &DBTransaction,result: &Result<T,TradingBotError>,next: TransactionState,reason: &str) -> Result<(),TradingBotError>{
+        match result {
+            Ok(_) => {
+                transaction_state::transition(transaction, next, reason)?;
+            }
+            Err(TradingBotError::OrderUnknown(err)) => {
+                transaction_state::transition(transaction, TransactionState::Failed, &format!("order state unknown {err}"))?;
+            }
+            Err(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Send an order retrying whatever is safe to retry.
+    ///
+    /// A send that fails without an answer is looked up by its client order id before anything
+    /// is sent again, so a timeout never turns into a second order. If the lookup can't answer
+    /// either the error is `OrderUnknown` and the order row stays PENDING.
+    fn send_with_retry<T>(&self,client_order_id: &str,send: impl Fn() -> Result<T,TradingBotError>,find: impl Fn() -> Result<Option<T>,TradingBotError>) -> Result<T,TradingBotError>{
+        let config = self.config.load();
+        let retries = match config.get::<u32>("trading", "order_retries")? {
+            Some(retries) => retries,
+            None => 3,
         };
-        let general = binance::general::General{
-            client:account.client.clone()
+        let backoff = match config.get::<u64>("trading", "order_retry_backoff_ms")? {
+            Some(ms) => Duration::from_millis(ms),
+            None => Duration::from_millis(500),
         };
-        let symbol_info = general.get_symbol_info(symbol)?;
-        let quote_balance = account.get_balance(symbol_info.quote_asset)?;
-        let base_balance = account.get_balance(symbol_info.base_asset)?;
-        return Ok((base_balance,quote_balance))
+        let mut attempt = 0;
+        //an earlier send may still land so a later rejection could be its duplicate
+        let mut ambiguous = false;
+        loop {
+            let err = match send() {
+                Ok(order) => return Ok(order),
+                Err(err) => err,
+            };
+            match send_failure(&err) {
+                SendFailure::Rejected => {
+                    if ambiguous {
+                        if let Ok(Some(order)) = find() {
+                            return Ok(order);
+                        }
+                    }
+                    return Err(err);
+                }
+                SendFailure::Retry => warn!("Order {client_order_id} refused {err} retrying"),
+                SendFailure::Unknown => {
+                    warn!("Order {client_order_id} failed without an answer {err} looking it up");
+                    ambiguous = true;
+                    let mut lookup = 0;
+                    loop {
+                        match find() {
+                            Ok(Some(order)) => return Ok(order),
+                            Ok(None) => break,
+                            Err(lookup_err) if lookup >= retries => {
+                                error!("Order {client_order_id} could not be looked up {lookup_err}");
+                                return Err(TradingBotError::OrderUnknown(format!("{client_order_id} may have been placed {err}")));
+                            }
+                            Err(lookup_err) => warn!("Looking up order {client_order_id} failed {lookup_err}"),
+                        }
+                        lookup += 1;
+                        thread::sleep(backoff * 2u32.pow(lookup));
+                    }
+                    debug!("Order {client_order_id} never reached the exchange");
+                }
+            }
+            attempt += 1;
+            if attempt > retries {
+                return Err(err);
+            }
+            thread::sleep(backoff * 2u32.pow(attempt));
+        }
     }
-    #[instrument(skip(self))]
-    pub fn buy(&self,price:Option<f32>,percentage: Option<f64>) -> Result<Transaction,TradingBotError>{
-        let Some(stub) = self.is_clocked_in()? else {
-            return Err(TradingBotError::NotClockedIn(String::new()))
-        };
-        let opt_transaction = self.get_transaction()?;
-        if let Some(transaction) = &opt_transaction{
-            if transaction.buyAvgPrice.is_some() || !transaction.buyReady{
-                return Err(TradingBotError::ActiveTransaction("Must sell before buying or wait for previous order to settle".into()))
+
+    //Orders that can't be settled either way are left PENDING for reconciliation
+    fn settle_result<T>(&self,client_order_ids: &[&str],result: &Result<T,TradingBotError>,order_ids: impl Fn(&T) -> Vec<u64>) -> Result<(),TradingBotError>{
+        match result {
+            Ok(order) => {
+                for (client_order_id,order_id) in client_order_ids.iter().zip(order_ids(order)) {
+                    self.settle_order(client_order_id, "NEW", Some(order_id))?;
+                }
+            }
+            Err(TradingBotError::OrderUnknown(_)) => {}
+            Err(_) => {
+                for client_order_id in client_order_ids {
+                    self.settle_order(client_order_id, "REJECTED", None)?;
+                }
             }
         }
-        let Some(account) = self.account.as_ref()  else{
-            error!("Account is missing");
-            return Err(TradingBotError::BinanceAccountMissing);
+        Ok(())
+    }
+
+    fn place_order(&self,transaction_id: Option<i32>,mut request: OrderRequest) -> Result<Transaction,TradingBotError>{
+        let client_order_id = self.file_order(transaction_id, &mut request)?;
+        self.send_order(&client_order_id, &request)
+    }
+
+    //Give the request a client order id and write its row, it is PENDING until `send_order`
+    fn file_order(&self,transaction_id: Option<i32>,request: &mut OrderRequest) -> Result<String,TradingBotError>{
+        let client_order_id = self.new_client_order_id();
+        request.client_order_id = Some(client_order_id.clone());
+        let (orig_qty,quote_qty) = match request.quantity {
+            OrderQuantity::Base(qty) => (Some(qty),None),
+            OrderQuantity::Quote(qty) => (None,Some(qty)),
         };
-        let symbol = match self.config.load().get::<String>("trading", "symbol")? {
-            Some(symbol) => symbol,
-            None => "BTCUSDT".into(),
+        self.record_order(NewOrder{
+            account_id: self.id,
+            transaction_id,
+            client_order_id: &client_order_id,
+            symbol: &request.symbol,
+            side: request.side.as_str(),
+            created_at: Utc::now(),
+            type_name: request.type_name(),
+            price: request.price,
+            orig_qty,
+            quote_qty,
+            stop_price: None,
+            time_in_force: request.time_in_force(),
+        })?;
+        Ok(client_order_id)
+    }
+
+    fn send_order(&self,client_order_id: &str,request: &OrderRequest) -> Result<Transaction,TradingBotError>{
+        let exchange = self.get()?;
+        let result = self.send_with_retry(
+            client_order_id,
+            || exchange.place_order(request),
+            || Ok(exchange.order_by_client_id(&request.symbol, client_order_id)?.map(order_to_transaction)),
+        );
+        self.settle_result(&[client_order_id], &result, |order| vec![order.order_id])?;
+        if let Ok(order) = &result {
+            self.track_order(&transaction_to_order(order))?;
+            for fill in order.fills.iter().flatten() {
+                self.record_fill(order.order_id, fill, order.transact_time)?;
+            }
+        }
+        result
+    }
+
+    fn place_oco(&self,transaction_id: i32,mut request: OcoRequest) -> Result<OcoOrder,TradingBotError>{
+        let exchange = self.get()?;
+        let client_order_id = self.new_client_order_id();
+        let (take_profit_id,stop_loss_id) = oco_leg_ids(&client_order_id);
+        request.client_order_id = Some(client_order_id.clone());
+        let leg = |client_order_id,type_name,price,stop_price,time_in_force| NewOrder{
+            account_id: self.id,
+            transaction_id: Some(transaction_id),
+            client_order_id,
+            symbol: &request.symbol,
+            side: OrderSide::Sell.as_str(),
+            created_at: Utc::now(),
+            type_name,
+            price: Some(price),
+            orig_qty: Some(request.quantity),
+            quote_qty: None,
+            stop_price,
+            time_in_force,
         };
-        let Some(general) = self.general.as_ref()  else{
-            error!("General is missing but not account");
-            return Err(TradingBotError::BinanceAccountMissing);
+        self.record_order(leg(&take_profit_id, "LIMIT_MAKER", request.take_profit, None, None))?;
+        self.record_order(leg(&stop_loss_id, "STOP_LOSS_LIMIT", request.stop_limit_price, Some(request.stop_price), Some("GTC")))?;
+        let result = self.send_with_retry(
+            &client_order_id,
+            || exchange.place_oco(&request),
+            || {
+                let take_profit = exchange.order_by_client_id(&request.symbol, &take_profit_id)?;
+                let stop_loss = exchange.order_by_client_id(&request.symbol, &stop_loss_id)?;
+                Ok(match (take_profit,stop_loss) {
+                    (Some(take_profit),Some(stop_loss)) => Some(OcoOrder{
+                        order_list_id: take_profit.order_list_id,
+                        take_profit_id: take_profit.order_id,
+                        stop_loss_id: stop_loss.order_id,
+                    }),
+                    _ => None,
+                })
+            },
+        );
+        self.settle_result(&[&take_profit_id,&stop_loss_id], &result, |order| vec![order.take_profit_id,order.stop_loss_id])?;
+        result
+    }
+}
+
+//Order tracking
+impl BinanceWrapped{
+    /// Orders on a transaction in the order they were placed as last recorded, nothing is fetched
+    pub fn transaction_orders(&self,transaction_id: i32) -> Result<Vec<DBOrder>,TradingBotError>{
+        use crate::schema::orders::dsl;
+        use diesel::ExpressionMethods;
+        let mut connection = establish_connection();
+        Ok(dsl::orders
+            .filter(dsl::transaction_id.eq(Some(transaction_id)))
+            .order((dsl::created_at.asc(),dsl::id.asc()))
+            .load::<DBOrder>(&mut connection)?)
+    }
+
+    /// Bring an order's row in line with the exchange's view of it.
+    ///
+    /// Orders the bot didn't place have no row and give None. An order that has settled keeps
+    /// its status even if an older update arrives after it.
+    pub fn track_order(&self,order: &Order) -> Result<Option<DBOrder>,TradingBotError>{
+        use crate::schema::orders::dsl;
+        use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension};
+        let mut connection = establish_connection();
+        let Some(row) = dsl::orders
+            .filter(dsl::account_id.eq(self.id))
+            .filter(dsl::order_id.eq(Some(order.order_id as i64)).or(dsl::client_order_id.eq(&order.client_order_id)))
+            .first::<DBOrder>(&mut connection)
+            .optional()? else {
+            return Ok(None);
         };
-        let symbol_info = general.get_symbol_info(&symbol)?;
-        debug!("Getting Symbol Info for {}",symbol);
-        let Ok(balance) = account.get_balance(symbol_info.quote_asset)?.free.parse::<f64>() else{
-            return Err(TradingBotError::ParsingDataError("Could no parse balance".into()));
+        if is_final_status(&row.status) && !is_final_status(&order.status){
+            return Ok(Some(row));
+        }
+        let parse = |value: &str| value.parse::<f64>().ok().filter(|value| *value > 0.0);
+        let row = diesel::update(dsl::orders.filter(dsl::id.eq(row.id))).set((
+            dsl::order_id.eq(Some(order.order_id as i64)),
+            dsl::status.eq(&order.status),
+            dsl::type_name.eq(Some(&order.type_name)),
+            //only limits keep a time in force, binance reports GTC on the rest
+            dsl::time_in_force.eq(row.time_in_force.clone().or(Some(order.time_in_force.clone()).filter(|_| order.type_name == "LIMIT"))),
+            //orders migrated from the id strings only learn what was asked for here
+            dsl::price.eq(row.price.or(Some(order.price).filter(|price| *price > 0.0))),
+            dsl::orig_qty.eq(row.orig_qty.or(parse(&order.orig_qty))),
+            dsl::stop_price.eq(row.stop_price.or(Some(order.stop_price).filter(|price| *price > 0.0))),
+            dsl::executed_qty.eq(parse(&order.executed_qty).unwrap_or(0.0)),
+            dsl::cummulative_quote_qty.eq(parse(&order.cummulative_quote_qty).unwrap_or(0.0)),
+            dsl::updated_at.eq(Some(Utc::now())),
+        )).get_result::<DBOrder>(&mut connection)?;
+        trace!("Order {} {}",row.client_order_id,row.status);
+        Ok(Some(row))
+    }
+
+    /// File a trade against the order it filled and total the order's commission.
+    ///
+    /// The same trade comes in the order response and on the user stream so repeats are dropped.
+    pub fn record_fill(&self,order_id: u64,fill: &FillInfo,time: u64) -> Result<(),TradingBotError>{
+        use crate::schema::{fills, orders::dsl};
+        use diesel::{ExpressionMethods, OptionalExtension};
+        let mut connection = establish_connection();
+        let Some(row) = dsl::orders
+            .filter(dsl::account_id.eq(self.id))
+            .filter(dsl::order_id.eq(Some(order_id as i64)))
+            .select(dsl::id)
+            .first::<i32>(&mut connection)
+            .optional()? else {
+            return Ok(());
         };
-        let adjusted_balance = format!("{:.5}",(balance - 1.0) * percentage.unwrap_or(1.0)).parse::<f64>().unwrap();
-        let order: Transaction;
-        if let Some(price) = price{
-            let price = format!("{:.1$}",price,2).parse::<f64>().unwrap();
+        diesel::insert_or_ignore_into(fills::dsl::fills).values(NewFill{
+            order_id: row,
+            trade_id: fill.trade_id.map(|id| id as i64),
+            price: fill.price,
+            qty: fill.qty,
+            commission: fill.commission,
+            commission_asset: &fill.commission_asset,
+            time: Utc.timestamp_millis_opt(time as i64).unwrap(),
+        }).execute(&mut connection)?;
+        let commission = fills::dsl::fills
+            .filter(fills::dsl::order_id.eq(row))
+            .select(diesel::dsl::sum(fills::dsl::commission))
+            .first::<Option<f64>>(&mut connection)?;
+        diesel::update(dsl::orders.filter(dsl::id.eq(row))).set((
+            dsl::commission.eq(commission.unwrap_or(0.0)),
+            dsl::commission_asset.eq(Some(&fill.commission_asset)),
+        )).execute(&mut connection)?;
+        Ok(())
+    }
 
+    /// Refresh this account's open orders on a transaction from the exchange and return all of
+    /// its orders.
+    ///
+    /// Settled orders are never fetched again. Orders still PENDING have no exchange id and are
+    /// left for reconciliation.
+    pub fn refresh_orders(&self,transaction_id: i32) -> Result<Vec<DBOrder>,TradingBotError>{
+        let exchange = self.get()?;
+        for order in self.transaction_orders(transaction_id)?{
+            if order.account_id != self.id || is_final_status(&order.status){
+                continue;
+            }
+            self.refresh_order(exchange.as_ref(), &order)?;
+        }
+        self.transaction_orders(transaction_id)
+    }
 
-            let quantity = format!("{:.5}",adjusted_balance/(price as f64)).parse::<f64>().unwrap();
-            if quantity < 0.0{
-                return Err(TradingBotError::ParsingDataError("Insuffecient balance".into()))
+    //Fetch one order by its exchange id, None if it has none yet
+    fn refresh_order(&self,exchange: &dyn Exchange,order: &DBOrder) -> Result<Option<DBOrder>,TradingBotError>{
+        let Some(order_id) = order.order_id else {
+            return Ok(None);
+        };
+        match exchange.order_status(&order.symbol, order_id as u64){
+            Ok(found) => self.track_order(&found),
+            //the exchange doesn't know the id so stop waiting on it
+            Err(err) if send_failure(&err) == SendFailure::Rejected => {
+                warn!("Order {} could not be found {err}",order.client_order_id);
+                use crate::schema::orders::dsl;
+                use diesel::ExpressionMethods;
+                let mut connection = establish_connection();
+                Ok(Some(diesel::update(dsl::orders.filter(dsl::id.eq(order.id)))
+                    .set((dsl::status.eq("REJECTED"),dsl::updated_at.eq(Some(Utc::now()))))
+                    .get_result::<DBOrder>(&mut connection)?))
             }
+            Err(err) => Err(err),
+        }
+    }
+}
 
-            debug!("Sending buy limit order for %{} of account with Qty:{} @{}",percentage.unwrap_or(1.0)*100.,quantity,price);
-            order = account.limit_buy(symbol, quantity, price as f64)?;
+/// What a transaction holds and has made so far in the quote asset
+#[derive(Debug, Clone, Default)]
+pub struct Position {
+    //base asset still held
+    pub qty: f64,
+    //average cost of what is held including buy commission
+    pub avg_cost: f64,
+    pub realized: f64,
+    pub commission: f64,
+    pub quote_asset: String,
+}
 
-        }else{
-            debug!("Sending buy market order for %{} of account",percentage.unwrap_or(1.0)*100.);
-            order = account.market_buy_using_quote_quantity(&symbol, adjusted_balance)?;
-        }
-        //file transaction
-        if opt_transaction.is_none(){
-            let transaction: DBTransaction;
-            {
-                use crate::schema::transactions::dsl;
-                let mut connection = establish_connection();
-                transaction = diesel::insert_into(dsl::transactions).values(NewTransaction{
-                    clock_stub_id: stub.id,
-                    buyOrderTime:Utc::now(),
-                    buyOrderIds: format!("{}",order.order_id),
-                    sellOrderIds: "".into(),
-                }).get_result(&mut connection)?;
-                trace!("Transaction Created")
+//Position
+impl BinanceWrapped{
+    //Fills for an order that traded without its trades being seen are pulled from the exchange
+    fn order_fills(&self,order: &DBOrder) -> Result<Vec<Fill>,TradingBotError>{
+        use crate::schema::fills::dsl;
+        use diesel::ExpressionMethods;
+        let mut connection = establish_connection();
+        let fills = dsl::fills.filter(dsl::order_id.eq(order.id)).load::<Fill>(&mut connection)?;
+        let filled: f64 = fills.iter().map(|fill| fill.qty).sum();
+        let (Some(order_id),true) = (order.order_id,filled < order.executed_qty * 0.999999) else {
+            return Ok(fills);
+        };
+        trace!("Order {} has {filled} of {} in fills pulling its trades",order.client_order_id,order.executed_qty);
+        let time = order.updated_at.unwrap_or(order.created_at).timestamp_millis() as u64;
+        for fill in self.get()?.order_fills(&order.symbol, order_id as u64)?{
+            self.record_fill(order_id as u64, &fill, time)?;
+        }
+        Ok(dsl::fills.filter(dsl::order_id.eq(order.id)).load::<Fill>(&mut connection)?)
+    }
+
+    /// Walk the transaction's orders oldest first to work out what it holds and what it has made,
+    /// and store it.
+    ///
+    /// Buys add to the position at their cost including commission so the average cost is the
+    /// break even price, sells realize against that average and each sell keeps what it made.
+    /// Commissions paid in the base asset shrink what a buy adds, ones paid in anything else like
+    /// BNB are valued at the current price.
+    pub fn position(&self,transaction_id: i32) -> Result<Position,TradingBotError>{
+        let orders = self.transaction_orders(transaction_id)?;
+        let Some(symbol) = orders.first().map(|order| order.symbol.clone()) else {
+            return Ok(Position::default());
+        };
+        let exchange = self.get()?;
+        let symbol_info = exchange.get_symbol_info(&symbol)?;
+        let (base,quote) = (symbol_info.base_asset,symbol_info.quote_asset);
+        let mut rates: HashMap<String,f64> = HashMap::new();
+        let mut rate = |asset: &str| -> f64 {
+            if let Some(rate) = rates.get(asset){
+                return *rate;
             }
-            {
-                use crate::schema::binance_accounts::dsl;
+            let rate = match exchange.get_price(&format!("{asset}{quote}")) {
+                Ok(price) => price,
+                Err(_) => match exchange.get_price(&format!("{quote}{asset}")) {
+                    Ok(price) if price > 0.0 => 1.0 / price,
+                    _ => {
+                        warn!("No {asset} {quote} market to value commission in");
+                        0.0
+                    }
+                },
+            };
+            rates.insert(asset.into(), rate);
+            rate
+        };
+        let mut position = Position{quote_asset: quote.clone(),..Default::default()};
+        let mut connection = establish_connection();
+        for order in orders.iter().filter(|order| order.executed_qty > 0.0){
+            //commission in the quote asset and in the base asset by quantity
+            let (mut fees,mut base_fees) = (0.0,0.0);
+            for fill in self.order_fills(order)?{
+                if fill.commission_asset == quote {
+                    fees += fill.commission;
+                } else if fill.commission_asset == base {
+                    base_fees += fill.commission;
+                    position.commission += fill.commission * fill.price;
+                } else if fill.commission != 0.0 {
+                    fees += fill.commission * rate(&fill.commission_asset);
+                }
+            }
+            position.commission += fees;
+            if order.side == "BUY"{
+                let cost = position.qty * position.avg_cost + order.cummulative_quote_qty + fees;
+                position.qty += order.executed_qty - base_fees;
+                position.avg_cost = if position.qty > 0.0 {cost / position.qty} else {0.0};
+            }else{
+                //commission taken in the base asset comes out of the position on top of what was sold
+                let sold = order.executed_qty + base_fees;
+                let realized = order.cummulative_quote_qty - fees - sold * position.avg_cost;
+                position.qty -= sold;
+                position.realized += realized;
+                use crate::schema::orders::dsl;
                 use diesel::ExpressionMethods;
-                let mut connection = establish_connection();
-                diesel::update(dsl::binance_accounts.filter(dsl::selected.eq(true))).set(dsl::active_transaction.eq(Some(transaction.id))).execute(&mut connection)?;
-                debug!("Transaction Linked")
+                diesel::update(dsl::orders.filter(dsl::id.eq(order.id)))
+                    .set(dsl::realized_pnl.eq(Some(realized)))
+                    .execute(&mut connection)?;
             }
-
-        }else if let Some(transaction) = &opt_transaction{
+        }
+        {
             use crate::schema::transactions::dsl;
             use diesel::ExpressionMethods;
-            let mut connection = establish_connection();
+            diesel::update(dsl::transactions.filter(dsl::id.eq(transaction_id))).set((
+                dsl::realizedPnl.eq(Some(position.realized)),
+                dsl::commission.eq(Some(position.commission)),
+                dsl::positionQty.eq(Some(position.qty.max(0.0))),
+            )).execute(&mut connection)?;
+        }
+        debug!("Transaction {transaction_id} holds {} at {} realized {} {quote} after {} commission",position.qty,position.avg_cost,position.realized,position.commission);
+        Ok(position)
+    }
+}
 
-            diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set((dsl::buyReady.eq(false),dsl::buyOrderIds.eq(format!("{},{}",transaction.buyOrderIds,order.order_id)))).execute(&mut connection)?;
+/// What reconciling does with an open order the bot has no record of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrphanAction {
+    //record it on the account and the active transaction if it trades the same symbol
+    Adopt,
+    //only report it
+    Flag,
+    Cancel,
+}
+impl OrphanAction {
+    pub fn parse(action: &str) -> Result<Self, TradingBotError> {
+        Ok(match action.to_lowercase().as_str() {
+            "adopt" => OrphanAction::Adopt,
+            "flag" => OrphanAction::Flag,
+            "cancel" => OrphanAction::Cancel,
+            action => return Err(TradingBotError::ConfigError(format!("Unknown orphan order action {action}"))),
+        })
+    }
+}
 
+//Something the database and the exchange disagreed on and what reconciling did about it
+#[derive(Debug, Clone)]
+pub struct Discrepancy {
+    pub action: &'static str,
+    pub detail: String,
+}
 
+//Reconciliation
+impl BinanceWrapped{
+    /// Compare the exchange's view of the account with the orders table and fix what can be fixed.
+    ///
+    /// Unsettled orders are looked up, open orders with no row are handled as set by
+    /// `reconcile/orphan_orders`, trades no order accounts for are flagged and a failed active
+    /// transaction is moved to wherever its orders say it is. The bot's own orders are always
+    /// adopted.
+    pub fn reconcile(&self) -> Result<Vec<Discrepancy>,TradingBotError>{
+        use crate::schema::orders::dsl;
+        use diesel::ExpressionMethods;
+        let exchange = self.get()?;
+        let config = self.config.load();
+        let orphan_action = OrphanAction::parse(&match config.get::<String>("reconcile", "orphan_orders")? {
+            Some(action) => action,
+            None => "flag".into(),
+        })?;
+        let lookback_h = match config.get::<i64>("reconcile", "trade_lookback_h")? {
+            Some(hours) => hours,
+            None => 24,
+        };
+        let mut connection = establish_connection();
+        let mut found = Vec::new();
+        let mut transaction = self.get_transaction()?;
+        let mut symbols = BTreeSet::from([self.default_symbol()?]);
+        symbols.extend(transaction.iter().map(|transaction| transaction.symbol.clone()));
+
+        //orders that never settled, a send still in flight is PENDING too so only stale ones count
+        let stale = Utc::now() - chrono::Duration::minutes(1);
+        let unsettled = dsl::orders
+            .filter(dsl::account_id.eq(self.id))
+            .load::<DBOrder>(&mut connection)?
+            .into_iter()
+            .filter(|order| !is_final_status(&order.status));
+        for order in unsettled{
+            symbols.insert(order.symbol.clone());
+            if order.order_id.is_some(){
+                if let Some(row) = self.refresh_order(exchange.as_ref(), &order)?{
+                    if row.status != order.status{
+                        found.push(Discrepancy{
+                            action: "settled",
+                            detail: format!("Order {} was {} is {}",order.client_order_id,order.status,row.status),
+                        });
+                    }
+                }
+                continue;
+            }
+            if order.created_at > stale{
+                continue;
+            }
+            match exchange.order_by_client_id(&order.symbol, &order.client_order_id)?{
+                Some(placed) => {
+                    self.track_order(&placed)?;
+                    found.push(Discrepancy{
+                        action: "settled",
+                        detail: format!("Order {} was placed as {} and is {}",order.client_order_id,placed.order_id,placed.status),
+                    });
+                }
+                None => {
+                    self.settle_order(&order.client_order_id, "REJECTED", None)?;
+                    found.push(Discrepancy{
+                        action: "settled",
+                        detail: format!("Order {} never reached the exchange",order.client_order_id),
+                    });
+                }
+            }
+        }
 
+        //open orders with no row
+        let own_prefix = format!("tb{}-",self.id);
+        for symbol in &symbols{
+            for order in exchange.open_orders(symbol)?{
+                if self.track_order(&order)?.is_some(){
+                    continue;
+                }
+                let action = if order.client_order_id.starts_with(&own_prefix) {OrphanAction::Adopt} else {orphan_action};
+                let describe = format!("{} {} {} {}@{} on {symbol}",order.order_id,order.side,order.type_name,order.orig_qty,order.price);
+                match action {
+                    OrphanAction::Adopt => {
+                        let adopted_by = transaction.clone().filter(|transaction| &transaction.symbol == symbol);
+                        self.adopt_order(adopted_by.as_ref().map(|transaction| transaction.id), &order)?;
+                        let detail = match adopted_by {
+                            Some(adopted_by) => {
+                                //an adopted order is working so the transaction is waiting on it
+                                let next = match (TransactionState::of(&adopted_by)?,order.side.as_str()) {
+                                    (TransactionState::Idle | TransactionState::Long,"BUY") => Some(TransactionState::BuyPending),
+                                    (TransactionState::Long,"SELL") => Some(TransactionState::SellPending),
+                                    _ => None,
+                                };
+                                if let Some(next) = next{
+                                    transaction = Some(transaction_state::transition(&adopted_by, next, &format!("adopted order {}",order.order_id))?);
+                                }
+                                format!("Order {describe} filed under transaction {}",adopted_by.id)
+                            }
+                            None => format!("Order {describe} has no transaction to go with"),
+                        };
+                        found.push(Discrepancy{action: "adopted", detail});
+                    }
+                    OrphanAction::Cancel => {
+                        exchange.cancel_order(symbol, order.order_id)?;
+                        found.push(Discrepancy{action: "cancelled", detail: format!("Order {describe}")});
+                    }
+                    OrphanAction::Flag => {
+                        found.push(Discrepancy{action: "flagged", detail: format!("Order {describe} was not placed by the bot")});
+                    }
+                }
+            }
         }
-        
-        
 
+        //trades the bot has no order for, fills of known orders missed while down are filed
+        let since = (Utc::now() - chrono::Duration::hours(lookback_h)).timestamp_millis() as u64;
+        for symbol in &symbols{
+            for trade in exchange.recent_trades(symbol, 100)?{
+                if trade.time < since{
+                    continue;
+                }
+                let known = dsl::orders
+                    .filter(dsl::account_id.eq(self.id))
+                    .filter(dsl::order_id.eq(Some(trade.order_id as i64)))
+                    .count()
+                    .get_result::<i64>(&mut connection)? > 0;
+                if known{
+                    self.record_fill(trade.order_id, &trade.fill, trade.time)?;
+                    continue;
+                }
+                found.push(Discrepancy{
+                    action: "flagged",
+                    detail: format!(
+                        "Trade {} {} {}@{} on {symbol} from order {} has no order on record",
+                        trade.fill.trade_id.unwrap_or_default(),trade.side.as_str(),trade.fill.qty,trade.fill.price,trade.order_id
+                    ),
+                });
+            }
+        }
+
+        //a failed transaction goes wherever its orders now say it is
+        if let Some(transaction) = transaction.filter(|transaction| transaction.state == TransactionState::Failed.as_str()){
+            let orders = self.transaction_orders(transaction.id)?;
+            let working = |side: &str| orders.iter().any(|order| order.side == side && !is_final_status(&order.status));
+            let next = if working("SELL") {
+                TransactionState::SellPending
+            } else if working("BUY") {
+                TransactionState::BuyPending
+            } else if transaction.buyAvgPrice.is_some() {
+                TransactionState::Long
+            } else if orders.iter().any(|order| order.side == "BUY" && order.executed_qty > 0.0) {
+                //the order handler finishes the buy if its last order filled
+                TransactionState::Idle
+            } else {
+                TransactionState::Closed
+            };
+            transaction_state::transition(&transaction, next, "reconciled")?;
+            found.push(Discrepancy{
+                action: "recovered",
+                detail: format!("Transaction {} failed is now {}",transaction.id,next.as_str()),
+            });
+        }
+
+        for discrepancy in &found{
+            warn!("Reconciling {} {} {}",self.name,discrepancy.action,discrepancy.detail);
+        }
+        Ok(found)
+    }
+
+    //File an order placed outside the bot so it is tracked like one of its own
+    fn adopt_order(&self,transaction_id: Option<i32>,order: &Order) -> Result<(),TradingBotError>{
+        let parse = |value: &str| value.parse::<f64>().ok().filter(|value| *value > 0.0);
+        self.record_order(NewOrder{
+            account_id: self.id,
+            transaction_id,
+            client_order_id: &order.client_order_id,
+            symbol: &order.symbol,
+            side: &order.side,
+            created_at: Utc.timestamp_millis_opt(order.time as i64).unwrap(),
+            type_name: &order.type_name,
+            price: Some(order.price).filter(|price| *price > 0.0),
+            orig_qty: parse(&order.orig_qty),
+            quote_qty: parse(&order.orig_quote_order_qty),
+            stop_price: Some(order.stop_price).filter(|price| *price > 0.0),
+            time_in_force: Some(order.time_in_force.as_str()).filter(|time_in_force| !time_in_force.is_empty() && order.type_name == "LIMIT"),
+        })?;
+        self.track_order(order)?;
+        Ok(())
+    }
+}
+
+//Risk
+impl BinanceWrapped{
+    //Set by /halt, nothing new goes out until it is cleared
+    pub fn is_halted(&self) -> Result<bool,TradingBotError>{
+        Ok(self.config.load().get::<bool>("risk", "halted")?.unwrap_or(false))
+    }
+
+    //Orders sent for a trader's transactions on any account since `since`
+    fn trader_orders(user_id: i64,since: DateTime<Utc>) -> Result<Vec<DBOrder>,TradingBotError>{
+        use crate::schema::{clock_stubs, orders, transactions};
+        use diesel::{BoolExpressionMethods, ExpressionMethods, NullableExpressionMethods};
+        let mut connection = establish_connection();
+        //a resting order counts on the day it filled
+        Ok(orders::table
+            .inner_join(transactions::table.inner_join(clock_stubs::table))
+            .filter(clock_stubs::user_id.eq(user_id))
+            .filter(orders::created_at.ge(since).nullable().or(orders::updated_at.ge(since)))
+            .select(orders::all_columns)
+            .load::<DBOrder>(&mut connection)?)
+    }
+
+    /// What the assets of the default symbol and the active transaction's symbol are worth in the
+    /// quote asset, the circuit breaker watches this for drawdowns
+    pub fn equity(&self) -> Result<f64,TradingBotError>{
+        let exchange = self.get()?;
+        let total = |balance: Balance| balance.free.parse::<f64>().unwrap_or(0.0) + balance.locked.parse::<f64>().unwrap_or(0.0);
+        let default_symbol = self.default_symbol()?;
+        let mut symbols = BTreeSet::from([default_symbol.clone()]);
+        symbols.extend(self.get_transaction()?.map(|transaction| transaction.symbol));
+        let (_,quote) = self.get_balance(&default_symbol)?;
+        let mut equity = total(quote);
+        for symbol in &symbols{
+            let base = exchange.get_balance(&exchange.get_symbol_info(symbol)?.base_asset)?;
+            equity += total(base) * self.price(symbol)?;
+        }
+        Ok(equity)
+    }
+
+    /// Check an order a trader is about to send against the limits in the `risk` config section.
+    ///
+    /// Order size and rate apply to every order, position size and daily loss only stop buys so a
+    /// trader can always get out. Unset limits don't apply.
+    fn check_risk(&self,user_id: i64,transaction: Option<&DBTransaction>,side: OrderSide,market: bool,quantity: f64,price: f64) -> Result<(),TradingBotError>{
+        if self.is_halted()?{
+            return Err(TradingBotError::Halted);
+        }
+        let config = self.config.load();
+        //a tripped breaker still lets positions be exited at a price
+        if let Some(reason) = circuit_breaker::tripped(&config)?{
+            if market || side == OrderSide::Buy{
+                return Err(TradingBotError::RiskLimit(format!("Circuit breaker tripped {reason}, only limit sells until it is re-armed")));
+            }
+        }
+        let notional = quantity * price;
+        if let Some(max) = config.get::<f64>("risk", "max_order_notional")?{
+            if notional > max{
+                return Err(TradingBotError::RiskLimit(format!("{notional:.2} is over the {max} per order limit")));
+            }
+        }
+        if let Some(max) = config.get::<i64>("risk", "max_orders_per_minute")?{
+            let since = Utc::now() - chrono::Duration::minutes(1);
+            let sent = Self::trader_orders(user_id, since)?.iter().filter(|order| order.created_at >= since).count();
+            if sent as i64 >= max{
+                return Err(TradingBotError::RiskLimit(format!("{sent} orders in the last minute, the limit is {max}")));
+            }
+        }
+        if side == OrderSide::Sell{
+            return Ok(());
+        }
+        if let Some(max) = config.get::<f64>("risk", "max_position_notional")?{
+            let held = transaction.and_then(|transaction| transaction.positionQty).unwrap_or(0.0);
+            let position = (held + quantity) * price;
+            if position > max{
+                return Err(TradingBotError::RiskLimit(format!("Position would be worth {position:.2} over the {max} limit")));
+            }
+        }
+        if let Some(max) = config.get::<f64>("risk", "max_daily_loss")?{
+            let today = Utc.from_utc_datetime(&Utc::now().date_naive().and_hms_opt(0, 0, 0).unwrap());
+            let realized: f64 = Self::trader_orders(user_id, today)?
+                .iter()
+                .filter(|order| order.side == "SELL")
+                .filter_map(|order| order.realized_pnl)
+                .sum();
+            if -realized >= max{
+                return Err(TradingBotError::RiskLimit(format!("Lost {:.2} today, the limit is {max}",-realized)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop the account trading for /halt.
+    ///
+    /// Cancels every open order on the account, whichever symbol it is on and whoever placed it,
+    /// and with `flatten` sells whatever the active transaction still holds. Returns what was done.
+    ///
+    /// The flatten is always a market sell, even with `trading/market_orders` off: the kill switch
+    /// is for getting out at any price, and the reply says so when it overrides the setting.
+    #[instrument(skip(self))]
+    pub fn halt(&self,flatten: bool) -> Result<Vec<String>,TradingBotError>{
+        //held conditional orders would go out as soon as trading resumed
+        let mut done: Vec<String> = self.cancel_conditionals(None, None)?
+            .iter()
+            .map(|order| format!("Cancelled conditional {}",conditional::describe(order)))
+            .collect();
+        //orders left over from earlier transactions or placed by hand sit on other symbols
+        let mut symbols: BTreeSet<String> = self.get()?.all_open_orders()?.into_iter().map(|order| order.symbol).collect();
+        symbols.extend(self.get_transaction()?.map(|transaction| transaction.symbol));
+        for symbol in &symbols{
+            done.extend(self.cancel_symbol_orders(symbol)?);
+        }
+        //cancelling leaves the transaction long if anything had filled
+        let Some(transaction) = self.get_transaction()? else {
+            return Ok(done);
+        };
+        if !flatten || !TransactionState::of(&transaction)?.can_sell(){
+            return Ok(done);
+        }
+        match self.size_sell(&transaction, None, Sizing::ALL, OrderOptions::default()).and_then(|sized| self.sell_position(&transaction, sized)){
+            Ok(order) => {
+                done.push(format!("Market selling {} {}",order.orig_qty,transaction.symbol));
+                if !circuit_breaker::market_orders_allowed(&self.config.load())?{
+                    done.push("Market orders are off, the kill switch sells at market anyway".into());
+                }
+            }
+            Err(err) => done.push(format!("Failed to flatten {} {err}",transaction.symbol)),
+        }
+        Ok(done)
+    }
+}
 
+//Buying Selling Order etc
+impl BinanceWrapped{
+    pub fn get_balance(&self,symbol: &str) -> Result<(Balance,Balance),TradingBotError>{
+        let exchange = self.get()?;
+        let symbol_info = exchange.get_symbol_info(symbol)?;
+        let quote_balance = exchange.get_balance(&symbol_info.quote_asset)?;
+        let base_balance = exchange.get_balance(&symbol_info.base_asset)?;
+        return Ok((base_balance,quote_balance))
+    }
+    //Put the trader's time in force and post-only on a sized limit order if the symbol takes it
+    fn apply_options(symbol_info: &Symbol,request: &mut OrderRequest,options: OrderOptions) -> Result<(),TradingBotError>{
+        if options == OrderOptions::default(){
+            return Ok(());
+        }
+        if request.price.is_none(){
+            return Err(TradingBotError::OrderRejected("Time in force and post-only need a price".into()));
+        }
+        if options.post_only && options.time_in_force != TimeInForce::Gtc{
+            return Err(TradingBotError::OrderRejected("Post-only orders rest on the book so they are always GTC".into()));
+        }
+        request.time_in_force = options.time_in_force;
+        request.post_only = options.post_only;
+        if !symbol_info.order_types.iter().any(|order_type| order_type == request.type_name()){
+            return Err(TradingBotError::OrderRejected(format!("{} doesn't take {} orders",symbol_info.symbol,request.type_name())));
+        }
+        Ok(())
+    }
 
-        return Ok(order);
+    /// Work out a buy on `symbol` from what the trader asked for, a limit order at `price` or a
+    /// market order without one
+    pub fn size_buy(&self,symbol: &str,price: Option<f32>,size: Sizing,options: OrderOptions) -> Result<SizedOrder,TradingBotError>{
+        let exchange = self.get()?;
+        let symbol_info = exchange.get_symbol_info(symbol)?;
+        debug!("Getting Symbol Info for {}",symbol);
+        let Ok(balance) = exchange.get_balance(&symbol_info.quote_asset)?.free.parse::<f64>() else{
+            return Err(TradingBotError::ParsingDataError("Could no parse balance".into()));
+        };
+        let filters = SymbolFilters::from_symbol(&symbol_info);
+        let (mut request,base_qty,quote_qty) = match price {
+            Some(price) => {
+                let price = filters.round_price(price as f64);
+                let quantity = filters.floor_qty(match size {
+                    Sizing::Base(qty) => qty,
+                    Sizing::Quote(spend) => spend / price,
+                    Sizing::Percent(percent) => balance * percent / 100.0 / price,
+                }, false);
+                filters.check(quantity, price, false)?;
+                (OrderRequest::limit(symbol, OrderSide::Buy, quantity, price),quantity,quantity * price)
+            }
+            None => {
+                let last_price = self.price(symbol)?;
+                let spend = |spend: f64| -> Result<(OrderRequest,f64,f64),TradingBotError> {
+                    let spend = filters.floor_quote(spend);
+                    filters.check(spend/last_price, last_price, true)?;
+                    Ok((OrderRequest::market(symbol, OrderSide::Buy, OrderQuantity::Quote(spend)),spend / last_price,spend))
+                };
+                match size {
+                    Sizing::Base(qty) => {
+                        let quantity = filters.floor_qty(qty, true);
+                        filters.check(quantity, last_price, true)?;
+                        (OrderRequest::market(symbol, OrderSide::Buy, OrderQuantity::Base(quantity)),quantity,quantity * last_price)
+                    }
+                    Sizing::Quote(quote) => spend(quote)?,
+                    Sizing::Percent(percent) => spend(balance * percent / 100.0)?,
+                }
+            }
+        };
+        if quote_qty > balance{
+            return Err(TradingBotError::OrderRejected(format!("{quote_qty:.2} {} is more than the free {balance:.2}",symbol_info.quote_asset)));
+        }
+        Self::apply_options(&symbol_info, &mut request, options)?;
+        Ok(SizedOrder{request,base_qty,quote_qty,base_asset: symbol_info.base_asset,quote_asset: symbol_info.quote_asset})
     }
+
+    /// Work out a sell out of `transaction`'s position from what the trader asked for, quote
+    /// amounts are sold at the limit price or the last price for market orders and percentages
+    /// are of what the transaction still holds
+    pub fn size_sell(&self,transaction: &DBTransaction,price: Option<f32>,size: Sizing,options: OrderOptions) -> Result<SizedOrder,TradingBotError>{
+        let symbol = transaction.symbol.as_str();
+        let exchange = self.get()?;
+        let symbol_info = exchange.get_symbol_info(symbol)?;
+        debug!("Getting Symbol Info for {}",symbol);
+        let Ok(free) = exchange.get_balance(&symbol_info.base_asset)?.free.parse::<f64>() else{
+            return Err(TradingBotError::ParsingDataError("Could no parse balance".into()));
+        };
+        //transactions from before positions were tracked can only go by the balance
+        let balance = match transaction.positionQty {
+            Some(held) => held.min(free),
+            None => free,
+        };
+        let filters = SymbolFilters::from_symbol(&symbol_info);
+        let market = price.is_none();
+        let price = match price {
+            Some(price) => filters.round_price(price as f64),
+            None => self.price(symbol)?,
+        };
+        let quantity = filters.floor_qty(match size {
+            Sizing::Base(qty) => qty,
+            Sizing::Quote(value) => value / price,
+            Sizing::Percent(percent) => balance * percent / 100.0,
+        }, market);
+        filters.check(quantity, price, market)?;
+        if quantity > balance{
+            return Err(TradingBotError::OrderRejected(format!("{quantity} {} is more than the {balance} held",symbol_info.base_asset)));
+        }
+        let mut request = if market {
+            OrderRequest::market(symbol, OrderSide::Sell, OrderQuantity::Base(quantity))
+        }else{
+            OrderRequest::limit(symbol, OrderSide::Sell, quantity, price)
+        };
+        Self::apply_options(&symbol_info, &mut request, options)?;
+        Ok(SizedOrder{request,base_qty: quantity,quote_qty: quantity * price,base_asset: symbol_info.base_asset,quote_asset: symbol_info.quote_asset})
+    }
+
     #[instrument(skip(self))]
-    pub fn sell(&self,price:Option<f32>,percentage: Option<f64>) -> Result<Transaction,TradingBotError>{
+    pub fn buy(&self,symbol: Option<String>,price:Option<f32>,size: Option<Sizing>,options: OrderOptions) -> Result<Transaction,TradingBotError>{
+        let Some(stub) = self.is_clocked_in()? else {
+            return Err(TradingBotError::NotClockedIn(String::new()))
+        };
+        let opt_transaction = self.get_transaction()?;
+        if let Some(transaction) = &opt_transaction{
+            if !TransactionState::of(transaction)?.can_buy(){
+                return Err(TradingBotError::ActiveTransaction("Wait for the previous order to settle before buying".into()))
+            }
+        }
+        //adding to an open transaction has to stay on its symbol
+        let symbol = match (&opt_transaction,symbol){
+            (Some(transaction),Some(symbol)) if transaction.symbol != symbol => {
+                return Err(TradingBotError::ActiveTransaction(format!("Active transaction is trading {}",transaction.symbol)))
+            }
+            (Some(transaction),_) => transaction.symbol.clone(),
+            (None,Some(symbol)) => symbol,
+            (None,None) => self.default_symbol()?,
+        };
+        let sized = self.size_buy(&symbol, price, size.unwrap_or(Sizing::ALL), options)?;
+        self.check_risk(stub.user_id, opt_transaction.as_ref(), OrderSide::Buy, price.is_none(), sized.base_qty, sized.quote_qty / sized.base_qty)?;
+        debug!("Sending {sized}");
+        //file transaction first so the order is on it from the start
+        let (transaction,opened) = match opt_transaction{
+            Some(transaction) => (transaction,false),
+            None => {
+                let transaction = transaction_state::open(self.id, NewTransaction{
+                    clock_stub_id: stub.id,
+                    buyOrderTime:Utc::now(),
+                    symbol: symbol.clone(),
+                }, TransactionState::Idle, "buy requested")?;
+                trace!("Transaction Created");
+                (transaction,true)
+            }
+        };
+        let result = self.place_order(Some(transaction.id), sized.request);
+        match &result{
+            //nothing was ever bought on a transaction opened for this order
+            Err(err) if opened && !matches!(err, TradingBotError::OrderUnknown(_)) => {
+                transaction_state::transition(&transaction, TransactionState::Closed, "buy rejected")?;
+            }
+            _ => self.settle_transaction(&transaction, &result, TransactionState::BuyPending, "buy order sent")?,
+        }
+        return result;
+    }
+    #[instrument(skip(self))]
+    pub fn sell(&self,price:Option<f32>,size: Option<Sizing>,options: OrderOptions) -> Result<Transaction,TradingBotError>{
         let Some(stub) = self.is_clocked_in()? else {
             return Err(TradingBotError::NotClockedIn(String::new()))
         };
@@ -402,55 +1297,523 @@ impl BinanceWrapped{
             return Err(TradingBotError::ActiveTransaction("Must buy before selling".into()));
         };
        
-        if transaction.buyAvgPrice.is_none() || !transaction.sellReady{
+        if !TransactionState::of(&transaction)?.can_sell(){
             return Err(TradingBotError::ActiveTransaction("Must wait previous order to settle before selling".into()));
         }
-        let Some(account) = self.account.as_ref()  else{
-            error!("Account is missing");
-            return Err(TradingBotError::BinanceAccountMissing);
+        let sized = self.size_sell(&transaction, price, size.unwrap_or(Sizing::ALL), options)?;
+        self.check_risk(stub.user_id, Some(&transaction), OrderSide::Sell, price.is_none(), sized.base_qty, sized.quote_qty / sized.base_qty)?;
+        self.sell_position(&transaction, sized)
+    }
+
+    //Places the sell for a transaction that is ready to sell without checking who holds the account
+    fn sell_position(&self,transaction: &DBTransaction,sized: SizedOrder) -> Result<Transaction,TradingBotError>{
+        debug!("Sending {sized}");
+        let request = sized.request;
+        let result = self.place_order(Some(transaction.id), request);
+        self.settle_transaction(transaction, &result, TransactionState::SellPending, "sell order sent")?;
+        return result;
+    }
+
+    //Bracket what is left of the position with a take profit and a stop loss relative to its average cost
+    #[instrument(skip(self))]
+    pub fn exit(&self,take_profit_pct: f64,stop_loss_pct: f64) -> Result<OcoOrder,TradingBotError>{
+        let Some(stub) = self.is_clocked_in()? else {
+            return Err(TradingBotError::NotClockedIn(String::new()))
         };
-        let symbol = match self.config.load().get::<String>("trading", "symbol")? {
-            Some(symbol) => symbol,
-            None => "BTCUSDT".into(),
+        let Some(transaction) = self.get_transaction()? else {
+            return Err(TradingBotError::ActiveTransaction("Must buy before selling".into()));
         };
-        let Some(general) = self.general.as_ref()  else{
-            error!("General is missing but not account");
-            return Err(TradingBotError::BinanceAccountMissing);
+        let (Some(buy_price),true) = (transaction.buyAvgPrice,TransactionState::of(&transaction)?.can_sell()) else{
+            return Err(TradingBotError::ActiveTransaction("Must wait previous order to settle before selling".into()));
         };
-        let symbol_info = general.get_symbol_info(&symbol)?;
-        debug!("Getting Symbol Info for {}",symbol);
-        let Ok(balance) = account.get_balance(symbol_info.base_asset)?.free.parse::<f64>() else{
+        if take_profit_pct <= 0.0 || stop_loss_pct <= 0.0 || stop_loss_pct >= 100.0{
+            return Err(TradingBotError::OrderRejected("Take profit and stop loss must be positive percentages".into()));
+        }
+        let stop_limit_offset = match self.config.load().get::<f64>("trading", "stop_limit_offset")? {
+            Some(offset) => offset,
+            None => 0.002,
+        };
+        let exchange = self.get()?;
+        let symbol = transaction.symbol.clone();
+        let symbol_info = exchange.get_symbol_info(&symbol)?;
+        let filters = SymbolFilters::from_symbol(&symbol_info);
+        let Ok(balance) = exchange.get_balance(&symbol_info.base_asset)?.free.parse::<f64>() else{
             return Err(TradingBotError::ParsingDataError("Could no parse balance".into()));
         };
-        let adjusted_balance = format!("{:.5}",(balance - 0.00001) * percentage.unwrap_or(1.0)).parse::<f64>().unwrap();
-        let order: Transaction;
+        let held = match transaction.positionQty {
+            Some(held) => held.min(balance),
+            None => balance,
+        };
+        let quantity = filters.floor_qty(held, false);
+        let take_profit = filters.round_price(buy_price * (1.0 + take_profit_pct / 100.0));
+        let stop_price = filters.round_price(buy_price * (1.0 - stop_loss_pct / 100.0));
+        let stop_limit_price = filters.round_price(stop_price * (1.0 - stop_limit_offset));
+        filters.check(quantity, take_profit, false)?;
+        filters.check(quantity, stop_limit_price, false)?;
+        self.check_risk(stub.user_id, Some(&transaction), OrderSide::Sell, false, quantity, take_profit)?;
+
+        debug!("Sending OCO exit Qty:{} take profit @{} stop @{} limit @{}",quantity,take_profit,stop_price,stop_limit_price);
+        //both legs are on the transaction so whichever fills closes it
+        let result = self.place_oco(transaction.id, OcoRequest{
+            symbol,
+            quantity,
+            take_profit,
+            stop_price,
+            stop_limit_price,
+            client_order_id: None,
+        });
+        self.settle_transaction(&transaction, &result, TransactionState::SellPending, "exit sent")?;
+        return result;
+    }
 
+}
 
-        if let Some(price) = price{
-            let price = format!("{:.1$}",price,2).parse::<f64>().unwrap();
-            debug!("Sending sell limit order for %{} of account with Qty:{} @{}",percentage.unwrap_or(1.0)*100.,adjusted_balance,price);
-            order = account.limit_sell(symbol, adjusted_balance, price)?;
 
-        }else{
-            debug!("Sending sell market order for %{} of account with balance {}",percentage.unwrap_or(1.0)*100.,adjusted_balance);
-            order = account.market_sell(&symbol, adjusted_balance)?;
+//Amending
+impl BinanceWrapped{
+    /// Move the active transaction's working limit order to `price`.
+    ///
+    /// The old order is cancelled on the exchange first and a replacement for what it had left
+    /// is sent after, there is no atomic replace. If the cancel fails the old order keeps
+    /// working and the error is returned. Once it lands the old order is gone whatever happens
+    /// to the replacement, so the cancelled order is returned alongside the replacement's
+    /// result and a replacement that doesn't go out settles the transaction as if the order had
+    /// just been cancelled. What the old order filled stays on it.
+    #[instrument(skip(self))]
+    pub fn amend(&self,price: f32) -> Result<(DBOrder,Result<Transaction,TradingBotError>),TradingBotError>{
+        let Some(stub) = self.is_clocked_in()? else {
+            return Err(TradingBotError::NotClockedIn(String::new()))
+        };
+        let Some(transaction) = self.get_transaction()? else {
+            return Err(TradingBotError::ActiveTransaction("No order to amend".into()));
+        };
+        let orders = self.refresh_orders(transaction.id)?;
+        let Some(working) = orders.into_iter().rev().find(|order| !is_final_status(&order.status)) else {
+            return Err(TradingBotError::ActiveTransaction("No working order to amend".into()));
+        };
+        //exit legs are LIMIT_MAKER and STOP_LOSS_LIMIT and move together, post-only orders can move on their own
+        let (Some(order_id),Some("LIMIT" | "LIMIT_MAKER"),Some(orig_qty),false) = (working.order_id,working.type_name.as_deref(),working.orig_qty,is_oco_leg(&working.client_order_id)) else {
+            return Err(TradingBotError::OrderRejected(format!("Order {} can't be amended, only placed limit orders can",working.client_order_id)));
+        };
+        let exchange = self.get()?;
+        let filters = SymbolFilters::from_symbol(&exchange.get_symbol_info(&working.symbol)?);
+        let side = if working.side == OrderSide::Buy.as_str() {OrderSide::Buy} else {OrderSide::Sell};
+        let price = filters.round_price(price as f64);
+        let quantity = filters.floor_qty(orig_qty - working.executed_qty, false);
+        filters.check(quantity, price, false)?;
+        self.check_risk(stub.user_id, Some(&transaction), side, false, quantity, price)?;
+
+        let mut request = OrderRequest::limit(&working.symbol, side, quantity, price);
+        //the replacement keeps how the order was placed
+        request.post_only = working.type_name.as_deref() == Some("LIMIT_MAKER");
+        if let Some(time_in_force) = working.time_in_force.as_deref(){
+            request.time_in_force = TimeInForce::parse(time_in_force)?;
+        }
+        let client_order_id = self.file_order(Some(transaction.id), &mut request)?;
+        if let Err(err) = exchange.cancel_order(&working.symbol, order_id as u64){
+            //the old order is still working or already done either way nothing replaces it
+            self.settle_order(&client_order_id, "REJECTED", None)?;
+            return Err(err);
+        }
+        let cancelled = self.refresh_order(exchange.as_ref(), &working)?.unwrap_or(working);
+        //the cancel can race a fill so only what was left when it landed is replaced
+        let left = filters.floor_qty(orig_qty - cancelled.executed_qty, false);
+        if left < quantity{
+            if filters.check(left, price, false).is_err(){
+                self.settle_order(&client_order_id, "REJECTED", None)?;
+                self.settle_cancelled(&transaction)?;
+                let err = TradingBotError::OrderRejected(format!("Order {} filled before it could be amended",cancelled.client_order_id));
+                return Ok((cancelled,Err(err)));
+            }
+            use crate::schema::orders::dsl;
+            use diesel::ExpressionMethods;
+            let mut connection = establish_connection();
+            diesel::update(dsl::orders.filter(dsl::client_order_id.eq(&client_order_id)))
+                .set(dsl::orig_qty.eq(Some(left)))
+                .execute(&mut connection)?;
+            request.quantity = OrderQuantity::Base(left);
+        }
+        debug!("Amending {} to {:?}",cancelled.client_order_id,request);
+        let result = self.send_order(&client_order_id, &request);
+        match &result{
+            Err(TradingBotError::OrderUnknown(err)) => {
+                transaction_state::transition(&transaction, TransactionState::Failed, &format!("amended order state unknown {err}"))?;
+            }
+            //nothing replaced the cancelled order so the transaction can't stay pending on it
+            Err(_) => {
+                self.settle_cancelled(&transaction)?;
+            }
+            Ok(_) => {}
+        }
+        Ok((cancelled,result))
+    }
+}
+
+//Cancelling
+impl BinanceWrapped{
+    //Orders on the active transaction placed and still working, oldest first
+    pub fn working_orders(&self) -> Result<Vec<DBOrder>,TradingBotError>{
+        let Some(transaction) = self.get_transaction()? else {
+            return Ok(Vec::new());
+        };
+        Ok(self.refresh_orders(transaction.id)?
+            .into_iter()
+            .filter(|order| order.order_id.is_some() && !is_final_status(&order.status))
+            .collect())
+    }
+
+    /// Cancel some of the active transaction's working orders by exchange id and move the
+    /// transaction on if nothing is left working. Returns what was done.
+    #[instrument(skip(self))]
+    pub fn cancel_orders(&self,order_ids: &[u64]) -> Result<Vec<String>,TradingBotError>{
+        let Some(transaction) = self.get_transaction()? else {
+            return Err(TradingBotError::ActiveTransaction("No active Transaction".into()));
+        };
+        let exchange = self.get()?;
+        let mut done = Vec::new();
+        for order in self.working_orders()?.into_iter().filter(|order| order.order_id.map_or(false, |id| order_ids.contains(&(id as u64)))){
+            let order_id = order.order_id.unwrap() as u64;
+            match exchange.cancel_order(&order.symbol, order_id){
+                Ok(_) => done.push(format!("Cancelled {} {} {}@{}",order_id,order.side,order.orig_qty.unwrap_or_default(),order.price.map_or("market".into(),|price| price.to_string()))),
+                Err(err) => done.push(format!("Failed to cancel {order_id} {err}")),
+            }
+        }
+        if let Some(next) = self.settle_cancelled(&transaction)?{
+            done.push(format!("Transaction is now {}",next.as_str()));
+        }
+        Ok(done)
+    }
+
+    /// Cancel every order working on `symbol` whoever placed it. The active transaction is moved
+    /// on if it was trading the symbol. Returns what was done.
+    #[instrument(skip(self))]
+    pub fn cancel_symbol_orders(&self,symbol: &str) -> Result<Vec<String>,TradingBotError>{
+        let exchange = self.get()?;
+        let mut done = Vec::new();
+        for order in exchange.open_orders(symbol)?{
+            match exchange.cancel_order(symbol, order.order_id){
+                Ok(_) => done.push(format!("Cancelled {} {} {}@{} on {symbol}",order.order_id,order.side,order.orig_qty,order.price)),
+                Err(err) => done.push(format!("Failed to cancel {} on {symbol} {err}",order.order_id)),
+            }
         }
+        if let Some(transaction) = self.get_transaction()?.filter(|transaction| transaction.symbol == symbol){
+            if let Some(next) = self.settle_cancelled(&transaction)?{
+                done.push(format!("Transaction is now {}",next.as_str()));
+            }
+        }
+        Ok(done)
+    }
 
-        {
+    /// Put a pending transaction where its orders leave it once nothing is working, the same
+    /// place the order handler would.
+    ///
+    /// Returns the new state, None if it stays put or the order handler got there first.
+    fn settle_cancelled(&self,transaction: &DBTransaction) -> Result<Option<TransactionState>,TradingBotError>{
+        let state = TransactionState::of(transaction)?;
+        if !matches!(state, TransactionState::BuyPending | TransactionState::SellPending){
+            return Ok(None);
+        }
+        if self.refresh_orders(transaction.id)?.iter().any(|order| !is_final_status(&order.status)){
+            return Ok(None);
+        }
+        let position = self.position(transaction.id)?;
+        let filters = SymbolFilters::from_symbol(&self.get()?.get_symbol_info(&transaction.symbol)?);
+        let held = position.qty > 0.0 && !filters.is_dust(position.qty, position.avg_cost);
+        let next = match (state,held) {
+            (_,true) => TransactionState::Long,
+            (TransactionState::BuyPending,false) => TransactionState::Idle,
+            _ => TransactionState::Closed,
+        };
+        if next == TransactionState::Long{
             use crate::schema::transactions::dsl;
             use diesel::ExpressionMethods;
             let mut connection = establish_connection();
-            if transaction.sellOrderIds == ""{
-                diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set((dsl::sellReady.eq(false),dsl::sellOrderIds.eq(format!("{}",order.order_id)))).execute(&mut connection)?;
-            }else{
-                diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set((dsl::sellReady.eq(false),dsl::sellOrderIds.eq(format!("{},{}",transaction.sellOrderIds,order.order_id)))).execute(&mut connection)?;
-            }
-            debug!("Sell Order ID Set");
+            diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set(dsl::buyAvgPrice.eq(Some(position.avg_cost))).execute(&mut connection)?;
         }
+        match transaction_state::transition(transaction, next, "orders cancelled"){
+            Ok(_) => Ok(Some(next)),
+            //the order handler moved it first
+            Err(TradingBotError::InvalidTransition(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
 
+//Trailing stop
+impl BinanceWrapped{
+    fn trailing_stop_level(price: f64,offset: f64,percent: bool) -> f64{
+        if percent {price * (1.0 - offset)} else {price - offset}
+    }
 
+    /// Trail a stop below the market for the active transaction.
+    ///
+    /// `offset` is a fraction of the price when `percent` is set otherwise a distance in the quote
+    /// asset. Returns the starting stop level.
+    #[instrument(skip(self))]
+    pub fn set_trailing_stop(&self,offset: f64,percent: bool) -> Result<f64,TradingBotError>{
+        let Some(transaction) = self.get_transaction()? else {
+            return Err(TradingBotError::ActiveTransaction("Must buy before setting a stop".into()));
+        };
+        if transaction.buyAvgPrice.is_none(){
+            return Err(TradingBotError::ActiveTransaction("Must wait for the buy to settle before setting a stop".into()));
+        }
+        if offset <= 0.0 || (percent && offset >= 1.0){
+            return Err(TradingBotError::OrderRejected("Trailing offset must be positive and a percentage below 1".into()));
+        }
+        let price = self.price(&transaction.symbol)?;
+        let level = Self::trailing_stop_level(price, offset, percent);
+        if level <= 0.0{
+            return Err(TradingBotError::OrderRejected(format!("Trailing offset is larger than the price {price}")));
+        }
+        use crate::schema::transactions::dsl;
+        use diesel::ExpressionMethods;
+        let mut connection = establish_connection();
+        diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set((
+            dsl::trailingStopOffset.eq(Some(offset)),
+            dsl::trailingStopPercent.eq(percent),
+            dsl::trailingStopLevel.eq(Some(level)),
+        )).execute(&mut connection)?;
+        debug!("Trailing stop set @{level} from {price}");
+        Ok(level)
+    }
 
-        return Ok(order);
+    pub fn clear_trailing_stop(&self) -> Result<(),TradingBotError>{
+        let Some(transaction) = self.get_transaction()? else {
+            return Err(TradingBotError::ActiveTransaction("No active Transaction".into()));
+        };
+        use crate::schema::transactions::dsl;
+        use diesel::ExpressionMethods;
+        let mut connection = establish_connection();
+        diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set((
+            dsl::trailingStopOffset.eq::<Option<f64>>(None),
+            dsl::trailingStopLevel.eq::<Option<f64>>(None),
+        )).execute(&mut connection)?;
+        debug!("Trailing stop cleared");
+        Ok(())
     }
 
+    /// Ratchet the trailing stop up to the live price and sell once it is breached.
+    ///
+    /// Runs from the interval handler so it fires even if the trader has been clocked out, the
+    /// sell is still checked against the risk limits of the trader who opened the transaction.
+    /// It is a market sell unless market orders are off, then a limit at the breached level.
+    /// Returns the breached level and the sell order when the stop fires.
+    pub fn check_trailing_stop(&self) -> Result<Option<(f64,Transaction)>,TradingBotError>{
+        let Some(transaction) = self.get_transaction()? else {
+            return Ok(None);
+        };
+        let (Some(offset),Some(level)) = (transaction.trailingStopOffset,transaction.trailingStopLevel) else {
+            return Ok(None);
+        };
+        //a resting sell already holds the coins
+        if !TransactionState::of(&transaction)?.can_sell(){
+            return Ok(None);
+        }
+        let price = self.price(&transaction.symbol)?;
+        use crate::schema::transactions::dsl;
+        use diesel::ExpressionMethods;
+        let mut connection = establish_connection();
+        if price <= level{
+            debug!("Trailing stop breached @{price} level {level}");
+            //with market orders off or the breaker tripped the exit rests as a limit at the level
+            let market = circuit_breaker::market_orders_allowed(&self.config.load())?;
+            let limit = if market {None} else {Some(level as f32)};
+            let user_id = {
+                use crate::schema::clock_stubs::dsl;
+                dsl::clock_stubs.filter(dsl::id.eq(transaction.clock_stub_id)).select(dsl::user_id).first::<i64>(&mut connection)?
+            };
+            //the stop stays armed if the sell fails so the next check retries it
+            let sized = self.size_sell(&transaction, limit, Sizing::ALL, OrderOptions::default())?;
+            self.check_risk(user_id, Some(&transaction), OrderSide::Sell, market, sized.base_qty, sized.quote_qty / sized.base_qty)?;
+            let order = self.sell_position(&transaction, sized)?;
+            diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set((
+                dsl::trailingStopOffset.eq::<Option<f64>>(None),
+                dsl::trailingStopLevel.eq::<Option<f64>>(None),
+            )).execute(&mut connection)?;
+            return Ok(Some((level,order)));
+        }
+        let new_level = Self::trailing_stop_level(price, offset, transaction.trailingStopPercent);
+        if new_level > level{
+            trace!("Trailing stop raised {level} -> {new_level}");
+            diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set(dsl::trailingStopLevel.eq(Some(new_level))).execute(&mut connection)?;
+        }
+        Ok(None)
+    }
+}
+
+//Conditional orders
+impl BinanceWrapped{
+    /// Hold an order until the last price meets `condition` against `trigger_price`.
+    ///
+    /// The order belongs to the trader clocked in and is only sent while they still are. Sells
+    /// come out of the active transaction so they stay on its symbol. It expires after
+    /// `conditional/expiry_min` minutes if it never fires.
+    #[instrument(skip(self))]
+    pub fn add_conditional(&self,symbol: Option<String>,side: OrderSide,condition: Condition,trigger_price: f64,limit_price: Option<f64>,size: Option<Sizing>) -> Result<ConditionalOrder,TradingBotError>{
+        let Some(stub) = self.is_clocked_in()? else {
+            return Err(TradingBotError::NotClockedIn(String::new()))
+        };
+        if trigger_price <= 0.0 || limit_price.map_or(false, |price| price <= 0.0){
+            return Err(TradingBotError::OrderRejected("Trigger and limit prices must be positive".into()));
+        }
+        let config = self.config.load();
+        if limit_price.is_none() && !circuit_breaker::market_orders_allowed(&config)?{
+            return Err(TradingBotError::OrderRejected("Market orders are disabled please provide a price".into()));
+        }
+        let symbol = match (side,self.get_transaction()?,symbol){
+            (OrderSide::Sell,None,_) => return Err(TradingBotError::ActiveTransaction("Must buy before selling".into())),
+            (_,Some(transaction),Some(symbol)) if transaction.symbol != symbol => {
+                return Err(TradingBotError::ActiveTransaction(format!("Active transaction is trading {}",transaction.symbol)))
+            }
+            (_,Some(transaction),_) => transaction.symbol,
+            (_,None,Some(symbol)) => symbol,
+            (_,None,None) => self.default_symbol()?,
+        };
+        //crossing conditions start from where the price is now
+        let price = self.price(&symbol)?;
+        let expiry_min = match config.get::<i64>("conditional", "expiry_min")?{
+            Some(expiry_min) => expiry_min,
+            None => 60,
+        };
+        let (quantity,unit) = match size.map(Sizing::to_options){
+            Some((quantity,unit)) => (Some(quantity),Some(unit)),
+            None => (None,None),
+        };
+        use crate::schema::conditional_orders::dsl;
+        let mut connection = establish_connection();
+        let now = Utc::now();
+        let order = diesel::insert_into(dsl::conditional_orders)
+            .values(NewConditionalOrder{
+                account_id: self.id,
+                user_id: stub.user_id,
+                symbol: &symbol,
+                side: side.as_str(),
+                condition: condition.as_str(),
+                trigger_price,
+                limit_price,
+                quantity,
+                unit,
+                last_price: Some(price),
+                status: "ACTIVE",
+                created_at: now,
+                expires_at: now + chrono::Duration::minutes(expiry_min),
+            })
+            .get_result::<ConditionalOrder>(&mut connection)?;
+        debug!("Conditional order {} set on {symbol} @{price}",order.id);
+        Ok(order)
+    }
+
+    /// Conditional orders on the account still waiting for their trigger, oldest first
+    pub fn conditional_orders(&self) -> Result<Vec<ConditionalOrder>,TradingBotError>{
+        use crate::schema::conditional_orders::dsl;
+        use diesel::ExpressionMethods;
+        let mut connection = establish_connection();
+        Ok(dsl::conditional_orders
+            .filter(dsl::account_id.eq(self.id))
+            .filter(dsl::status.eq("ACTIVE"))
+            .order(dsl::id.asc())
+            .load::<ConditionalOrder>(&mut connection)?)
+    }
+
+    /// Cancel waiting conditional orders, only `user_id`'s if given and only `id` if given.
+    /// Returns the ones cancelled, an order that fired in the meantime is left out.
+    pub fn cancel_conditionals(&self,user_id: Option<i64>,id: Option<i32>) -> Result<Vec<ConditionalOrder>,TradingBotError>{
+        use crate::schema::conditional_orders::dsl;
+        use diesel::ExpressionMethods;
+        let ids: Vec<i32> = self.conditional_orders()?
+            .into_iter()
+            .filter(|order| user_id.map_or(true, |user_id| order.user_id == user_id) && id.map_or(true, |id| order.id == id))
+            .map(|order| order.id)
+            .collect();
+        let mut connection = establish_connection();
+        let cancelled = diesel::update(dsl::conditional_orders.filter(dsl::id.eq_any(ids)).filter(dsl::status.eq("ACTIVE")))
+            .set(dsl::status.eq("CANCELLED"))
+            .get_results::<ConditionalOrder>(&mut connection)?;
+        debug!("Cancelled {} conditional orders",cancelled.len());
+        Ok(cancelled)
+    }
+
+    /// Send the conditional orders whose condition is met and expire the ones out of time.
+    ///
+    /// Runs from the interval handler. While the account is halted or someone other than the
+    /// trader who set an order is clocked in the order is held and only follows the price.
+    /// Returns the orders that stopped waiting and what became of them.
+    pub fn check_conditionals(&self) -> Result<Vec<(ConditionalOrder,Outcome)>,TradingBotError>{
+        let orders = self.conditional_orders()?;
+        if orders.is_empty(){
+            return Ok(Vec::new());
+        }
+        let clocked_in = self.is_clocked_in()?.map(|stub| stub.user_id);
+        let halted = self.is_halted()?;
+        use crate::schema::conditional_orders::dsl;
+        use diesel::ExpressionMethods;
+        let mut connection = establish_connection();
+        let mut prices: HashMap<String,f64> = HashMap::new();
+        let mut done = Vec::new();
+        for order in orders{
+            let now = Utc::now();
+            let waiting = dsl::conditional_orders.filter(dsl::id.eq(order.id)).filter(dsl::status.eq("ACTIVE"));
+            if order.expires_at <= now{
+                if diesel::update(waiting).set(dsl::status.eq("EXPIRED")).execute(&mut connection)? > 0{
+                    done.push((order,Outcome::Expired));
+                }
+                continue;
+            }
+            let price = match prices.get(&order.symbol){
+                Some(price) => *price,
+                None => {
+                    let price = self.price(&order.symbol)?;
+                    prices.insert(order.symbol.clone(), price);
+                    price
+                }
+            };
+            let held = halted || clocked_in != Some(order.user_id);
+            if held || !Condition::of(&order)?.met(order.trigger_price, order.last_price, price){
+                diesel::update(waiting).set(dsl::last_price.eq(Some(price))).execute(&mut connection)?;
+                continue;
+            }
+            //claimed before it is sent so a cancel racing it can't see it still waiting
+            let claimed = diesel::update(waiting)
+                .set((dsl::status.eq("TRIGGERED"),dsl::triggered_at.eq(Some(now)),dsl::last_price.eq(Some(price))))
+                .execute(&mut connection)?;
+            if claimed == 0{
+                continue;
+            }
+            debug!("Conditional order {} triggered @{price}",order.id);
+            let outcome = match self.send_conditional(&order){
+                Ok(sent) => {
+                    diesel::update(dsl::conditional_orders.filter(dsl::id.eq(order.id)))
+                        .set(dsl::client_order_id.eq(Some(&sent.client_order_id)))
+                        .execute(&mut connection)?;
+                    Outcome::Sent(sent)
+                }
+                Err(err) => {
+                    warn!("Conditional order {} failed {err}",order.id);
+                    diesel::update(dsl::conditional_orders.filter(dsl::id.eq(order.id)))
+                        .set((dsl::status.eq("FAILED"),dsl::detail.eq(Some(err.to_string()))))
+                        .execute(&mut connection)?;
+                    Outcome::Failed(err)
+                }
+            };
+            done.push((order,outcome));
+        }
+        Ok(done)
+    }
+
+    //Send a triggered conditional order the same way /buy and /sell would
+    fn send_conditional(&self,order: &ConditionalOrder) -> Result<Transaction,TradingBotError>{
+        if order.limit_price.is_none() && !circuit_breaker::market_orders_allowed(&self.config.load())?{
+            return Err(TradingBotError::OrderRejected("Market orders are disabled".into()));
+        }
+        let size = Sizing::from_options(order.quantity, order.unit.as_deref())?;
+        let price = order.limit_price.map(|price| price as f32);
+        if order.side == OrderSide::Buy.as_str(){
+            return self.buy(Some(order.symbol.clone()), price, size, OrderOptions::default());
+        }
+        //the position it was set against may have been closed and another opened since
+        match self.get_transaction()?{
+            Some(transaction) if transaction.symbol == order.symbol => self.sell(price, size, OrderOptions::default()),
+            _ => Err(TradingBotError::ActiveTransaction(format!("No open position on {} to sell",order.symbol))),
+        }
+    }
 }
@@ -0,0 +1,295 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use arc_swap::ArcSwap;
+use chrono::{DateTime, TimeZone, Utc};
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::{
+    config::Config,
+    db::establish_connection,
+    error::TradingBotError,
+    market_data::Feed,
+    models,
+    ops::config_ops::{self, Operations},
+    transaction_state::TransactionState,
+};
+
+/// Why the breaker tripped, None while it is armed.
+///
+/// Tripping stops new entries and market orders until an admin re-arms it with /rearm.
+pub fn tripped(config: &Config) -> Result<Option<String>, TradingBotError> {
+    config.get::<String>("breaker", "tripped")
+}
+
+//Market orders are off when the config says so or the breaker has tripped
+pub fn market_orders_allowed(config: &Config) -> Result<bool, TradingBotError> {
+    let allowed = config.get("trading", "market_orders")?.unwrap_or(true);
+    Ok(allowed && tripped(config)?.is_none())
+}
+
+//Only counts what happened since the breaker was last re-armed
+fn armed_at(config: &Config) -> Result<DateTime<Utc>, TradingBotError> {
+    Ok(match config.get::<i64>("breaker", "armed_at")? {
+        Some(millis) => Utc.timestamp_millis_opt(millis).unwrap(),
+        None => Utc.timestamp_millis_opt(0).unwrap(),
+    })
+}
+
+fn store(config: &ArcSwap<Config>, key: &str, value: Option<String>) -> Result<(), TradingBotError> {
+    config_ops::handle(Operations::UpdateConfig(models::UpdateConfig {
+        section: "breaker".into(),
+        key: key.into(),
+        value,
+    }))?;
+    config.store(Arc::from(Config::load()?));
+    Ok(())
+}
+
+/// Trip the breaker, false if it had already tripped
+pub fn trip(config: &ArcSwap<Config>, reason: &str) -> Result<bool, TradingBotError> {
+    if tripped(&config.load())?.is_some() {
+        return Ok(false);
+    }
+    warn!("Circuit breaker tripped {reason}");
+    store(config, "tripped", Some(reason.into()))?;
+    Ok(true)
+}
+
+/// Re-arm the breaker returning why it had tripped
+pub fn rearm(config: &ArcSwap<Config>) -> Result<Option<String>, TradingBotError> {
+    let reason = tripped(&config.load())?;
+    store(config, "tripped", None)?;
+    store(config, "armed_at", Some(Utc::now().timestamp_millis().to_string()))?;
+    Ok(reason)
+}
+
+//When each equity sample was taken and the equity
+type Samples = VecDeque<(DateTime<Utc>, f64)>;
+
+/// Equity samples per account kept for the drawdown window
+#[derive(Default)]
+pub struct EquityHistory {
+    samples: Mutex<HashMap<i32, Samples>>,
+}
+
+impl EquityHistory {
+    /// Add a sample and return the drawdown from the window's peak in percent
+    pub fn record(&self, account_id: i32, equity: f64, since: DateTime<Utc>) -> f64 {
+        let mut samples = self.samples.lock().unwrap();
+        let history = samples.entry(account_id).or_default();
+        history.push_back((Utc::now(), equity));
+        while history.front().is_some_and(|(at, _)| *at < since) {
+            history.pop_front();
+        }
+        let peak = history.iter().map(|(_, equity)| *equity).fold(equity, f64::max);
+        if peak > 0.0 {
+            (peak - equity) / peak * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Check an account against the breaker's triggers, returns why it should trip.
+///
+/// Triggers with no limit set in the `breaker` config section are skipped.
+pub fn check(
+    config: &Config,
+    history: &EquityHistory,
+    account_id: i32,
+    equity: Option<f64>,
+    feeds: &[Arc<Feed>],
+) -> Result<Option<String>, TradingBotError> {
+    let armed_at = armed_at(config)?;
+    if let (Some(limit), Some(equity)) = (config.get::<f64>("breaker", "drawdown_pct")?, equity) {
+        let window = config.get::<i64>("breaker", "drawdown_window_min")?.unwrap_or(60);
+        let since = armed_at.max(Utc::now() - chrono::Duration::minutes(window));
+        let drawdown = history.record(account_id, equity, since);
+        if drawdown >= limit {
+            return Ok(Some(format!("Equity down {drawdown:.2}% in {window} minutes")));
+        }
+    }
+    if let Some(limit) = config.get::<f64>("breaker", "price_move_pct")? {
+        let window = config.get::<i64>("breaker", "price_move_secs")?.unwrap_or(60);
+        let since = Utc::now() - chrono::Duration::seconds(window);
+        for feed in feeds {
+            let quotes = feed.quotes_since(since);
+            let Some(latest) = quotes.last() else {
+                continue;
+            };
+            let moved = quotes
+                .iter()
+                .filter(|quote| quote.price > 0.0)
+                .map(|quote| (latest.price - quote.price) / quote.price * 100.0)
+                .fold(0.0, |moved: f64, change| if change.abs() > moved.abs() { change } else { moved });
+            if moved.abs() >= limit {
+                return Ok(Some(format!("{} moved {moved:.2}% in {window} seconds", feed.symbol)));
+            }
+        }
+    }
+    if let Some(limit) = config.get::<i64>("breaker", "losing_streak")? {
+        use crate::schema::{clock_stubs, transaction_events, transactions};
+        let mut connection = establish_connection();
+        //transactions closed with nothing bought have no PnL and don't break a streak
+        let closed = transaction_events::table
+            .inner_join(transactions::table.inner_join(clock_stubs::table))
+            .filter(clock_stubs::account_id.eq(Some(account_id)))
+            .filter(transaction_events::to_state.eq(TransactionState::Closed.as_str()))
+            .filter(transaction_events::created_at.ge(armed_at))
+            .filter(transactions::realizedPnl.is_not_null())
+            .order(transaction_events::id.desc())
+            .limit(limit)
+            .select(transactions::realizedPnl)
+            .load::<Option<f64>>(&mut connection)?;
+        if closed.len() as i64 >= limit && closed.iter().all(|pnl| pnl.unwrap_or(0.0) < 0.0) {
+            return Ok(Some(format!("{limit} losing transactions in a row")));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread, time::Duration};
+
+    use arc_swap::ArcSwap;
+    use chrono::Utc;
+    use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+
+    use super::{check, rearm, EquityHistory};
+    use crate::{
+        config::Config,
+        db::{establish_connection, test::database},
+        market_data::{Feed, Quote},
+        models::{self, BinanceAccount, ClockStub, NewBinanceAccount, NewClockStub, NewTransaction, NewUser},
+        ops::config_ops::{self, Operations},
+        transaction_state::{self, TransactionState},
+    };
+
+    const ACCOUNT: i32 = 1;
+
+    //Fresh config with the given breaker limits set
+    fn configured(limits: &[(&str, &str)]) -> Config {
+        Config::first_setup().unwrap();
+        for (key, value) in limits {
+            config_ops::handle(Operations::UpdateConfig(models::UpdateConfig {
+                section: "breaker".into(),
+                key: (*key).into(),
+                value: Some((*value).into()),
+            }))
+            .unwrap();
+        }
+        Config::load().unwrap()
+    }
+
+    fn quote(secs_ago: i64, price: f64) -> Quote {
+        Quote { price, bid: price, ask: price, time: Utc::now() - chrono::Duration::seconds(secs_ago) }
+    }
+
+    //Close a transaction on the account with the given PnL
+    fn closed(clock_stub_id: i32, pnl: f64) {
+        let transaction = transaction_state::open(
+            ACCOUNT,
+            NewTransaction { clock_stub_id, buyOrderTime: Utc::now(), symbol: "BTCUSDT".into() },
+            TransactionState::Long,
+            "opened",
+        )
+        .unwrap();
+        use crate::schema::transactions::dsl;
+        diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id)))
+            .set(dsl::realizedPnl.eq(Some(pnl)))
+            .execute(&mut establish_connection())
+            .unwrap();
+        transaction_state::transition(&transaction, TransactionState::Closed, "sold").unwrap();
+    }
+
+    #[test]
+    fn drawdown_trips_from_the_peak_since_rearming() {
+        let _database = database();
+        let config = Arc::new(ArcSwap::from_pointee(configured(&[("drawdown_pct", "5")])));
+        let history = EquityHistory::default();
+
+        assert_eq!(check(&config.load(), &history, ACCOUNT, Some(1000.0), &[]).unwrap(), None);
+        assert_eq!(check(&config.load(), &history, ACCOUNT, Some(960.0), &[]).unwrap(), None);
+        let reason = check(&config.load(), &history, ACCOUNT, Some(940.0), &[]).unwrap();
+        assert_eq!(reason.as_deref(), Some("Equity down 6.00% in 60 minutes"));
+        //other accounts keep their own peak
+        assert_eq!(check(&config.load(), &history, ACCOUNT + 1, Some(500.0), &[]).unwrap(), None);
+
+        //re-arming forgets the peak it tripped on
+        thread::sleep(Duration::from_millis(5));
+        rearm(&config).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(check(&config.load(), &history, ACCOUNT, Some(930.0), &[]).unwrap(), None);
+        //no equity means no drawdown to measure
+        assert_eq!(check(&config.load(), &history, ACCOUNT, None, &[]).unwrap(), None);
+    }
+
+    #[test]
+    fn price_move_trips_on_the_largest_move_in_the_window() {
+        let _database = database();
+        let config = configured(&[("price_move_pct", "3")]);
+        let history = EquityHistory::default();
+
+        //1% at most against the latest price, the drop before the window doesn't count
+        let calm = Arc::new(Feed::with_quotes("BTCUSDT", &[quote(120, 90.0), quote(30, 100.0), quote(10, 102.0), quote(0, 101.0)]));
+        assert_eq!(check(&config, &history, ACCOUNT, None, std::slice::from_ref(&calm)).unwrap(), None);
+
+        let falling = Arc::new(Feed::with_quotes("ETHUSDT", &[quote(30, 100.0), quote(10, 98.0), quote(0, 96.0)]));
+        let reason = check(&config, &history, ACCOUNT, None, &[calm, falling]).unwrap();
+        assert_eq!(reason.as_deref(), Some("ETHUSDT moved -4.00% in 60 seconds"));
+
+        //a feed with nothing received yet is skipped
+        let silent = Arc::new(Feed::with_quotes("BNBUSDT", &[]));
+        assert_eq!(check(&config, &history, ACCOUNT, None, &[silent]).unwrap(), None);
+    }
+
+    #[test]
+    fn losing_streak_trips_only_on_consecutive_losses() {
+        let _database = database();
+        let config = Arc::new(ArcSwap::from_pointee(configured(&[("losing_streak", "3")])));
+        let history = EquityHistory::default();
+        let mut connection = establish_connection();
+        diesel::insert_into(crate::schema::users::table)
+            .values(NewUser { id: 1, tag: "trader" })
+            .execute(&mut connection)
+            .unwrap();
+        diesel::insert_into(crate::schema::binance_accounts::table)
+            .values(NewBinanceAccount {
+                name: "test".into(),
+                is_paper: true,
+                api_key: None,
+                secret: None,
+                is_simulated: true,
+            })
+            .get_result::<BinanceAccount>(&mut connection)
+            .unwrap();
+        let stub = diesel::insert_into(crate::schema::clock_stubs::table)
+            .values(NewClockStub { start_time: Utc::now(), user_id: 1, last_interaction: Utc::now(), account_id: ACCOUNT })
+            .get_result::<ClockStub>(&mut connection)
+            .unwrap();
+
+        closed(stub.id, -10.0);
+        closed(stub.id, 5.0);
+        closed(stub.id, -10.0);
+        closed(stub.id, -10.0);
+        //the win is still among the last three
+        assert_eq!(check(&config.load(), &history, ACCOUNT, None, &[]).unwrap(), None);
+
+        closed(stub.id, -10.0);
+        let reason = check(&config.load(), &history, ACCOUNT, None, &[]).unwrap();
+        assert_eq!(reason.as_deref(), Some("3 losing transactions in a row"));
+
+        //losses before re-arming don't count towards the next streak
+        thread::sleep(Duration::from_millis(5));
+        rearm(&config).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        closed(stub.id, -10.0);
+        assert_eq!(check(&config.load(), &history, ACCOUNT, None, &[]).unwrap(), None);
+    }
+}
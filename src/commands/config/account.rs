@@ -3,7 +3,6 @@ use binance::account::Account;
 use diesel::RunQueryDsl;
 use serenity::{client::Context, model::prelude::{component::ButtonStyle, command::CommandOptionType, interaction::application_command::CommandDataOption}};
 use std::{sync::{Arc}, thread, time::Duration};
-use tokio::sync::RwLock;
 use tracing::{debug, warn, trace, error};
 
 use serenity::{
@@ -12,7 +11,8 @@ use serenity::{
 };
 
 use crate::{
-    binance_wrapped::BinanceWrapped,
+    account_manager::AccountManager,
+    exchange::simulated::SimulatedExchange,
     commands::{CommandError, SlashCommand, AutoComplete},
     config::{Config, ValueType}, utils::get_option::get_option, db::establish_connection, ops::config_ops, models::{self, BinanceAccount},
 };
@@ -34,15 +34,13 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
         )
         .create_sub_option(|sub_opt|
             sub_opt.name("account_api")
-            .description("The Binance Account Api Key")
+            .description("The Binance Account Api Key not needed for simulated accounts")
             .kind(CommandOptionType::String)
-            .required(true)
         )
         .create_sub_option(|opt|
             opt.name("account_secret")
-            .description("The Binance Secret")
+            .description("The Binance Secret not needed for simulated accounts")
             .kind(CommandOptionType::String)
-            .required(true)
         )
         .create_sub_option(|opt|
             opt.name("is_paper")
@@ -50,11 +48,30 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
             .default_option(false)
             .kind(CommandOptionType::Boolean)
         ) 
+        .create_sub_option(|opt|
+            opt.name("is_simulated")
+            .description("Trade virtual balances locally against live prices")
+            .default_option(false)
+            .kind(CommandOptionType::Boolean)
+        ) 
     )
     .create_option(|opt|
         opt.kind(CommandOptionType::SubCommand)
-        .name("set")
-        .description("set the binance account")  
+        .name("activate")
+        .description("start trading on a binance account alongside the other active ones")  
+        .create_sub_option(|sub_opt|
+            sub_opt.name("account_name")
+            .description("The custom name to recognize this account in the db")
+            .kind(CommandOptionType::String)
+            .required(true)
+            .set_autocomplete(true)
+        )
+        
+    )
+    .create_option(|opt|
+        opt.kind(CommandOptionType::SubCommand)
+        .name("deactivate")
+        .description("stop trading on a binance account")  
         .create_sub_option(|sub_opt|
             sub_opt.name("account_name")
             .description("The custom name to recognize this account in the db")
@@ -83,12 +100,12 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
 
 
 pub struct AccountCommand {
-    binance: Arc<RwLock<BinanceWrapped>>
+    accounts: Arc<AccountManager>
 }
 
 impl AccountCommand {
-    pub fn new(binance: Arc<RwLock<BinanceWrapped>>) -> Self {
-        AccountCommand {binance }
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        AccountCommand {accounts }
     }
 }
 #[async_trait]
@@ -114,39 +131,55 @@ impl SlashCommand for AccountCommand {
             debug!("Running create sub command");
             let options:&Vec<CommandDataOption> = sub_command.options.as_ref();
             let name = get_option::<String>(&mut options.iter(), "account_name")?;
-            let api = get_option::<String>(&mut options.iter(), "account_api")?;
-            let secret = get_option::<String>(&mut options.iter(), "account_secret")?;
+            let api = get_option::<String>(&mut options.iter(), "account_api").ok();
+            let secret = get_option::<String>(&mut options.iter(), "account_secret").ok();
             let is_paper = get_option::<bool>(&mut options.iter(), "is_paper").unwrap_or(false);
+            let is_simulated = get_option::<bool>(&mut options.iter(), "is_simulated").unwrap_or(false);
+            if !is_simulated && (api.is_none() || secret.is_none()){
+                return Err(CommandError::IncorrectParameters("Api key and secret are required unless the account is simulated".into()));
+            }
             debug!("Executing create_account Command");
             use crate::schema::binance_accounts::dsl;
             let mut connection = establish_connection();
-            trace!("Recieved Options {name} {api} {secret} {is_paper}");
-            diesel::insert_into(dsl::binance_accounts).values(crate::models::NewBinanceAccount{
+            trace!("Recieved Options {name} {api:?} {secret:?} {is_paper} {is_simulated}");
+            let account = diesel::insert_into(dsl::binance_accounts).values(crate::models::NewBinanceAccount{
                 name,
                 api_key: api,
                 secret,
                 is_paper,
-            }).execute(&mut connection)?;
+                is_simulated,
+            }).get_result::<BinanceAccount>(&mut connection)?;
+            if is_simulated{
+                let config = config_swap.load();
+                let asset = match config.get::<String>("paper", "starting_asset")? {
+                    Some(asset) => asset,
+                    None => "USDT".into(),
+                };
+                let amount = match config.get::<f64>("paper", "starting_balance")? {
+                    Some(amount) => amount,
+                    None => 10000.0,
+                };
+                SimulatedExchange::seed(account.id, &asset, amount)?;
+                debug!("Seeded simulated account with {amount} {asset}");
+            }
             interaction.edit_original_interaction_response(&ctx.http, |i|
                 i.content("Account Created succesfully")
             ).await?;
-        }else if let Some(sub_command) = options.iter().find(|opt| opt.name == "set"){
-            debug!("Running set sub command");
+        }else if let Some(sub_command) = options.iter().find(|opt| opt.name == "activate"){
+            debug!("Running activate sub command");
             let mut options = sub_command.options.iter();
             let name = get_option::<String>(&mut options, "account_name")?;
-            config_ops::handle(config_ops::Operations::UpdateConfig(models::UpdateConfig{
-                section: "trading".into(),
-                key: "account_name".into(),
-                value: Some(name)
-            }))?;
-
-            config_swap.store(Arc::from(Config::load()?));
-            {
-                let mut binance = self.binance.write().await;
-                binance.load_account()?;
-            }
+            self.accounts.activate(&name).await?;
+            interaction.edit_original_interaction_response(&ctx.http, |i|
+                i.content(format!("{name} is now active"))
+            ).await?;
+        }else if let Some(sub_command) = options.iter().find(|opt| opt.name == "deactivate"){
+            debug!("Running deactivate sub command");
+            let mut options = sub_command.options.iter();
+            let name = get_option::<String>(&mut options, "account_name")?;
+            self.accounts.deactivate(&name).await?;
             interaction.edit_original_interaction_response(&ctx.http, |i|
-                i.content("Account Set succesfully")
+                i.content(format!("{name} is no longer active"))
             ).await?;
         }else if let Some(delete_command) = options.iter().find(|opt| opt.name == "delete"){
             debug!("Running set delete command");
@@ -154,6 +187,9 @@ impl SlashCommand for AccountCommand {
             let mut connection = establish_connection();
 
             let name = get_option::<String>(&mut options, "account_name")?;
+            if self.accounts.get(&name).await.is_ok(){
+                self.accounts.deactivate(&name).await?;
+            }
             use diesel::ExpressionMethods;
             use crate::schema::binance_accounts::dsl;
             diesel::delete(dsl::binance_accounts).filter(dsl::name.eq(name)).execute(&mut connection)?;
@@ -179,7 +215,7 @@ impl AutoComplete for AccountCommand {
         let accounts = dsl::binance_accounts.load::<BinanceAccount>(&mut connection)?;
         let options:&Vec<CommandDataOption> = interaction.data.options.as_ref();
 
-        let Some(set_option) = options.iter().find(|a| a.name == "set" || a.name =="delete") else {
+        let Some(set_option) = options.iter().find(|a| a.name == "activate" || a.name == "deactivate" || a.name =="delete") else {
             trace!("Did not find set sub command for auto_complete");
             return Ok(());
         };
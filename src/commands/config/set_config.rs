@@ -164,6 +164,22 @@ impl SlashCommand for SetConfigCommand {
                     response.content(format!("Updated Config at {section}/{key} previous:{old_value:?} new:{value:?}"))
                 }).await?;
             }
+            ValueType::FLOAT => {
+                let old_value = config_load.get::<f64>(&section, &key)?;
+
+                let Ok(value) = value.parse::<f64>() else{
+                    return Err(CommandError::IncorrectParameters("Expected float".into()));
+                };
+                //update config
+                config_ops::handle(Operations::UpdateConfig(models::UpdateConfig {
+                    section: section.clone(),
+                    key: key.clone(),
+                    value: Some(value.to_string()),
+                }))?;
+                interaction.edit_original_interaction_response(&ctx.http, |response| {
+                    response.content(format!("Updated Config at {section}/{key} previous:{old_value:?} new:{value:?}"))
+                }).await?;
+            }
         }
         }   
         match Config::load() {
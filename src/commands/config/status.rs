@@ -2,7 +2,6 @@ use arc_swap::{ArcSwapAny, Guard};
 use last_git_commit::LastGitCommit;
 use serenity::client::Context;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use serenity::{
     async_trait,
     builder::CreateApplicationCommand,
@@ -13,7 +12,7 @@ use serenity::{
 
 use crate::{
     commands::{CommandError, SlashCommand},
-    config::Config, binance_wrapped::BinanceWrapped,
+    config::Config, account_manager::AccountManager,
 };
 pub(crate) const COMMAND_NAME: &'static str = "status";
 pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
@@ -23,13 +22,11 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
 }
 
 pub struct StatusCommand{
-    binance: Arc<RwLock<BinanceWrapped>>
+    accounts: Arc<AccountManager>
 }
 impl StatusCommand {
-    pub fn new(binance: Arc<RwLock<BinanceWrapped>>) -> Self {
-        StatusCommand {
-            binance
-        }
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        StatusCommand { accounts }
     }
 }
 #[async_trait]
@@ -47,15 +44,19 @@ impl SlashCommand for StatusCommand {
         ctx: Context,
         config: Arc<ArcSwapAny<Arc<Config>>>,
     ) -> Result<(), CommandError> {
-        let binance = self.binance.read().await;
-        let mut binance_status = "".into();
-        if let Some(account) = binance.account.as_ref(){
-            let account_info = account.get_account();
-            binance_status = match account_info{
+        let mut binance_status = Vec::new();
+        for binance in self.accounts.all().await{
+            let account_info = binance.blocking(|b| b.get()?.get_account()).await;
+            binance_status.push((binance.name.clone(),match account_info{
                 Err(err) => format!("❌ {}",err),
-                Ok(_) => format!("✅")
-            }
+                Ok(_) => format!("✅ {} orders in the last 10s",binance.orders_used())
+            }));
+        }
+        binance_status.sort();
+        if binance_status.is_empty(){
+            binance_status.push(("Binance".into(),"No active accounts".into()));
         }
+        let usage = self.accounts.governor().usage()?;
 
         let lgc = LastGitCommit::new().build().unwrap();
         
@@ -63,7 +64,13 @@ impl SlashCommand for StatusCommand {
         //Check if rrservation handler is up
         interaction
             .edit_original_interaction_response(&ctx.http, |response| response.embed(|e|
-            e.field("Binance", binance_status, false)
+            e.fields(binance_status.into_iter().map(|(name,status)| (name,status,false)))
+            ).embed(|e|
+                e.title("Rate Limits")
+                .field("Request Weight", format!("{}/{} this minute",usage.used,usage.limit), false)
+                .field("Waiting", usage.waiting, true)
+                .field("Queued", usage.queued, true)
+                .field("Shed", usage.shed, true)
             ).embed(|e|
                 e.title("Git Commit")
                 .field("Message", lgc.message().unwrap_or(&"No Message".into()), false)
@@ -12,7 +12,6 @@ use serenity::{
         application_command::ApplicationCommandInteraction, InteractionResponseType,
     },
 };
-use tokio::sync::RwLock;
 
 use crate::{
     commands::{AutoComplete, CommandError, SlashCommand},
@@ -21,23 +20,29 @@ use crate::{
     ops::user_ops,
     schedule::{Schedule, TimeSlot},
     schema::reservations,
-    utils::get_option::{self, get_option}, binance_wrapped::BinanceWrapped,
+    utils::get_option::{self, get_option}, account_manager::AccountManager,
 };
 pub(crate) const COMMAND_NAME: &'static str = "clock";
 pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command
         .name(COMMAND_NAME)
         .description("Change your clocked in status")
+        .create_option(|opt|
+            opt.name("account")
+            .description("account to clock into leave blank if only one is active")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
 }
 
 
 
 pub struct ClockCommand{
-    account: Arc<RwLock<BinanceWrapped>>
+    accounts: Arc<AccountManager>
 }
 impl ClockCommand {
-    pub fn new(account: Arc<RwLock<BinanceWrapped>>) -> Self {
-        ClockCommand {account}
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        ClockCommand { accounts }
     }
 }
 #[async_trait]
@@ -57,7 +62,8 @@ impl SlashCommand for ClockCommand {
     ) -> Result<(), CommandError> {
         trace!("Clock Command");
 
-        let account = self.account.read().await;     
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let account = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
         let clocked_in = account.is_clocked_in()?;   
         trace!("Clock {clocked_in:?}");
 
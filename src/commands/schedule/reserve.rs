@@ -15,6 +15,7 @@ use serenity::{
 };
 const FORMAT_STRING: &'static str = "%d/%m/%Y %I:%M %P";
 use crate::{
+    account_manager::AccountManager,
     commands::{AutoComplete, CommandError, SlashCommand},
     config::Config,
     models::NewReservation,
@@ -46,6 +47,12 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
                     .set_autocomplete(true)
                     .required(true)
             })
+            .create_sub_option(|opt| {
+                opt.kind(CommandOptionType::String)
+                    .name("account")
+                    .description("Account to reserve leave blank if only one is active")
+                    .set_autocomplete(true)
+            })
         )
         .create_option(|opt|
             opt.kind(CommandOptionType::SubCommand)
@@ -57,11 +64,12 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
 }
 
 
-#[derive(Debug)]
-pub struct ReserveCommand;
+pub struct ReserveCommand {
+    accounts: Arc<AccountManager>,
+}
 impl ReserveCommand {
-    pub fn new() -> Self {
-        ReserveCommand {}
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        ReserveCommand { accounts }
     }
 }
 #[async_trait]
@@ -85,6 +93,8 @@ impl SlashCommand for ReserveCommand {
         let options:&Vec<CommandDataOption> = interaction.data.options.as_ref();
         
         if let Some(sub_command) = options.iter().find(|opt| opt.name == "create"){ 
+            let account = get_option::<String>(&mut sub_command.options.iter(), "account").ok();
+            let account_id = self.accounts.resolve(account, interaction.user.id.0 as i64).await?.id;
             let time_zone = match config.get::<String>("schedule","timezone")? {
                 Some(tz_str) => tz_str.parse::<Tz>().unwrap_or(Tz::UTC),
                 None => Tz::UTC
@@ -106,6 +116,7 @@ impl SlashCommand for ReserveCommand {
                 start_time,
                 end_time,
                 user_id: i64::from(interaction.user.id),
+                account_id,
             })?;
 
             if out {
@@ -229,6 +240,8 @@ impl AutoComplete for ReserveCommand {
         if let Some(sub_command) = options.iter().find(|opt| opt.name == "create"){ 
             let mut options = sub_command.options.iter();
             let focused = options.clone().find(|opt| opt.focused);
+            let account = get_option::<String>(&mut options.clone(), "account").ok();
+            let account_id = self.accounts.resolve(account, interaction.user.id.0 as i64).await?.id;
 
             let Some(focused) = focused else{
                 return Ok(());
@@ -242,7 +255,7 @@ impl AutoComplete for ReserveCommand {
             if focused.name == "start_time" {
                 let start_time = get_option::<String>(&mut options, "start_time")?;
                 
-                let time_slots = Schedule::open_time_slots(None, &config)?;
+                let time_slots = Schedule::open_time_slots(account_id, None, &config)?;
                 trace!(
                     "Starttime {}",start_time
                 );
@@ -279,7 +292,7 @@ impl AutoComplete for ReserveCommand {
                 let start_time = start_time.and_local_timezone(time_zone).unwrap();
 
                 let start_time = start_time.with_timezone(&Utc);
-                let time_slots = Schedule::open_time_slots(Some(start_time), &config)?;
+                let time_slots = Schedule::open_time_slots(account_id, Some(start_time), &config)?;
                 trace!(
                     "Starttime {}",start_time
                 );
@@ -122,13 +122,11 @@ impl SlashCommand for SummaryCommand {
                 trace!("Transactions {:?}",transactions);
                 let mut stub_pay = 0.0;
                 for transaction in transactions.iter(){
-                    let Some(buy_price ) = transaction.buyAvgPrice else{
+                    //quote asset after commissions, nothing until something has been sold
+                    let Some(pnl) = transaction.realizedPnl else{
                         continue;
                     };
-                    let Some(sell_price ) = transaction.sellAvgPrice else{
-                        continue;
-                    };
-                    stub_pay += sell_price - buy_price
+                    stub_pay += pnl
                     
                 }
                 let mins = (end_time - stub.start_time).num_minutes();
@@ -0,0 +1,110 @@
+use arc_swap::ArcSwapAny;
+use serenity::{client::Context, model::prelude::command::CommandOptionType};
+use std::sync::Arc;
+use tracing::debug;
+
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::interaction::application_command::ApplicationCommandInteraction,
+};
+
+use crate::{
+    account_manager::AccountManager,
+    commands::{CommandError, SlashCommand},
+    config::Config, utils::get_option::get_option, error::TradingBotError,
+};
+
+pub(crate) const COMMAND_NAME: &'static str = "amend";
+pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND_NAME)
+        .description("Move the working limit order to a new price")
+        .create_option(|opt|
+            opt.name("price")
+            .description("new price for the order")
+            .kind(CommandOptionType::Number)
+            .required(true)
+        )
+        .create_option(|opt|
+            opt.name("account")
+            .description("account to trade on leave blank to use the one you are clocked into")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
+}
+
+pub struct AmendCommand {
+    accounts: Arc<AccountManager>,
+}
+impl AmendCommand {
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        AmendCommand { accounts }
+    }
+}
+#[async_trait]
+impl SlashCommand for AmendCommand {
+    fn config(&self) -> crate::commands::CommandConfig {
+        crate::commands::CommandConfig {
+            accessLevel: crate::commands::AccessLevels::TRADER,
+            counts_as_activity: true,
+            ..Default::default()
+        }
+    }
+
+    async fn run(
+        &self,
+        interaction: ApplicationCommandInteraction,
+        ctx: Context,
+        _config: Arc<ArcSwapAny<Arc<Config>>>,
+    ) -> Result<(), CommandError> {
+        debug!("Executing Amend Command");
+        let price = get_option::<f32>(&mut interaction.data.options.iter(), "price")?;
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
+        if let Some(stub) = binance.is_clocked_in()?{
+            if stub.user_id != interaction.user.id.0 as i64{
+                return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into())))
+            }
+        }else{
+            return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into())))
+        }
+        let (cancelled, result) = binance.blocking(move |b| b.amend(price)).await?;
+        let order = result.map_err(|err| format!(
+            "Cancelled {} {} {}@{} but the replacement did not go out {err}",
+            cancelled.side,
+            cancelled.orig_qty.unwrap_or_default() - cancelled.executed_qty,
+            cancelled.symbol,
+            cancelled.price.unwrap_or_default()
+        ));
+        //the old order is gone either way so the trader has to hear about it
+        let order = match order {
+            Ok(order) => order,
+            Err(content) => {
+                interaction
+                    .edit_original_interaction_response(&ctx.http, |response| response.content(content))
+                    .await?;
+                return Ok(());
+            }
+        };
+        interaction
+            .edit_original_interaction_response(&ctx.http, |response| {
+                response.content(format!(
+                    "Moved {} {} {} from {} to {} as order {}{}",
+                    order.side,
+                    order.orig_qty,
+                    order.symbol,
+                    cancelled.price.unwrap_or_default(),
+                    order.price,
+                    order.order_id,
+                    if cancelled.executed_qty > 0.0 {
+                        format!(", {} filled before the move", cancelled.executed_qty)
+                    } else {
+                        String::new()
+                    }
+                ))
+            })
+            .await?;
+        Ok(())
+    }
+}
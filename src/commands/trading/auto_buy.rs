@@ -3,7 +3,6 @@ use binance::account::Account;
 use serenity::{client::Context, model::prelude::{component::ButtonStyle, command::CommandOptionType, interaction::InteractionResponseType}};
 use std::{sync::Arc, thread, time::Duration};
 use tracing::{debug, warn, trace};
-use tokio::sync::RwLock;
 
 use serenity::{
     async_trait, builder::CreateApplicationCommand,
@@ -11,9 +10,10 @@ use serenity::{
 };
 
 use crate::{
-    binance_wrapped::BinanceWrapped,
+    account_manager::AccountManager,
+    circuit_breaker,
     commands::{CommandError, SlashCommand},
-    config::{Config, ValueType}, utils::get_option::get_option, error::TradingBotError,
+    config::{Config, ValueType}, sizing::Sizing, utils::get_option::get_option, error::TradingBotError, transaction_state::TransactionState, exchange::OrderOptions,
 };
 
 pub(crate) const COMMAND_NAME: &'static str = "auto_buy";
@@ -21,15 +21,34 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
     command
         .name(COMMAND_NAME)
         .description("buy BTC at market and sell at a offset price")
+        .create_option(|opt|
+            opt.name("quantity")
+            .description("amount to buy in the unit picked leave blank to buy with the whole balance")
+            .kind(CommandOptionType::Number)
+        )
+        .create_option(|opt|
+            opt.name("unit")
+            .description("what the quantity is in defaults to percent of the free quote balance")
+            .kind(CommandOptionType::String)
+            .add_string_choice("percent of balance", "percent")
+            .add_string_choice("base asset eg BTC", "base")
+            .add_string_choice("quote asset eg USDT", "quote")
+        )
+        .create_option(|opt|
+            opt.name("account")
+            .description("account to trade on leave blank to use the one you are clocked into")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
 }
 
 pub struct AutoBuyCommand {
-    binance: Arc<RwLock<BinanceWrapped>>,
+    accounts: Arc<AccountManager>,
 }
 
 impl AutoBuyCommand {
-    pub fn new(binance: Arc<RwLock<BinanceWrapped>>) -> Self {
-        AutoBuyCommand { binance }
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        AutoBuyCommand { accounts }
     }
 }
 #[async_trait]
@@ -50,8 +69,8 @@ impl SlashCommand for AutoBuyCommand {
     ) -> Result<(), CommandError> {
         let config = config.load();
 
-        let binance = self.binance.read().await;
-        trace!("Locked Binance Account");
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
         if let Some(stub) = binance.is_clocked_in()?{
             if stub.user_id != interaction.user.id.0 as i64{
                 return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into())))
@@ -59,10 +78,8 @@ impl SlashCommand for AutoBuyCommand {
         }else{
             return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into())))
         }
-        let market_orders_allowed = match config.get("trading", "market_orders")? {
-            Some(int) => int,
-            None => true,
-        };
+        //a tripped circuit breaker turns market orders off too
+        let market_orders_allowed = circuit_breaker::market_orders_allowed(&config)?;
 
         if !market_orders_allowed{
             interaction
@@ -76,7 +93,15 @@ impl SlashCommand for AutoBuyCommand {
         }
 
 
-        let msg = format!("Pick a offset price to sell at after market buy ");
+        let quantity = get_option::<f64>(&mut interaction.data.options.iter(), "quantity").ok();
+        let unit = get_option::<String>(&mut interaction.data.options.iter(), "unit").ok();
+        let size = Sizing::from_options(quantity, unit.as_deref())?;
+        let symbol = match binance.get_transaction()? {
+            Some(transaction) => transaction.symbol,
+            None => binance.default_symbol()?,
+        };
+        let sized = binance.blocking(move |b| b.size_buy(&symbol, None, size.unwrap_or(Sizing::ALL), OrderOptions::default())).await?;
+        let msg = format!("Pick a offset price to sell at after the market {sized}");
         interaction
             .edit_original_interaction_response(&ctx.http, |response| {
                 response
@@ -139,7 +164,7 @@ impl SlashCommand for AutoBuyCommand {
         };
         trace!("Recieved Button Interaction");
         if a.data.custom_id != "cancel" {
-            let Some(binance_account) = binance.account.as_ref() else {
+            let Ok(binance_account) = binance.get() else {
                 a.edit_original_interaction_response(&ctx, |response| {
                     response
                         .content("Request failed No Account set")
@@ -150,12 +175,12 @@ impl SlashCommand for AutoBuyCommand {
             a.create_interaction_response(&ctx, |r| {
                 r.kind(InteractionResponseType::DeferredUpdateMessage)
             }).await?;
-            let order = binance.buy(None, None)?;
+            let order = binance.blocking(move |b| b.buy(None, None, size, OrderOptions::default())).await?;
             debug!("Order {:#?}",order);
             let transaction;
             loop{
                 if let Ok(Some(db_transaction)) = binance.get_transaction(){
-                    if db_transaction.sellReady && db_transaction.buyAvgPrice.is_some(){
+                    if TransactionState::of(&db_transaction)?.can_sell(){
                         a.edit_original_interaction_response(&ctx, |response| {
                             response
                                 .content("Market Order filled sending sell order")
@@ -180,7 +205,7 @@ impl SlashCommand for AutoBuyCommand {
             let price = a.data.custom_id.parse::<f32>().unwrap();
             let buy_price = transaction.buyAvgPrice.unwrap() as f32;
             debug!("selling at price {}",buy_price+price);
-            let order = binance.sell(Some(buy_price+price), None)?;
+            let order = binance.blocking(move |b| b.sell(Some(buy_price+price), None, OrderOptions::default())).await?;
             a.edit_original_interaction_response(&ctx, |response| {
                 response
                     .content("Order Sent")
@@ -1,8 +1,7 @@
 use arc_swap::{ArcSwap, ArcSwapAny, Guard};
 use binance::account::Account;
-use serenity::client::Context;
+use serenity::{client::Context, model::prelude::command::CommandOptionType};
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 use serenity::{
     async_trait,
@@ -13,9 +12,9 @@ use serenity::{
 };
 
 use crate::{
-    binance_wrapped::BinanceWrapped,
+    account_manager::AccountManager,
     commands::{CommandError, SlashCommand},
-    config::Config,
+    config::Config, utils::get_option::get_option,
 };
 
 pub(crate) const COMMAND_NAME: &'static str = "balance";
@@ -23,14 +22,20 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
     command
         .name(COMMAND_NAME)
         .description("Check the balance of the account")
+        .create_option(|opt|
+            opt.name("account")
+            .description("account to check leave blank to use the one you are clocked into")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
 }
 
 pub struct BalanceCommand {
-    binance: Arc<RwLock<BinanceWrapped>>,
+    accounts: Arc<AccountManager>,
 }
 impl BalanceCommand {
-    pub fn new(binance: Arc<RwLock<BinanceWrapped>>) -> Self {
-        BalanceCommand { binance }
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        BalanceCommand { accounts }
     }
 }
 #[async_trait]
@@ -49,9 +54,14 @@ impl SlashCommand for BalanceCommand {
         ctx: Context,
         config: Arc<ArcSwapAny<Arc<Config>>>,
     ) -> Result<(), CommandError> {
-        let binance = self.binance.read().await;
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
         
-        let (base,quote) = binance.get_balance()?;
+        let symbol = match binance.get_transaction()? {
+            Some(transaction) => transaction.symbol,
+            None => binance.default_symbol()?,
+        };
+        let (base,quote) = binance.blocking(move |b| b.get_balance(&symbol)).await?;
 
 
         interaction
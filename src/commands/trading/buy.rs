@@ -3,7 +3,6 @@ use binance::account::Account;
 use serenity::{client::Context, model::prelude::{component::ButtonStyle, command::CommandOptionType, interaction::InteractionResponseType}};
 use std::{sync::Arc, thread, time::Duration};
 use tracing::{debug, warn, trace};
-use tokio::sync::RwLock;
 
 use serenity::{
     async_trait, builder::CreateApplicationCommand,
@@ -11,9 +10,10 @@ use serenity::{
 };
 
 use crate::{
-    binance_wrapped::BinanceWrapped,
-    commands::{CommandError, SlashCommand},
-    config::{Config, ValueType}, utils::get_option::get_option, error::TradingBotError,
+    account_manager::AccountManager,
+    circuit_breaker,
+    commands::{AutoComplete, CommandError, SlashCommand},
+    config::{Config, ValueType}, sizing::Sizing, utils::get_option::get_option, error::TradingBotError, exchange::{governor::Priority, OrderOptions, TimeInForce},
 };
 
 pub(crate) const COMMAND_NAME: &'static str = "buy";
@@ -29,18 +29,51 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
         )
         .create_option(|opt|
             opt.name("quantity")
-            .description("account percentage to buy with leave blank to buy with whole account 0-1")
+            .description("amount to buy in the unit picked leave blank to buy with the whole balance")
             .kind(CommandOptionType::Number)
         )
+        .create_option(|opt|
+            opt.name("unit")
+            .description("what the quantity is in defaults to percent of the free quote balance")
+            .kind(CommandOptionType::String)
+            .add_string_choice("percent of balance", "percent")
+            .add_string_choice("base asset eg BTC", "base")
+            .add_string_choice("quote asset eg USDT", "quote")
+        )
+        .create_option(|opt|
+            opt.name("time_in_force")
+            .description("how long a limit order stays on the book defaults to GTC")
+            .kind(CommandOptionType::String)
+            .add_string_choice("GTC good till cancelled", "GTC")
+            .add_string_choice("IOC immediate or cancel", "IOC")
+            .add_string_choice("FOK fill or kill", "FOK")
+        )
+        .create_option(|opt|
+            opt.name("post_only")
+            .description("only place the limit order if it would rest on the book as a maker")
+            .kind(CommandOptionType::Boolean)
+        )
+        .create_option(|opt|
+            opt.name("symbol")
+            .description("pair to trade eg ETHUSDT leave blank for the default symbol")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
+        .create_option(|opt|
+            opt.name("account")
+            .description("account to trade on leave blank to use the one you are clocked into")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
 }
 
 pub struct BuyCommand {
-    binance: Arc<RwLock<BinanceWrapped>>,
+    accounts: Arc<AccountManager>,
 }
 
 impl BuyCommand {
-    pub fn new(binance: Arc<RwLock<BinanceWrapped>>) -> Self {
-        BuyCommand { binance }
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        BuyCommand { accounts }
     }
 }
 #[async_trait]
@@ -74,8 +107,19 @@ impl SlashCommand for BuyCommand {
                 None
             }
         };
+        let unit = get_option::<String>(&mut interaction.data.options.iter(), "unit").ok();
+        let size = Sizing::from_options(quantity, unit.as_deref())?;
+        let symbol = get_option::<String>(&mut interaction.data.options.iter(), "symbol").ok().map(|s| s.to_uppercase());
+        let options = OrderOptions {
+            time_in_force: match get_option::<String>(&mut interaction.data.options.iter(), "time_in_force") {
+                Ok(time_in_force) => TimeInForce::parse(&time_in_force)?,
+                Err(_) => TimeInForce::Gtc,
+            },
+            post_only: get_option::<bool>(&mut interaction.data.options.iter(), "post_only").unwrap_or(false),
+        };
         debug!("Executing Buy Command");
-        let binance = self.binance.read().await;
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
         trace!("Locked Binance Account");
         if let Some(stub) = binance.is_clocked_in()?{
             if stub.user_id != interaction.user.id.0 as i64{
@@ -84,10 +128,8 @@ impl SlashCommand for BuyCommand {
         }else{
             return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into())))
         }
-        let market_orders_allowed = match config.get("trading", "market_orders")? {
-            Some(int) => int,
-            None => true,
-        };
+        //a tripped circuit breaker turns market orders off too
+        let market_orders_allowed = circuit_breaker::market_orders_allowed(&config)?;
 
         let confirm_order = match config.get("trading", "confirm_orders")? {
             Some(int) => int,
@@ -107,7 +149,21 @@ impl SlashCommand for BuyCommand {
 
         if confirm_order{
 
-            let msg = format!("Confirm placing order at {}",if price.is_some() {price.unwrap().to_string()}else{"Market Price".into()});
+            //an open transaction keeps its symbol
+            let preview_symbol = match (binance.get_transaction()?,symbol.clone()) {
+                (Some(transaction),_) => transaction.symbol,
+                (None,Some(symbol)) => symbol,
+                (None,None) => binance.default_symbol()?,
+            };
+            let market_price = match price {
+                Some(price) => price.to_string(),
+                None => {
+                    let preview_symbol = preview_symbol.clone();
+                    format!("Market Price (~{})",binance.blocking(move |b| b.price(&preview_symbol)).await?)
+                }
+            };
+            let sized = binance.blocking(move |b| b.size_buy(&preview_symbol, price, size.unwrap_or(Sizing::ALL), options)).await?;
+            let msg = format!("Confirm placing {sized} at {}",market_price);
             interaction
                 .edit_original_interaction_response(&ctx.http, |response| {
                     response
@@ -173,7 +229,7 @@ impl SlashCommand for BuyCommand {
             .edit_original_interaction_response(&ctx.http, |r| {
             r.content("sending buy")
         }).await?;
-        let order = binance.buy(price, quantity)?;
+        let order = binance.blocking(move |b| b.buy(symbol, price, size, options)).await?;
         debug!("Order {:#?}",order);
         interaction.edit_original_interaction_response(&ctx, |response| {
                 response
@@ -195,3 +251,27 @@ impl SlashCommand for BuyCommand {
         Ok(())
     }
 }
+#[async_trait]
+impl AutoComplete for BuyCommand {
+    async fn auto_complete(
+        &self,
+        interaction: serenity::model::prelude::interaction::autocomplete::AutocompleteInteraction,
+        ctx: Context,
+        config: Arc<Config>,
+    ) -> Result<(), CommandError> {
+        let symbol = get_option::<String>(&mut interaction.data.options.iter(), "symbol").unwrap_or_default().to_uppercase();
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
+        let symbols = binance.blocking_at(Priority::Low, |b| b.get_symbols()).await?;
+
+        interaction
+            .create_autocomplete_response(&ctx.http, |a| {
+                symbols.iter().filter(|s| s.starts_with(&symbol)).take(25).for_each(|s| {
+                    a.add_string_choice(s, s);
+                });
+                a
+            })
+            .await?;
+        Ok(())
+    }
+}
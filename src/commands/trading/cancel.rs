@@ -1,37 +1,47 @@
-use arc_swap::{ArcSwap, ArcSwapAny, Guard};
-use binance::account::Account;
-use diesel::{query_dsl::methods::FilterDsl, QueryDsl};
-use serenity::{client::Context, model::prelude::{component::ButtonStyle, command::CommandOptionType}};
-use std::{sync::Arc, thread, time::Duration, f32::consts::E};
-use tracing::{debug, warn, trace};
-use tokio::{sync::RwLock, time};
+use arc_swap::ArcSwapAny;
+use serenity::{client::Context, model::prelude::command::CommandOptionType};
+use std::{sync::Arc, time::Duration};
+use tracing::{debug, trace};
 
 use serenity::{
     async_trait, builder::CreateApplicationCommand,
-    model::prelude::interaction::application_command::ApplicationCommandInteraction,
+    model::prelude::interaction::{application_command::ApplicationCommandInteraction, InteractionResponseType},
 };
 
 use crate::{
-    binance_wrapped::BinanceWrapped,
+    account_manager::AccountManager,
     commands::{CommandError, SlashCommand},
-    config::{Config, ValueType}, utils::get_option::get_option, error::TradingBotError, db::establish_connection, models::DBTransaction,
+    config::Config, utils::{get_option::get_option, message::truncate}, error::TradingBotError,
 };
 
+//Discord caps a select menu at 25 options and one is taken by "all"
+const MAX_MENU_ORDERS: usize = 24;
+
 pub(crate) const COMMAND_NAME: &'static str = "cancel";
 pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command
         .name(COMMAND_NAME)
-        .description("cancel the active order")
-
+        .description("pick working orders to cancel")
+        .create_option(|opt|
+            opt.name("whole_symbol")
+            .description("ADMIN cancel every open order on the symbol including ones the bot didn't place")
+            .kind(CommandOptionType::Boolean)
+        )
+        .create_option(|opt|
+            opt.name("account")
+            .description("account to cancel on leave blank to use the one you are clocked into")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
 }
 
 pub struct CancelCommand {
-    binance: Arc<RwLock<BinanceWrapped>>,
+    accounts: Arc<AccountManager>,
 }
 
 impl CancelCommand {
-    pub fn new(binance: Arc<RwLock<BinanceWrapped>>) -> Self {
-        CancelCommand { binance }
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        CancelCommand { accounts }
     }
 }
 #[async_trait]
@@ -50,50 +60,118 @@ impl SlashCommand for CancelCommand {
         config: Arc<ArcSwapAny<Arc<Config>>>,
     ) -> Result<(), CommandError> {
         let config = config.load();
-        debug!("Executing Orders Command");
-        let binance = self.binance.read().await;
-    
-        let symbol = &match config.get::<String>("trading", "symbol")?{
-            Some(n) => n,
-            None => "BTCUSDT".into()
-        };
-
-        let Some(account) = &binance.account else{
-            return Err(CommandError::TradingBotError(TradingBotError::BinanceAccountMissing))
-        };
-        let Some(active_transaction) = binance.get_transaction()? else{
-            return Err(CommandError::TradingBotError(TradingBotError::ActiveTransaction("No active Transaction".into())))
-        };
-
-        if active_transaction.buyAvgPrice.is_none(){
-            let mut ids = active_transaction.buyOrderIds.split(',');
-            let id = ids.last().unwrap();
-            trace!("Last Buy Order {}",id);
-            if id ==""{
-                return Err(CommandError::ParsingDataError("No last buy order".into()))
+        debug!("Executing Cancel Command");
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let whole_symbol = get_option::<bool>(&mut interaction.data.options.iter(), "whole_symbol").unwrap_or(false);
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
 
+        let done = if whole_symbol {
+            //the command itself is open to traders so the admin role is checked here
+            let is_admin = match (config.get::<i64>("roles", "admin")?, interaction.guild_id) {
+                (Some(role_id), Some(guild_id)) => interaction.user.has_role(&ctx.http, guild_id, role_id as u64).await?,
+                _ => false,
+            };
+            if !is_admin {
+                return Err(CommandError::IncorrectParameters("Cancelling the whole symbol needs ADMIN ACCESS".into()));
             }
-            let last_order = id.parse::<u64>().unwrap();
-            account.cancel_order(symbol, last_order)?;
-
-        }else{
-            let mut ids = active_transaction.sellOrderIds.split(',');
-            let id = ids.last().unwrap();
-            trace!("Last sell Order {}",id);
-            if id ==""{
-                return Err(CommandError::ParsingDataError("No last sell order".into()))
+            let symbol = match binance.get_transaction()? {
+                Some(transaction) => transaction.symbol,
+                None => binance.default_symbol()?,
+            };
+            binance.blocking(move |b| b.cancel_symbol_orders(&symbol)).await?
+        } else {
+            //only the trader clocked into the account can cancel its orders
+            match binance.is_clocked_in()? {
+                Some(stub) if stub.user_id == interaction.user.id.0 as i64 => {}
+                _ => return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into()))),
             }
-            let last_order = id.parse::<u64>().unwrap();
-            account.cancel_order(symbol, last_order)?;
-
-        }
-
-        interaction.edit_original_interaction_response(&ctx.http, |i| i.content("Done")).await?;
-        
+            let working = binance.blocking(|b| b.working_orders()).await?;
+            if working.is_empty() {
+                return Err(CommandError::TradingBotError(TradingBotError::ActiveTransaction("No working orders to cancel".into())));
+            }
+            interaction
+                .edit_original_interaction_response(&ctx.http, |response| {
+                    response
+                        .content("Pick the orders to cancel")
+                        .components(|c| {
+                            c.create_action_row(|row| {
+                                row.create_select_menu(|menu| {
+                                    menu.custom_id("orders")
+                                        .placeholder("Working orders")
+                                        .min_values(1)
+                                        .max_values((working.len().min(MAX_MENU_ORDERS) + 1) as u64)
+                                        .options(|options| {
+                                            options.create_option(|option| {
+                                                option.label("All working orders").value("all")
+                                            });
+                                            for order in working.iter().rev().take(MAX_MENU_ORDERS) {
+                                                options.create_option(|option| {
+                                                    option
+                                                        .label(format!(
+                                                            "{} {} {}@{}",
+                                                            order.side,
+                                                            order.orig_qty.unwrap_or_default(),
+                                                            order.symbol,
+                                                            order.price.map_or("market".into(), |price| price.to_string())
+                                                        ))
+                                                        .description(format!("{} {}", order.status, order.client_order_id))
+                                                        .value(order.order_id.unwrap_or_default())
+                                                });
+                                            }
+                                            options
+                                        })
+                                })
+                            })
+                        })
+                })
+                .await?;
+            let message = interaction.get_interaction_response(&ctx).await?;
+            let timeout = match config.get("trading", "cancel_timeout_s")? {
+                Some(int) => int,
+                None => 60,
+            };
+            let Some(picked) = message
+                .await_component_interaction(&ctx)
+                //only the trader who asked picks what gets cancelled
+                .author_id(interaction.user.id)
+                .timeout(Duration::from_secs(timeout as u64))
+                .await
+            else {
+                interaction
+                    .edit_original_interaction_response(&ctx.http, |response| {
+                        response
+                            .content("Cancel Timed Out")
+                            .components(|c| c.set_action_rows(Vec::new()))
+                    })
+                    .await?;
+                return Ok(());
+            };
+            trace!("Picked {:?}", picked.data.values);
+            picked
+                .create_interaction_response(&ctx, |r| r.kind(InteractionResponseType::DeferredUpdateMessage))
+                .await?;
+            let order_ids: Vec<u64> = if picked.data.values.iter().any(|value| value == "all") {
+                working.iter().filter_map(|order| order.order_id).map(|id| id as u64).collect()
+            } else {
+                picked.data.values.iter().filter_map(|value| value.parse().ok()).collect()
+            };
+            binance.blocking(move |b| b.cancel_orders(&order_ids)).await?
+        };
 
-        
-        
-        
+        let mut description = if done.is_empty() {
+            "Nothing was open".to_string()
+        } else {
+            done.join("\n")
+        };
+        truncate(&mut description, 4000);
+        interaction
+            .edit_original_interaction_response(&ctx.http, |response| {
+                response
+                    .content("Done")
+                    .components(|c| c.set_action_rows(Vec::new()))
+                    .embed(|embed| embed.title(format!("Cancelled on {}", binance.name)).description(description))
+            })
+            .await?;
 
         Ok(())
     }
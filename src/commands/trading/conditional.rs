@@ -0,0 +1,218 @@
+use arc_swap::ArcSwapAny;
+use serenity::{client::Context, model::prelude::{command::CommandOptionType, interaction::application_command::CommandDataOption}};
+use std::sync::Arc;
+use tracing::debug;
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::interaction::application_command::ApplicationCommandInteraction,
+};
+
+use crate::{
+    account_manager::AccountManager,
+    commands::{AutoComplete, CommandError, SlashCommand},
+    conditional::{self, Condition},
+    config::Config, exchange::{governor::Priority, OrderSide}, sizing::Sizing, utils::{get_option::get_option, message::truncate}, error::TradingBotError,
+};
+pub(crate) const COMMAND_NAME: &str = "conditional";
+pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND_NAME)
+        .description("orders the bot holds and sends once the price meets a condition")
+        .create_option(|opt|
+            opt.kind(CommandOptionType::SubCommand)
+            .name("add")
+            .description("buy or sell when the price reaches a trigger")
+            .create_sub_option(|sub_opt|
+                sub_opt.name("side")
+                .description("buy or sell once triggered")
+                .kind(CommandOptionType::String)
+                .required(true)
+                .add_string_choice("buy", "BUY")
+                .add_string_choice("sell", "SELL")
+            )
+            .create_sub_option(|sub_opt|
+                sub_opt.name("condition")
+                .description("when the trigger fires")
+                .kind(CommandOptionType::String)
+                .required(true)
+                .add_string_choice("price at or above", "ABOVE")
+                .add_string_choice("price at or below", "BELOW")
+                .add_string_choice("price crosses up through", "CROSS_UP")
+                .add_string_choice("price crosses down through", "CROSS_DOWN")
+            )
+            .create_sub_option(|sub_opt|
+                sub_opt.name("trigger")
+                .description("price the condition is checked against")
+                .kind(CommandOptionType::Number)
+                .required(true)
+            )
+            .create_sub_option(|sub_opt|
+                sub_opt.name("price")
+                .description("limit price to send the order at leave blank for market")
+                .kind(CommandOptionType::Number)
+            )
+            .create_sub_option(|sub_opt|
+                sub_opt.name("quantity")
+                .description("amount in the unit picked leave blank to use the whole balance")
+                .kind(CommandOptionType::Number)
+            )
+            .create_sub_option(|sub_opt|
+                sub_opt.name("unit")
+                .description("what the quantity is in defaults to percent of the free balance")
+                .kind(CommandOptionType::String)
+                .add_string_choice("percent of balance", "percent")
+                .add_string_choice("base asset eg BTC", "base")
+                .add_string_choice("quote asset eg USDT", "quote")
+            )
+            .create_sub_option(|sub_opt|
+                sub_opt.name("symbol")
+                .description("pair to trade eg ETHUSDT leave blank for the default symbol")
+                .kind(CommandOptionType::String)
+                .set_autocomplete(true)
+            )
+            .create_sub_option(|sub_opt|
+                sub_opt.name("account")
+                .description("account to trade on leave blank to use the one you are clocked into")
+                .kind(CommandOptionType::String)
+                .set_autocomplete(true)
+            )
+        )
+        .create_option(|opt|
+            opt.kind(CommandOptionType::SubCommand)
+            .name("list")
+            .description("show the conditional orders waiting on an account")
+            .create_sub_option(|sub_opt|
+                sub_opt.name("account")
+                .description("account to look at leave blank to use the one you are clocked into")
+                .kind(CommandOptionType::String)
+                .set_autocomplete(true)
+            )
+        )
+        .create_option(|opt|
+            opt.kind(CommandOptionType::SubCommand)
+            .name("cancel")
+            .description("cancel your conditional orders")
+            .create_sub_option(|sub_opt|
+                sub_opt.name("id")
+                .description("order to cancel leave blank to cancel all of yours")
+                .kind(CommandOptionType::Integer)
+            )
+            .create_sub_option(|sub_opt|
+                sub_opt.name("account")
+                .description("account to trade on leave blank to use the one you are clocked into")
+                .kind(CommandOptionType::String)
+                .set_autocomplete(true)
+            )
+        )
+}
+
+pub struct ConditionalCommand {
+    accounts: Arc<AccountManager>,
+}
+impl ConditionalCommand {
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        ConditionalCommand { accounts }
+    }
+}
+#[async_trait]
+impl SlashCommand for ConditionalCommand {
+    fn config(&self) -> crate::commands::CommandConfig {
+        crate::commands::CommandConfig {
+            accessLevel: crate::commands::AccessLevels::TRADER,
+            counts_as_activity: true,
+            ..Default::default()
+        }
+    }
+
+    async fn run(
+        &self,
+        interaction: ApplicationCommandInteraction,
+        ctx: Context,
+        _config: Arc<ArcSwapAny<Arc<Config>>>,
+    ) -> Result<(), CommandError> {
+        let Some(sub_command) = interaction.data.options.first() else{
+            return Err(CommandError::ParsingDataError("Did not recieve a sub command".into()));
+        };
+        let options:&Vec<CommandDataOption> = sub_command.options.as_ref();
+        let account = get_option::<String>(&mut options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
+
+        if sub_command.name == "list"{
+            debug!("Running list sub command");
+            let waiting = binance.conditional_orders()?;
+            let mut description = if waiting.is_empty(){
+                "Nothing waiting".to_string()
+            }else{
+                waiting.iter().map(|order| format!("{} expires <t:{}:R>",conditional::describe(order),order.expires_at.timestamp())).collect::<Vec<_>>().join("\n")
+            };
+            truncate(&mut description, 4000);
+            interaction
+                .edit_original_interaction_response(&ctx.http, |response| {
+                    response.embed(|embed| embed.title(format!("Conditional orders on {}", binance.name)).description(description))
+                })
+                .await?;
+            return Ok(());
+        }
+
+        //orders only go out for the trader holding the account
+        match binance.is_clocked_in()?{
+            Some(stub) if stub.user_id == interaction.user.id.0 as i64 => {}
+            _ => return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into())))
+        }
+        let content = if sub_command.name == "add"{
+            debug!("Running add sub command");
+            let side = match get_option::<String>(&mut options.iter(), "side")?.as_str(){
+                "BUY" => OrderSide::Buy,
+                _ => OrderSide::Sell,
+            };
+            let condition = Condition::parse(&get_option::<String>(&mut options.iter(), "condition")?)?;
+            let trigger = get_option::<f64>(&mut options.iter(), "trigger")?;
+            let price = get_option::<f64>(&mut options.iter(), "price").ok();
+            let quantity = get_option::<f64>(&mut options.iter(), "quantity").ok();
+            let unit = get_option::<String>(&mut options.iter(), "unit").ok();
+            let size = Sizing::from_options(quantity, unit.as_deref())?;
+            let symbol = get_option::<String>(&mut options.iter(), "symbol").ok().map(|s| s.to_uppercase());
+            let order = binance.blocking(move |b| b.add_conditional(symbol, side, condition, trigger, price, size)).await?;
+            format!("Set {} until <t:{}:t>", conditional::describe(&order), order.expires_at.timestamp())
+        }else{
+            debug!("Running cancel sub command");
+            let id = get_option::<i32>(&mut options.iter(), "id").ok();
+            let cancelled = binance.cancel_conditionals(Some(interaction.user.id.0 as i64), id)?;
+            match (cancelled.is_empty(), id){
+                (true, Some(id)) => return Err(CommandError::IncorrectParameters(format!("You have no conditional order #{id} waiting"))),
+                (true, None) => "You have no conditional orders waiting".to_string(),
+                (false, _) => cancelled.iter().map(|order| format!("Cancelled {}", conditional::describe(order))).collect::<Vec<_>>().join("\n"),
+            }
+        };
+        interaction.edit_original_interaction_response(&ctx.http, |i| i.content(content)).await?;
+        Ok(())
+    }
+}
+#[async_trait]
+impl AutoComplete for ConditionalCommand {
+    async fn auto_complete(
+        &self,
+        interaction: serenity::model::prelude::interaction::autocomplete::AutocompleteInteraction,
+        ctx: Context,
+        _config: Arc<Config>,
+    ) -> Result<(), CommandError> {
+        let Some(sub_command) = interaction.data.options.first() else{
+            return Err(CommandError::ParsingDataError("Did not recieve a sub command".into()));
+        };
+        let symbol = get_option::<String>(&mut sub_command.options.iter(), "symbol").unwrap_or_default().to_uppercase();
+        let account = get_option::<String>(&mut sub_command.options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
+        let symbols = binance.blocking_at(Priority::Low, |b| b.get_symbols()).await?;
+
+        interaction
+            .create_autocomplete_response(&ctx.http, |a| {
+                symbols.iter().filter(|s| s.starts_with(&symbol)).take(25).for_each(|s| {
+                    a.add_string_choice(s, s);
+                });
+                a
+            })
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use arc_swap::ArcSwapAny;
+use serenity::{client::Context, model::prelude::{component::ButtonStyle, command::CommandOptionType}};
+use std::{sync::Arc, time::Duration};
+use tracing::{debug, trace};
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::interaction::application_command::ApplicationCommandInteraction,
+};
+
+use crate::{
+    account_manager::AccountManager,
+    commands::{CommandError, SlashCommand},
+    config::Config, utils::get_option::get_option, error::TradingBotError,
+};
+pub(crate) const COMMAND_NAME: &'static str = "exit";
+pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND_NAME)
+        .description("protect the position with a take profit and a stop loss one cancels the other")
+        .create_option(|opt|
+            opt.name("take_profit")
+            .description("percent above the buy price to take profit at eg 2 for +2%")
+            .kind(CommandOptionType::Number)
+            .required(true)
+        )
+        .create_option(|opt|
+            opt.name("stop_loss")
+            .description("percent below the buy price to stop out at eg 1 for -1%")
+            .kind(CommandOptionType::Number)
+            .required(true)
+        )
+        .create_option(|opt|
+            opt.name("account")
+            .description("account to trade on leave blank to use the one you are clocked into")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
+}
+
+pub struct ExitCommand {
+    accounts: Arc<AccountManager>,
+}
+impl ExitCommand {
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        ExitCommand { accounts }
+    }
+}
+#[async_trait]
+impl SlashCommand for ExitCommand {
+    fn config(&self) -> crate::commands::CommandConfig {
+        crate::commands::CommandConfig {
+            accessLevel: crate::commands::AccessLevels::TRADER,
+            counts_as_activity: true,
+            ..Default::default()
+        }
+    }
+
+    async fn run(
+        &self,
+        interaction: ApplicationCommandInteraction,
+        ctx: Context,
+        config: Arc<ArcSwapAny<Arc<Config>>>,
+    ) -> Result<(), CommandError> {
+        let config = config.load();
+        debug!("Executing Exit Command");
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
+        match binance.is_clocked_in()?{
+            Some(stub) if stub.user_id == interaction.user.id.0 as i64 => {}
+            _ => return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into())))
+        }
+        let take_profit = get_option::<f64>(&mut interaction.data.options.iter(), "take_profit")?;
+        let stop_loss = get_option::<f64>(&mut interaction.data.options.iter(), "stop_loss")?;
+        let Some(buy_price) = binance.get_transaction()?.and_then(|transaction| transaction.buyAvgPrice) else{
+            return Err(CommandError::TradingBotError(TradingBotError::ActiveTransaction("Must buy before selling".into())))
+        };
+
+        let confirm_order = match config.get("trading", "confirm_orders")? {
+            Some(int) => int,
+            None => true,
+        };
+        if confirm_order{
+            let msg = format!(
+                "Confirm exit from {buy_price} take profit @{:.2} (+{take_profit}%) stop loss @{:.2} (-{stop_loss}%)",
+                buy_price * (1.0 + take_profit / 100.0),
+                buy_price * (1.0 - stop_loss / 100.0)
+            );
+            trace!(msg);
+            interaction
+                .edit_original_interaction_response(&ctx.http, |response| {
+                    response
+                        .content(msg)
+                        .components(|c| {
+                            c.create_action_row(|row| {
+                                row.create_button(|button| {
+                                    button
+                                        .custom_id("confirmed")
+                                        .label("Confirm")
+                                        .style(ButtonStyle::Success)
+                                })
+                                .create_button(|button| {
+                                    button
+                                        .custom_id("canceled")
+                                        .label("Cancel")
+                                        .style(ButtonStyle::Danger)
+                                })
+                            })
+                        })
+                })
+                .await?;
+            let message = interaction.get_interaction_response(&ctx).await.unwrap();
+
+            let timeout = match config.get("trading", "sell_timeout_s")? {
+                Some(int) => int,
+                None => 60,
+            };
+
+            let a = match message
+                .await_component_interaction(&ctx)
+                .timeout(Duration::from_secs(timeout as u64))
+                .await
+            {
+                Some(x) => x,
+                None => {
+                    interaction
+                        .edit_original_interaction_response(&ctx.http, |response| {
+                            response
+                                .content("Order Place Timed Out")
+                                .components(|c| c.set_action_rows(Vec::new()))
+                        })
+                        .await?;
+                    return Ok(());
+                }
+            };
+            trace!("Recieved button response");
+            if a.data.custom_id != "confirmed" {
+                interaction
+                    .edit_original_interaction_response(&ctx.http, |response| {
+                        response
+                            .content("Order Cancelled")
+                            .components(|c| c.set_action_rows(Vec::new()))
+                    })
+                    .await?;
+                return Ok(())
+            }
+        }
+        trace!("sending exit");
+        interaction
+        .edit_original_interaction_response(&ctx.http, |r| {
+        r.content("sending exit")
+        }).await?;
+        let order = binance.blocking(move |b| b.exit(take_profit, stop_loss)).await?;
+        interaction.edit_original_interaction_response(&ctx, |response| {
+                response
+                    .content("Exit Sent")
+                    .embed(|embed| {
+                        embed
+                            .title(format!("Order List {}", order.order_list_id))
+                            .field("Take Profit ID", order.take_profit_id, false)
+                            .field("Stop Loss ID", order.stop_loss_id, false)
+                    })
+                    .components(|c| c.set_action_rows(Vec::new()))
+        })
+        .await?;
+        Ok(())
+    }
+}
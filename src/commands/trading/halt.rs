@@ -0,0 +1,104 @@
+use arc_swap::ArcSwapAny;
+use serenity::{client::Context, model::prelude::command::CommandOptionType};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::interaction::application_command::ApplicationCommandInteraction,
+};
+
+use crate::{
+    account_manager::AccountManager,
+    commands::{CommandError, SlashCommand},
+    config::Config, models, ops::config_ops::{self, Operations}, utils::{get_option::get_option, message::truncate},
+};
+
+pub(crate) const COMMAND_NAME: &'static str = "halt";
+pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND_NAME)
+        .description("Stop all trading, cancels open orders on every account")
+        .create_option(|opt|
+            opt.name("flatten")
+            .description("Also market sell what every active transaction holds")
+            .kind(CommandOptionType::Boolean)
+        )
+        .create_option(|opt|
+            opt.name("resume")
+            .description("Lift the halt and let orders go out again")
+            .kind(CommandOptionType::Boolean)
+        )
+}
+
+pub struct HaltCommand {
+    accounts: Arc<AccountManager>,
+}
+impl HaltCommand {
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        HaltCommand { accounts }
+    }
+}
+#[async_trait]
+impl SlashCommand for HaltCommand {
+    fn config(&self) -> crate::commands::CommandConfig {
+        crate::commands::CommandConfig {
+            accessLevel: crate::commands::AccessLevels::ADMIN,
+            ..Default::default()
+        }
+    }
+
+    async fn run(
+        &self,
+        interaction: ApplicationCommandInteraction,
+        ctx: Context,
+        config: Arc<ArcSwapAny<Arc<Config>>>,
+    ) -> Result<(), CommandError> {
+        debug!("Executing Halt Command");
+        let flatten = get_option::<bool>(&mut interaction.data.options.iter(), "flatten").unwrap_or(false);
+        let resume = get_option::<bool>(&mut interaction.data.options.iter(), "resume").unwrap_or(false);
+        //the flag goes up before anything is cancelled so nothing new slips out in between
+        config_ops::handle(Operations::UpdateConfig(models::UpdateConfig {
+            section: "risk".into(),
+            key: "halted".into(),
+            value: Some((!resume).to_string()),
+        }))?;
+        config.store(Arc::from(Config::load()?));
+        if resume {
+            warn!("Trading resumed by {}", interaction.user.name);
+            interaction
+                .edit_original_interaction_response(&ctx.http, |response| {
+                    response.content("Trading resumed")
+                })
+                .await?;
+            return Ok(());
+        }
+        warn!("Trading halted by {} flatten {flatten}", interaction.user.name);
+        let mut lines = Vec::new();
+        for account in self.accounts.all().await {
+            let name = account.name.clone();
+            match account.blocking(move |b| b.halt(flatten)).await {
+                Ok(done) => lines.extend(done.into_iter().map(|line| format!("{name}: {line}"))),
+                Err(err) => lines.push(format!("{name}: Failed to halt {err}")),
+            }
+        }
+        let mut description = if lines.is_empty() {
+            "Nothing was open".to_string()
+        } else {
+            lines.join("\n")
+        };
+        truncate(&mut description, 4000);
+        interaction
+            .edit_original_interaction_response(&ctx.http, |response| {
+                response.embed(|embed| {
+                    embed
+                        .title("Trading halted")
+                        .description(description)
+                        .footer(|footer| footer.text("/halt resume:true to trade again"))
+                })
+            })
+            .await?;
+        Ok(())
+    }
+}
@@ -3,5 +3,12 @@ pub mod buy;
 pub mod auto_buy;
 pub mod price;
 pub mod sell;
+pub mod exit;
+pub mod trail;
 pub mod orders;
 pub mod cancel;
+pub mod reconcile;
+pub mod halt;
+pub mod rearm;
+pub mod amend;
+pub mod conditional;
@@ -4,7 +4,7 @@ use diesel::{query_dsl::methods::FilterDsl, QueryDsl};
 use serenity::{client::Context, model::prelude::{component::ButtonStyle, command::CommandOptionType}};
 use std::{sync::Arc, thread, time::Duration};
 use tracing::{debug, warn};
-use tokio::{sync::RwLock, time};
+use tokio::time;
 
 use serenity::{
     async_trait, builder::CreateApplicationCommand,
@@ -12,9 +12,9 @@ use serenity::{
 };
 
 use crate::{
-    binance_wrapped::BinanceWrapped,
+    account_manager::AccountManager,
     commands::{CommandError, SlashCommand},
-    config::{Config, ValueType}, utils::get_option::get_option, error::TradingBotError, db::establish_connection, models::DBTransaction,
+    config::{Config, ValueType}, utils::get_option::get_option, error::TradingBotError, db::establish_connection, models::{DBOrder, DBTransaction}, exchange::governor::Priority, transaction_state,
 };
 
 pub(crate) const COMMAND_NAME: &'static str = "orders";
@@ -28,16 +28,21 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
             .max_int_value(25)
             .kind(CommandOptionType::Integer)
         )
-
+        .create_option(|opt|
+            opt.name("account")
+            .description("account to view leave blank to use the one you are clocked into")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
 }
 
 pub struct OrdersCommand {
-    binance: Arc<RwLock<BinanceWrapped>>,
+    accounts: Arc<AccountManager>,
 }
 
 impl OrdersCommand {
-    pub fn new(binance: Arc<RwLock<BinanceWrapped>>) -> Self {
-        OrdersCommand { binance }
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        OrdersCommand { accounts }
     }
 }
 #[async_trait]
@@ -64,7 +69,8 @@ impl SlashCommand for OrdersCommand {
             }
         };
         debug!("Executing Orders Command");
-        let binance = self.binance.read().await;
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
         let orders: Vec<DBTransaction>;
         {
             use crate::schema::transactions::dsl;
@@ -73,50 +79,31 @@ impl SlashCommand for OrdersCommand {
             let mut connection = establish_connection(); 
             orders = dsl::transactions.order(dsl::buyOrderTime.desc()).limit(length.unwrap_or(1).into()).get_results::<DBTransaction>(&mut connection)?;
         }
-        let symbol = &match config.get::<String>("trading", "symbol")?{
-            Some(n) => n,
-            None => "BTCUSDT".into()
-        };
+        let transaction_ids: Vec<i32> = orders.iter().map(|order| order.id).collect();
         let mut interval = time::interval(Duration::from_secs(2));
         let mut dots = true;
         loop{
+        let ids = transaction_ids.clone();
+        //open orders are refreshed on the blocking pool, settled ones come straight from the db
+        let statuses = binance.blocking_at(Priority::Low, move |b| {
+            ids.iter().map(|id| {
+                let orders = b.refresh_orders(*id)?;
+                let (buy_orders, sell_orders): (Vec<DBOrder>, Vec<DBOrder>) = orders.into_iter().partition(|order| order.side == "BUY");
+                Ok((buy_orders, sell_orders))
+            }).collect::<Result<Vec<_>, TradingBotError>>()
+        }).await;
+        let statuses = match statuses {
+            Ok(statuses) => statuses,
+            //keep the last render up until there is weight to spare
+            Err(TradingBotError::RateLimited(_)) => {
+                interval.tick().await;
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let histories = transaction_ids.iter().map(|id| transaction_state::events(*id)).collect::<Result<Vec<_>, TradingBotError>>()?;
         interaction.edit_original_interaction_response(&ctx.http, |i| {
-            for (n,order) in orders.iter().enumerate(){
-                
-                let mut buy_orders = Vec::new();
-                let mut sell_orders = Vec::new();
-
-                if let Some(account) = binance.account.as_ref(){
-                    let mut buy_order_ids = order.buyOrderIds.split(',');
-                    while let Some(order_str) = buy_order_ids.next(){
-                        if order_str.is_empty(){
-                            continue;
-                        }
-                        let order_id = order_str.parse::<u64>().unwrap();
-                        if let Ok(order) = account.order_status(symbol.clone(), order_id){
-                            let ct = order.cummulative_quote_qty.parse::<f64>().unwrap();
-                            let eq = order.executed_qty.parse::<f64>().unwrap();
-                            let price = ct/eq;
-                            let quantity = eq;
-                            buy_orders.push((price,order));
-                        }
-                        
-                    }
-                    let mut sell_order_ids = order.sellOrderIds.split(',');
-                    while let Some(order_str) = sell_order_ids.next(){
-                        if order_str.is_empty(){
-                            continue;
-                        }
-                        let order_id = order_str.parse::<u64>().unwrap();
-                        if let Ok(order) = account.order_status(symbol.clone(), order_id){
-                            let ct = order.cummulative_quote_qty.parse::<f64>().unwrap();
-                            let eq = order.executed_qty.parse::<f64>().unwrap();
-                            let price = ct/eq;
-                            let quantity = eq;
-                            sell_orders.push((price,order));
-                        }
-                    }
-                }
+            for (n,((order,(buy_orders,sell_orders)),history)) in orders.iter().zip(statuses.iter()).zip(histories.iter()).enumerate(){
                 if dots{
                     i.content("Pulling data..");
                 }else{
@@ -127,16 +114,16 @@ impl SlashCommand for OrdersCommand {
                 i.embed(|e| 
                 {
 
-                    e.title(format!("#{} BUY",n+1));
+                    e.title(format!("#{} BUY {}",n+1,order.symbol));
+                    if let (Some(held),Some(avg_cost)) = (order.positionQty,order.buyAvgPrice){
+                        e.field("Position",format!("{held} at {avg_cost:.5} avg cost"),false);
+                    }
                     
-                    for (price,order) in buy_orders.iter(){
-                        let cq = order.cummulative_quote_qty.parse::<f32>().unwrap();
-                        let oq = order.orig_qty.parse::<f32>().unwrap();
-                        let eq = order.executed_qty.parse::<f32>().unwrap();
-                        e.field("TPrice", if order.price == 0.0{"market".into()}else{order.price.to_string()}, false);
+                    for order in buy_orders.iter(){
+                        e.field("TPrice", target_price(order), false);
                         
-                        e.field("AvgPrice",price,true)
-                        .field("Percentage Done",(eq/oq)*100.0,false)
+                        e.field("AvgPrice",order.cummulative_quote_qty/order.executed_qty,true)
+                        .field("Percentage Done",percent_done(order),false)
                         .field("Status",order.status.clone(),false);
                     }
 
@@ -144,19 +131,30 @@ impl SlashCommand for OrdersCommand {
                 }).embed(|e| 
                     {
     
-                        e.title(format!("#{} Sell",n+1));
+                        e.title(format!("#{} Sell {}",n+1,order.symbol));
+                        if let Some(event) = history.last(){
+                            e.field("State",event.to_state.clone(),false);
+                        }
+                        if let Some(pnl) = order.realizedPnl{
+                            e.field("Realized PnL",format!("{pnl:.2} after {:.2} commission",order.commission.unwrap_or(0.0)),false);
+                        }
                         
-                        for (price,order) in sell_orders.iter(){
-                            let cq = order.cummulative_quote_qty.parse::<f32>().unwrap();
-                            let oq = order.orig_qty.parse::<f32>().unwrap();
-                            let eq = order.executed_qty.parse::<f32>().unwrap();
-                            
-                            e.field("TPrice", if order.price == 0.0{"market".into()}else{order.price.to_string()}, false);
+                        for order in sell_orders.iter(){
+                            e.field("TPrice", target_price(order), false);
                         
                             
-                            e.field("AvgPrice",price,false)
-                            .field("Percentage Done",(eq/oq)*100.0,false)
+                            e.field("AvgPrice",order.cummulative_quote_qty/order.executed_qty,false)
+                            .field("Percentage Done",percent_done(order),false)
                             .field("Status",order.status.clone(),false);
+                            if let Some(pnl) = order.realized_pnl{
+                                e.field("PnL",format!("{pnl:.2}"),true);
+                            }
+                        }
+                        let recent: Vec<String> = history.iter().rev().take(5).rev().map(|event| {
+                            format!("{} {} -> {} {}",event.created_at.format("%m/%d %H:%M"),event.from_state.as_deref().unwrap_or("NEW"),event.to_state,event.reason)
+                        }).collect();
+                        if !recent.is_empty(){
+                            e.field("History",recent.join("\n"),false);
                         }
     
                         return e;
@@ -173,3 +171,19 @@ impl SlashCommand for OrdersCommand {
         Ok(())
     }
 }
+
+fn target_price(order: &DBOrder) -> String {
+    match order.price {
+        Some(price) => price.to_string(),
+        None => "market".into(),
+    }
+}
+
+//Market buys are sized in the quote asset so they have no base quantity to measure against
+fn percent_done(order: &DBOrder) -> f64 {
+    match (order.orig_qty, order.quote_qty) {
+        (Some(qty), _) => order.executed_qty / qty * 100.0,
+        (None, Some(quote)) => order.cummulative_quote_qty / quote * 100.0,
+        (None, None) => 0.0,
+    }
+}
@@ -1,5 +1,5 @@
 use arc_swap::{ArcSwap, ArcSwapAny, Guard};
-use binance::{account::Account, market::Market, model::SymbolPrice};
+use binance::account::Account;
 use plotters::{
     backend::PixelFormat,
     prelude::{BitMapBackend, ChartBuilder, DrawingArea, IntoDrawingArea, PathElement},
@@ -10,11 +10,11 @@ use serenity::{
     builder::CreateComponents,
     client::Context,
     futures::StreamExt,
-    model::prelude::{component::{ButtonStyle, InputTextStyle}, AttachmentId, AttachmentType, EmbedImage, Message, interaction::InteractionResponseType},
+    model::prelude::{command::CommandOptionType, component::{ButtonStyle, InputTextStyle}, AttachmentId, AttachmentType, EmbedImage, Message, interaction::InteractionResponseType},
     FutureExt,
 };
 use std::{borrow::Cow, future::IntoFuture, path::Path, sync::Arc, task::Poll, time::Duration};
-use tokio::{fs::File,sync::RwLock, pin, select, time};
+use tokio::{fs::File, pin, select, time};
 use tracing::{debug, instrument, warn};
 
 use serenity::{
@@ -23,9 +23,10 @@ use serenity::{
 };
 
 use crate::{
-    binance_wrapped::BinanceWrapped,
+    account_manager::AccountManager,
+    circuit_breaker,
     commands::{CommandError, SlashCommand},
-    config::{Config, ValueType}, error::TradingBotError,
+    config::{Config, ValueType}, error::TradingBotError, sizing::Sizing, utils::get_option::get_option, exchange::{governor::Priority, OrderOptions}, transaction_state::TransactionState,
 };
 
 pub(crate) const COMMAND_NAME: &'static str = "price";
@@ -33,16 +34,34 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
     command
         .name(COMMAND_NAME)
         .description("view real time price of btc")
+        .create_option(|opt|
+            opt.name("quantity")
+            .description("amount the buttons trade in the unit picked leave blank to trade the whole balance")
+            .kind(CommandOptionType::Number)
+        )
+        .create_option(|opt|
+            opt.name("unit")
+            .description("what the quantity is in defaults to percent of the free balance")
+            .kind(CommandOptionType::String)
+            .add_string_choice("percent of balance", "percent")
+            .add_string_choice("base asset eg BTC", "base")
+            .add_string_choice("quote asset eg USDT", "quote")
+        )
+        .create_option(|opt|
+            opt.name("account")
+            .description("account to trade on leave blank to use the one you are clocked into")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
 }
 
 pub struct PriceCommand {
-    binance: Arc<RwLock<BinanceWrapped>>,
-    market: Market,
+    accounts: Arc<AccountManager>,
 }
 
 impl PriceCommand {
-    pub fn new(binance: Arc<RwLock<BinanceWrapped>>, market: Market) -> Self {
-        PriceCommand { binance, market }
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        PriceCommand { accounts }
     }
 }
 
@@ -64,7 +83,11 @@ impl SlashCommand for PriceCommand {
     ) -> Result<(), CommandError> {
         let config = config.load();
         debug!("Executiuting Price Command");
-        let binance = self.binance.read().await;
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
+        let quantity = get_option::<f64>(&mut interaction.data.options.iter(), "quantity").ok();
+        let unit = get_option::<String>(&mut interaction.data.options.iter(), "unit").ok();
+        let size = Sizing::from_options(quantity, unit.as_deref())?;
         let mut msg = interaction
             .get_interaction_response(&ctx.http)
             .await
@@ -75,19 +98,21 @@ impl SlashCommand for PriceCommand {
 
         let mut interval = time::interval(Duration::from_millis(1000));
         let mut prices = vec![];
-        let market_orders_allowed = match config.get("trading", "market_orders")? {
-            Some(int) => int,
-            None => true,
-        };
+        //a tripped circuit breaker turns market orders off too
+        let market_orders_allowed = circuit_breaker::market_orders_allowed(&config)?;
         let len = match config.get("trading", "price_command_price_len")? {
             Some(int) => int,
             None => 60,
         };
-        let symbol = match config.get::<String>("trading", "symbol")? {
-            Some(symbol) => symbol,
-            None => "BTCUSDT".into(),
+        let symbol = match binance.get_transaction()? {
+            Some(transaction) => transaction.symbol,
+            None => binance.default_symbol()?,
         };
-        let mut price = self.market.get_price(&symbol)?;
+        //the shared stream keeps the history so the chart starts full
+        let feed = binance.price_feed(&symbol);
+        let mut updates = feed.as_ref().map(|feed| feed.subscribe());
+        let price_symbol = symbol.clone();
+        let mut price = binance.blocking(move |b| b.price(&price_symbol)).await?;
         let mut content_msg = String::new();
         loop {
             let transaction = binance.get_transaction()?;
@@ -122,8 +147,9 @@ impl SlashCommand for PriceCommand {
                         a.create_interaction_response(&ctx, |r| {
                             r.kind(InteractionResponseType::DeferredUpdateMessage)
                         }).await?;
-                        binance.buy(Some(price.price as f32), None)?;
-                        content_msg=format!("Bought @${}",price.price);
+                        let buy_symbol = symbol.clone();
+                        let order = binance.blocking(move |b| b.buy(Some(buy_symbol), Some(price as f32), size, OrderOptions::default())).await?;
+                        content_msg=format!("Buying {} @${}",order.orig_qty,price);
                         a.edit_original_interaction_response(&ctx, |a| {
                             a.content(&content_msg)
                         })
@@ -144,8 +170,9 @@ impl SlashCommand for PriceCommand {
                         a.create_interaction_response(&ctx, |r| {
                             r.kind(InteractionResponseType::DeferredUpdateMessage)
                         }).await?;
-                        binance.buy(None, None)?;
-                        content_msg="Buying @Market".into();
+                        let buy_symbol = symbol.clone();
+                        let order = binance.blocking(move |b| b.buy(Some(buy_symbol), None, size, OrderOptions::default())).await?;
+                        content_msg=format!("Buying {} @Market",order.orig_qty);
                         a.edit_original_interaction_response(&ctx, |a| {
                             a.content(&content_msg)
 
@@ -165,8 +192,8 @@ impl SlashCommand for PriceCommand {
                         a.create_interaction_response(&ctx, |r| {
                             r.kind(InteractionResponseType::DeferredUpdateMessage)
                         }).await?;
-                        binance.sell(None, None)?;
-                        content_msg="Selling @Market".into();
+                        let order = binance.blocking(move |b| b.sell(None, size, OrderOptions::default())).await?;
+                        content_msg=format!("Selling {} @Market",order.orig_qty);
                         a.edit_original_interaction_response(&ctx, |a| {
                             a.content(&content_msg)
                         })
@@ -184,59 +211,92 @@ impl SlashCommand for PriceCommand {
                         a.create_interaction_response(&ctx, |r| {
                             r.kind(InteractionResponseType::DeferredUpdateMessage)
                         }).await?;
-                        binance.sell(Some(price.price as f32), None)?;
-                        content_msg=format!("Selling @${}",price.price);
+                        let order = binance.blocking(move |b| b.sell(Some(price as f32), size, OrderOptions::default())).await?;
+                        content_msg=format!("Selling {} @${}",order.orig_qty,price);
                         a.edit_original_interaction_response(&ctx, |a| {
                             a.content(&content_msg)
                         })
                         .await?;
                        
                     }
+                    "amend" => {
+                        if let Some(stub) = binance.is_clocked_in()?{
+                            if stub.user_id != interaction.user.id.0 as i64{
+                                return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into())))
+                            }
+                        }else{
+                            return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into())))
+                        }
+                        a.create_interaction_response(&ctx, |r| {
+                            r.kind(InteractionResponseType::DeferredUpdateMessage)
+                        }).await?;
+                        let (cancelled,result) = binance.blocking(move |b| b.amend(price as f32)).await?;
+                        content_msg = match result{
+                            Ok(order) => format!("Moved {} {} from ${} to ${}",order.side,order.orig_qty,cancelled.price.unwrap_or_default(),order.price),
+                            Err(err) => format!("Cancelled {} {} at ${} but the replacement did not go out {err}",cancelled.side,cancelled.orig_qty.unwrap_or_default() - cancelled.executed_qty,cancelled.price.unwrap_or_default()),
+                        };
+                        a.edit_original_interaction_response(&ctx, |a| {
+                            a.content(&content_msg)
+                        })
+                        .await?;
+                    }
                     _ => {}
                 }
             }
-            price = self.market.get_price(&symbol).unwrap();
+            let price_symbol = symbol.clone();
+            //chart refreshes are the first thing dropped when request weight runs low
+            price = match binance.blocking_at(Priority::Low, move |b| b.price(&price_symbol)).await {
+                Ok(price) => price,
+                Err(TradingBotError::RateLimited(_)) => price,
+                Err(err) => return Err(err.into()),
+            };
 
-            if transaction.as_ref().is_some() && transaction.as_ref().unwrap().sellReady && transaction.as_ref().unwrap().sellAvgPrice.is_none(){
+            let state = transaction.as_ref().map(TransactionState::of).transpose()?;
+            //a long transaction can be added to as well as sold from
+            let can_buy = state.map_or(true, |state| state.can_buy());
+            let can_sell = state.map_or(false, |state| state.can_sell());
+            //a working limit order can be moved to the current price
+            let can_amend = matches!(state, Some(TransactionState::BuyPending | TransactionState::SellPending));
+            if can_buy || can_sell || can_amend{
                 let mut c = CreateComponents::default();
-                c.create_action_row(|r|
-                    
+                c.create_action_row(|r| {
+                    if can_buy{
+                        r.create_button(|b|
+                            b.custom_id("buy")
+                            .label(format!("Buy@${:.5}",price))
+                            .style(ButtonStyle::Success)
+                        ).create_button(|b|
+                            b.custom_id("market_buy")
+                            .label("Market Buy")
+                            .style(ButtonStyle::Success)
+                            .disabled(!market_orders_allowed)
+                        );
+                    }
+                    if can_sell{
+                        r.create_button(|b|
+                            b.custom_id("sell")
+                            .label(format!("Sell@${:.5}",price))
+                            .style(ButtonStyle::Success)
+                        ).create_button(|b|
+                            b.custom_id("market_sell")
+                            .label("Market Sell")
+                            .style(ButtonStyle::Success)
+                            .disabled(!market_orders_allowed)
+                        );
+                    }
+                    if can_amend{
+                        r.create_button(|b|
+                            b.custom_id("amend")
+                            .label(format!("Move order to ${:.5}",price))
+                            .style(ButtonStyle::Primary)
+                        );
+                    }
                     r.create_button(|b|
-                        b.custom_id("sell")
-                        .label(format!("Sell@${:.5}",price.price))
-                        .style(ButtonStyle::Success)
-                    ).create_button(|b|
-                        b.custom_id("market_sell")
-                        .label("Market Sell")
-                        .style(ButtonStyle::Success)
-                        .disabled(!market_orders_allowed)
-                    )
-                    .create_button(|b|
                         b.custom_id("cancel")
                         .label("Cancel")
                         .style(ButtonStyle::Danger)
-                    )           
-                );
-                components = Some(c)
-            }else if transaction.is_none() || (transaction.as_ref().unwrap().buyReady && transaction.as_ref().unwrap().buyAvgPrice.is_none()){
-                let mut c = CreateComponents::default();
-                c.create_action_row(|r|
-                r.create_button(|b|
-                    b.custom_id("buy")
-                    .label(format!("Buy@${:.5}",price.price))
-                    .style(ButtonStyle::Success)
-                ).create_button(|b|
-                    b.custom_id("market_buy")
-                    .label("Market Buy")
-                    .style(ButtonStyle::Success)
-                    .disabled(!market_orders_allowed)
-                )
-                .create_button(|b|
-                    b.custom_id("cancel")
-                    .label("Cancel")
-                    .style(ButtonStyle::Danger)
-                )
-                );
+                    )
+                });
                 components = Some(c)
             }else{
                 components = None;
@@ -245,11 +305,16 @@ impl SlashCommand for PriceCommand {
                
             
             //MAKE SYMBOL A CONFIG
-            prices.push(price.price as f32);
-            if prices.len() > len as usize {
-                prices.remove(0);
+            match &feed {
+                Some(feed) => prices = feed.history(len as usize).iter().map(|p| *p as f32).collect(),
+                None => {
+                    prices.push(price as f32);
+                    if prices.len() > len as usize {
+                        prices.remove(0);
+                    }
+                }
             }
-            draw_canvas(&prices).unwrap();
+            draw_canvas(&symbol, &prices).unwrap();
 
             msg.edit(&ctx.http, |m| {
                 if let Some(at) = id {
@@ -263,6 +328,9 @@ impl SlashCommand for PriceCommand {
                             e.field("Bought At Price", format!("${:.5}",price), false);
 
                         }
+                        if let Some(held) = transaction.positionQty{
+                            e.field("Holding", held.to_string(), false);
+                        }
                     }
                     e
                 })
@@ -275,20 +343,24 @@ impl SlashCommand for PriceCommand {
             .unwrap();
 
             interval.tick().await;
+            //no point redrawing until the stream has moved
+            if let Some(updates) = updates.as_mut() {
+                let _ = time::timeout(Duration::from_secs(5), updates.changed()).await;
+            }
         }
 
         Ok(())
     }
 }
 
-fn draw_canvas(prices: &Vec<f32>) -> Result<(), Box<dyn std::error::Error>> {
+fn draw_canvas(symbol: &str, prices: &Vec<f32>) -> Result<(), Box<dyn std::error::Error>> {
     let (x, y) = (720, 480);
     let root = BitMapBackend::new("data/image.png", (x, y)).into_drawing_area();
     root.fill(&WHITE)?;
     
     let mut chart = ChartBuilder::on(&root)
         .caption(
-            "BTCUSDT",//TODO CHANGE THIS BASED ON SYMBOL
+            symbol,
             ("sans-serif", 50).into_font().color(&BLACK),
         )
         .margin(5)
@@ -0,0 +1,59 @@
+use arc_swap::ArcSwapAny;
+use serenity::client::Context;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::interaction::application_command::ApplicationCommandInteraction,
+};
+
+use crate::{
+    circuit_breaker,
+    commands::{CommandError, SlashCommand},
+    config::Config,
+};
+
+pub(crate) const COMMAND_NAME: &'static str = "rearm";
+pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND_NAME)
+        .description("Re-arm the circuit breaker so new entries and market orders are allowed again")
+}
+
+pub struct RearmCommand;
+impl RearmCommand {
+    pub fn new() -> Self {
+        RearmCommand
+    }
+}
+#[async_trait]
+impl SlashCommand for RearmCommand {
+    fn config(&self) -> crate::commands::CommandConfig {
+        crate::commands::CommandConfig {
+            accessLevel: crate::commands::AccessLevels::ADMIN,
+            ..Default::default()
+        }
+    }
+
+    async fn run(
+        &self,
+        interaction: ApplicationCommandInteraction,
+        ctx: Context,
+        config: Arc<ArcSwapAny<Arc<Config>>>,
+    ) -> Result<(), CommandError> {
+        debug!("Executing Rearm Command");
+        let reason = circuit_breaker::rearm(&config)?;
+        warn!("Circuit breaker re-armed by {}", interaction.user.name);
+        interaction
+            .edit_original_interaction_response(&ctx.http, |response| {
+                response.content(match reason {
+                    Some(reason) => format!("Circuit breaker re-armed, it had tripped on {reason}"),
+                    None => "Circuit breaker was already armed".into(),
+                })
+            })
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use arc_swap::ArcSwapAny;
+use serenity::{client::Context, model::prelude::command::CommandOptionType};
+use std::sync::Arc;
+use tracing::debug;
+
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::interaction::application_command::ApplicationCommandInteraction,
+};
+
+use crate::{
+    account_manager::AccountManager,
+    commands::{CommandError, SlashCommand},
+    config::Config, interval_handler, utils::get_option::get_option,
+};
+
+pub(crate) const COMMAND_NAME: &'static str = "reconcile";
+pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND_NAME)
+        .description("Check the account's open orders and trades against what the bot has recorded")
+        .create_option(|opt|
+            opt.name("account")
+            .description("account to reconcile leave blank to use the one you are clocked into")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
+}
+
+pub struct ReconcileCommand {
+    accounts: Arc<AccountManager>,
+}
+impl ReconcileCommand {
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        ReconcileCommand { accounts }
+    }
+}
+#[async_trait]
+impl SlashCommand for ReconcileCommand {
+    fn config(&self) -> crate::commands::CommandConfig {
+        crate::commands::CommandConfig {
+            accessLevel: crate::commands::AccessLevels::ADMIN,
+            ..Default::default()
+        }
+    }
+
+    async fn run(
+        &self,
+        interaction: ApplicationCommandInteraction,
+        ctx: Context,
+        config: Arc<ArcSwapAny<Arc<Config>>>,
+    ) -> Result<(), CommandError> {
+        debug!("Executing Reconcile Command");
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
+        let found = interval_handler::reconcile(&ctx, &config, &binance).await?;
+        interaction
+            .edit_original_interaction_response(&ctx.http, |response| {
+                response.embed(|embed| {
+                    embed
+                        .title(format!("Reconciled {}", binance.name))
+                        .description(if found.is_empty() {
+                            "The exchange and the bot agree".into()
+                        } else {
+                            interval_handler::discrepancy_lines(&found)
+                        })
+                })
+            })
+            .await?;
+        Ok(())
+    }
+}
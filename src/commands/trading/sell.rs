@@ -4,7 +4,6 @@ use diesel::IntoSql;
 use serenity::{client::Context, model::prelude::{component::ButtonStyle, command::CommandOptionType}};
 use std::{sync::Arc, time::Duration};
 use tracing::{debug, warn, trace};
-use tokio::sync::RwLock;
 use serenity::{
     async_trait,
     builder::CreateApplicationCommand,
@@ -14,9 +13,11 @@ use serenity::{
 };
 
 use crate::{
-    binance_wrapped::BinanceWrapped,
+    account_manager::AccountManager,
+    circuit_breaker,
     commands::{CommandError, SlashCommand},
-    config::{Config, ValueType}, utils::get_option::get_option, error::TradingBotError,
+    config::{Config, ValueType}, sizing::Sizing, utils::get_option::get_option, error::TradingBotError,
+    exchange::{OrderOptions, TimeInForce},
 };
 pub(crate) const COMMAND_NAME: &'static str = "sell";
 pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
@@ -31,17 +32,44 @@ pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApp
         )
         .create_option(|opt|
             opt.name("quantity")
-            .description("account percentage to sell with leave blank to sell with whole account 0-1")
+            .description("amount to sell in the unit picked leave blank to sell with the whole balance")
             .kind(CommandOptionType::Number)
         )
+        .create_option(|opt|
+            opt.name("unit")
+            .description("what the quantity is in defaults to percent of the free base balance")
+            .kind(CommandOptionType::String)
+            .add_string_choice("percent of balance", "percent")
+            .add_string_choice("base asset eg BTC", "base")
+            .add_string_choice("quote asset eg USDT", "quote")
+        )
+        .create_option(|opt|
+            opt.name("time_in_force")
+            .description("how long a limit order stays on the book defaults to GTC")
+            .kind(CommandOptionType::String)
+            .add_string_choice("GTC good till cancelled", "GTC")
+            .add_string_choice("IOC immediate or cancel", "IOC")
+            .add_string_choice("FOK fill or kill", "FOK")
+        )
+        .create_option(|opt|
+            opt.name("post_only")
+            .description("only place the limit order if it would rest on the book as a maker")
+            .kind(CommandOptionType::Boolean)
+        )
+        .create_option(|opt|
+            opt.name("account")
+            .description("account to trade on leave blank to use the one you are clocked into")
+            .kind(CommandOptionType::String)
+            .set_autocomplete(true)
+        )
 }
 
 pub struct SellCommand {
-    binance: Arc<RwLock<BinanceWrapped>>,
+    accounts: Arc<AccountManager>,
 }
 impl SellCommand {
-    pub fn new(binance: Arc<RwLock<BinanceWrapped>>) -> Self {
-        SellCommand { binance }
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        SellCommand { accounts }
     }
 }
 #[async_trait]
@@ -62,8 +90,8 @@ impl SlashCommand for SellCommand {
     ) -> Result<(), CommandError> {
         let config = config.load();
         debug!("Executing Sell Command");
-        let binance = self.binance.read().await;
-        trace!("Locked Binance Account");
+        let account = get_option::<String>(&mut interaction.data.options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
         if let Some(stub) = binance.is_clocked_in()?{
             if stub.user_id != interaction.user.id.0 as i64{
                 return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into())))
@@ -85,10 +113,17 @@ impl SlashCommand for SellCommand {
                 None
             }
         };
-        let market_orders_allowed = match config.get("trading", "market_orders")? {
-            Some(int) => int,
-            None => true,
+        let unit = get_option::<String>(&mut interaction.data.options.iter(), "unit").ok();
+        let size = Sizing::from_options(quantity, unit.as_deref())?;
+        let options = OrderOptions {
+            time_in_force: match get_option::<String>(&mut interaction.data.options.iter(), "time_in_force") {
+                Ok(time_in_force) => TimeInForce::parse(&time_in_force)?,
+                Err(_) => TimeInForce::Gtc,
+            },
+            post_only: get_option::<bool>(&mut interaction.data.options.iter(), "post_only").unwrap_or(false),
         };
+        //a tripped circuit breaker turns market orders off too
+        let market_orders_allowed = circuit_breaker::market_orders_allowed(&config)?;
 
         let confirm_order = match config.get("trading", "confirm_orders")? {
             Some(int) => int,
@@ -106,7 +141,15 @@ impl SlashCommand for SellCommand {
 
         }
         if confirm_order{
-            let msg = format!("Confirm placing order at {}",if price.is_some() {price.unwrap().to_string()}else{"Market Price".into()});
+            let Some(transaction) = binance.get_transaction()? else {
+                return Err(CommandError::TradingBotError(TradingBotError::ActiveTransaction("Must buy before selling".into())))
+            };
+            let market_price = {
+                let symbol = transaction.symbol.clone();
+                format!("Market Price (~{})",binance.blocking(move |b| b.price(&symbol)).await?)
+            };
+            let sized = binance.blocking(move |b| b.size_sell(&transaction, price, size.unwrap_or(Sizing::ALL), options)).await?;
+            let msg = format!("Confirm placing {sized} at {}",if price.is_some() {price.unwrap().to_string()}else{market_price});
             trace!(msg);
             interaction
                 .edit_original_interaction_response(&ctx.http, |response| {
@@ -172,7 +215,7 @@ impl SlashCommand for SellCommand {
         .edit_original_interaction_response(&ctx.http, |r| {
         r.content("sending sell")
         }).await?;
-        let order = binance.sell(price, quantity)?;//TODO ADD QUANTITY PARAM
+        let order = binance.blocking(move |b| b.sell(price, size, options)).await?;
         interaction.edit_original_interaction_response(&ctx, |response| {
                 response
                     .content("Order Sent")
@@ -0,0 +1,108 @@
+use arc_swap::ArcSwapAny;
+use serenity::{client::Context, model::prelude::{command::CommandOptionType, interaction::application_command::CommandDataOption}};
+use std::sync::Arc;
+use tracing::debug;
+use serenity::{
+    async_trait,
+    builder::CreateApplicationCommand,
+    model::prelude::interaction::application_command::ApplicationCommandInteraction,
+};
+
+use crate::{
+    account_manager::AccountManager,
+    commands::{CommandError, SlashCommand},
+    config::Config, utils::get_option::get_option, error::TradingBotError,
+};
+pub(crate) const COMMAND_NAME: &'static str = "trail";
+pub(crate) fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command
+        .name(COMMAND_NAME)
+        .description("trailing stop that market sells the position when price falls back")
+        .create_option(|opt|
+            opt.kind(CommandOptionType::SubCommand)
+            .name("set")
+            .description("start trailing a stop below the current price")
+            .create_sub_option(|sub_opt|
+                sub_opt.name("percent")
+                .description("distance below the highest price as a percent eg 2 for 2%")
+                .kind(CommandOptionType::Number)
+            )
+            .create_sub_option(|sub_opt|
+                sub_opt.name("offset")
+                .description("distance below the highest price in the quote asset eg 150 USDT")
+                .kind(CommandOptionType::Number)
+            )
+            .create_sub_option(|sub_opt|
+                sub_opt.name("account")
+                .description("account to trade on leave blank to use the one you are clocked into")
+                .kind(CommandOptionType::String)
+                .set_autocomplete(true)
+            )
+        )
+        .create_option(|opt|
+            opt.kind(CommandOptionType::SubCommand)
+            .name("clear")
+            .description("stop trailing the position")
+            .create_sub_option(|sub_opt|
+                sub_opt.name("account")
+                .description("account to trade on leave blank to use the one you are clocked into")
+                .kind(CommandOptionType::String)
+                .set_autocomplete(true)
+            )
+        )
+}
+
+pub struct TrailCommand {
+    accounts: Arc<AccountManager>,
+}
+impl TrailCommand {
+    pub fn new(accounts: Arc<AccountManager>) -> Self {
+        TrailCommand { accounts }
+    }
+}
+#[async_trait]
+impl SlashCommand for TrailCommand {
+    fn config(&self) -> crate::commands::CommandConfig {
+        crate::commands::CommandConfig {
+            accessLevel: crate::commands::AccessLevels::TRADER,
+            counts_as_activity: true,
+            ..Default::default()
+        }
+    }
+
+    async fn run(
+        &self,
+        interaction: ApplicationCommandInteraction,
+        ctx: Context,
+        _config: Arc<ArcSwapAny<Arc<Config>>>,
+    ) -> Result<(), CommandError> {
+        let Some(sub_command) = interaction.data.options.get(0) else{
+            return Err(CommandError::ParsingDataError("Did not recieve a sub command".into()));
+        };
+        let options:&Vec<CommandDataOption> = sub_command.options.as_ref();
+        let account = get_option::<String>(&mut options.iter(), "account").ok();
+        let binance = self.accounts.resolve(account, interaction.user.id.0 as i64).await?;
+        match binance.is_clocked_in()?{
+            Some(stub) if stub.user_id == interaction.user.id.0 as i64 => {}
+            _ => return Err(CommandError::TradingBotError(TradingBotError::NotClockedIn("".into())))
+        }
+
+        let content = if sub_command.name == "set"{
+            debug!("Running set sub command");
+            let percent = get_option::<f64>(&mut options.iter(), "percent").ok();
+            let offset = get_option::<f64>(&mut options.iter(), "offset").ok();
+            let level = match (percent, offset){
+                (Some(percent), None) => binance.blocking(move |b| b.set_trailing_stop(percent / 100.0, true)).await?,
+                (None, Some(offset)) => binance.blocking(move |b| b.set_trailing_stop(offset, false)).await?,
+                _ => return Err(CommandError::IncorrectParameters("Give either a percent or an offset".into())),
+            };
+            format!("Trailing stop set @{level}")
+        }else{
+            debug!("Running clear sub command");
+            binance.clear_trailing_stop()?;
+            "Trailing stop cleared".to_string()
+        };
+        interaction.edit_original_interaction_response(&ctx.http, |i| i.content(content)).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use binance::model::Transaction;
+
+use crate::{error::TradingBotError, models::ConditionalOrder};
+
+/// When a conditional order fires against the last price.
+///
+/// Stored as text on `conditional_orders.condition`. Above and below fire whenever the price is
+/// on that side of the trigger, even straight away, while the crossing ones have to see the
+/// price on the other side first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Above,
+    Below,
+    CrossUp,
+    CrossDown,
+}
+
+impl Condition {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Condition::Above => "ABOVE",
+            Condition::Below => "BELOW",
+            Condition::CrossUp => "CROSS_UP",
+            Condition::CrossDown => "CROSS_DOWN",
+        }
+    }
+
+    pub fn parse(condition: &str) -> Result<Self, TradingBotError> {
+        Ok(match condition {
+            "ABOVE" => Condition::Above,
+            "BELOW" => Condition::Below,
+            "CROSS_UP" => Condition::CrossUp,
+            "CROSS_DOWN" => Condition::CrossDown,
+            condition => return Err(TradingBotError::ParsingDataError(format!("Unknown condition {condition}"))),
+        })
+    }
+
+    pub fn of(order: &ConditionalOrder) -> Result<Self, TradingBotError> {
+        Self::parse(&order.condition)
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Condition::Above => "is at or above",
+            Condition::Below => "is at or below",
+            Condition::CrossUp => "crosses up through",
+            Condition::CrossDown => "crosses down through",
+        }
+    }
+
+    //`last` is the price at the previous check, None if it hasn't been checked yet
+    pub fn met(self, trigger: f64, last: Option<f64>, price: f64) -> bool {
+        match self {
+            Condition::Above => price >= trigger,
+            Condition::Below => price <= trigger,
+            Condition::CrossUp => last.is_some_and(|last| last < trigger) && price >= trigger,
+            Condition::CrossDown => last.is_some_and(|last| last > trigger) && price <= trigger,
+        }
+    }
+}
+
+/// What a check did with a conditional order that left ACTIVE
+#[derive(Debug)]
+pub enum Outcome {
+    Expired,
+    Sent(Transaction),
+    Failed(TradingBotError),
+}
+
+//One line summary eg `#3 BUY 50 percent ETHUSDT @market when the price crosses up through 2000`
+pub fn describe(order: &ConditionalOrder) -> String {
+    let size = match (order.quantity, order.unit.as_deref()) {
+        (Some(quantity), unit) => format!("{quantity} {}", unit.unwrap_or("percent")),
+        (None, _) => "all of".into(),
+    };
+    let price = order.limit_price.map_or("market".to_string(), |price| price.to_string());
+    let condition = Condition::of(order).map_or(order.condition.as_str(), |condition| condition.describe());
+    format!(
+        "#{} {} {size} {} @{price} when the price {condition} {}",
+        order.id, order.side, order.symbol, order.trigger_price
+    )
+}
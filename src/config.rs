@@ -20,6 +20,7 @@ pub enum ValueType {
     INT,
     BIGINT,
     BOOL,
+    FLOAT,
 }
 impl ValueType {
     fn to_i32(self) -> i32 {
@@ -28,6 +29,7 @@ impl ValueType {
             ValueType::INT => 1,
             ValueType::BIGINT => 2,
             ValueType::BOOL => 3,
+            ValueType::FLOAT => 4,
         }
     }
     fn from_i32(n: i32) -> Self {
@@ -36,6 +38,7 @@ impl ValueType {
             1 => ValueType::INT,
             2 => ValueType::BIGINT,
             3 => ValueType::BOOL,
+            4 => ValueType::FLOAT,
             _ => {
                 panic!("Tried to parse type got {n}")
             }
@@ -168,17 +171,6 @@ impl Config {
             &mut connection,
         )?;
 
-        insert_config(
-            models::NewConfig {
-                section: "trading",
-                key: "account_name",
-                value_type: ValueType::STRING.to_i32(),
-                value: None,
-                description: "Active Account Name DO NOT SET IN /set_config only /account set command",
-            },
-            &mut connection,
-        )?;
-
         insert_config(
             models::NewConfig {
                 section: "trading",
@@ -232,6 +224,16 @@ impl Config {
             },
             &mut connection,
         )?;
+        insert_config(
+            models::NewConfig {
+                section: "trading",
+                key: "cancel_timeout_s",
+                value_type: ValueType::INT.to_i32(),
+                value: Some(&60.to_string()),
+                description: "The time for the cancel command to stop waiting for orders to be picked",
+            },
+            &mut connection,
+        )?;
         insert_config(
             models::NewConfig {
                 section: "trading",
@@ -337,7 +339,7 @@ impl Config {
                 key: "quote_asset_threshold",
                 value_type: ValueType::STRING.to_i32(),
                 value: Some("10".into()),
-                description: "Amount of money in quote balance for it to count as a full buy",
+                description: "Quote balance a finished buy is expected to leave, only checked to log accounts holding other funds",
             },
             &mut connection,
         )?;
@@ -347,7 +349,7 @@ impl Config {
                 key: "base_asset_threshold",
                 value_type: ValueType::STRING.to_i32(),
                 value: Some("0.0001".into()),
-                description: "Amount of money in base balance for it to count as a full sell",
+                description: "Base balance a finished sell is expected to leave, only checked to warn when it disagrees with the orders",
             },
             &mut connection,
         )?;
@@ -391,6 +393,286 @@ impl Config {
             },
             &mut connection,
         )?;
+        insert_config(
+            models::NewConfig {
+                section: "paper",
+                key: "commission_rate",
+                value_type: ValueType::FLOAT.to_i32(),
+                value: Some("0.001"),
+                description: "Fee charged on simulated account fills as a fraction of the fill",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "paper",
+                key: "starting_asset",
+                value_type: ValueType::STRING.to_i32(),
+                value: Some("USDT"),
+                description: "Asset credited to newly created simulated accounts",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "paper",
+                key: "starting_balance",
+                value_type: ValueType::FLOAT.to_i32(),
+                value: Some("10000"),
+                description: "Amount of the starting asset credited to newly created simulated accounts",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "trading",
+                key: "stop_limit_offset",
+                value_type: ValueType::FLOAT.to_i32(),
+                value: Some("0.002"),
+                description: "How far below the stop price the stop loss of /exit sells as a fraction of the stop",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "trading",
+                key: "reconcile_interval_s",
+                value_type: ValueType::INT.to_i32(),
+                value: Some("60"),
+                description: "Seconds between order polls while the user data stream is connected",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "rate_limit",
+                key: "request_weight_per_minute",
+                value_type: ValueType::INT.to_i32(),
+                value: Some("1200"),
+                description: "Binance request weight the bot may use per minute across every account",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "rate_limit",
+                key: "low_priority_share",
+                value_type: ValueType::FLOAT.to_i32(),
+                value: Some("0.5"),
+                description: "Fraction of the request weight chart and order view refreshes may use before they are dropped",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "rate_limit",
+                key: "normal_priority_share",
+                value_type: ValueType::FLOAT.to_i32(),
+                value: Some("0.8"),
+                description: "Fraction of the request weight order reconciliation may use before waiting for the next minute",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "rate_limit",
+                key: "orders_per_10s",
+                value_type: ValueType::INT.to_i32(),
+                value: Some("50"),
+                description: "Orders an account may send every 10 seconds",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "rate_limit",
+                key: "max_waiting",
+                value_type: ValueType::INT.to_i32(),
+                value: Some("16"),
+                description: "Calls that may wait for request weight at once before more are refused, orders always wait",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "trading",
+                key: "order_retries",
+                value_type: ValueType::INT.to_i32(),
+                value: Some("3"),
+                description: "Times an order is resent after a failure that means it never reached the exchange",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "trading",
+                key: "order_retry_backoff_ms",
+                value_type: ValueType::INT.to_i32(),
+                value: Some("500"),
+                description: "Base delay before resending or looking up a failed order doubling every attempt",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "channels",
+                key: "admin",
+                value_type: ValueType::BIGINT.to_i32(),
+                value: None,
+                description: "The channel to send reconciliation reports to None == OFF",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "reconcile",
+                key: "orphan_orders",
+                value_type: ValueType::STRING.to_i32(),
+                value: Some("flag"),
+                description: "What to do with open orders the bot didn't place adopt, flag or cancel",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "reconcile",
+                key: "trade_lookback_h",
+                value_type: ValueType::INT.to_i32(),
+                value: Some("24"),
+                description: "Hours of account trades checked for ones the bot has no order for",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "risk",
+                key: "max_order_notional",
+                value_type: ValueType::FLOAT.to_i32(),
+                value: None,
+                description: "Most a single order can be worth in the quote asset None == no limit",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "risk",
+                key: "max_position_notional",
+                value_type: ValueType::FLOAT.to_i32(),
+                value: None,
+                description: "Most a transaction can hold in the quote asset at the order price, only stops buys None == no limit",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "risk",
+                key: "max_daily_loss",
+                value_type: ValueType::FLOAT.to_i32(),
+                value: None,
+                description: "Realized loss in the quote asset a trader can take in a UTC day before their buys are refused None == no limit",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "risk",
+                key: "max_orders_per_minute",
+                value_type: ValueType::INT.to_i32(),
+                value: None,
+                description: "Orders a trader can send in a minute None == no limit",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "risk",
+                key: "halted",
+                value_type: ValueType::BOOL.to_i32(),
+                value: Some("false"),
+                description: "Set by /halt, no new orders go out while true",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "breaker",
+                key: "drawdown_pct",
+                value_type: ValueType::FLOAT.to_i32(),
+                value: None,
+                description: "Percent an account's equity can fall from its peak in the window before the breaker trips None == OFF",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "breaker",
+                key: "drawdown_window_min",
+                value_type: ValueType::INT.to_i32(),
+                value: Some("60"),
+                description: "Minutes of equity the drawdown is measured over",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "breaker",
+                key: "price_move_pct",
+                value_type: ValueType::FLOAT.to_i32(),
+                value: None,
+                description: "Percent a traded symbol can move in the window before the breaker trips None == OFF",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "breaker",
+                key: "price_move_secs",
+                value_type: ValueType::INT.to_i32(),
+                value: Some("60"),
+                description: "Seconds of prices the move is measured over",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "breaker",
+                key: "losing_streak",
+                value_type: ValueType::INT.to_i32(),
+                value: None,
+                description: "Losing transactions in a row on an account before the breaker trips None == OFF",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "breaker",
+                key: "tripped",
+                value_type: ValueType::STRING.to_i32(),
+                value: None,
+                description: "Why the breaker tripped, stops new entries and market orders until /rearm None == armed",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "breaker",
+                key: "armed_at",
+                value_type: ValueType::BIGINT.to_i32(),
+                value: None,
+                description: "When the breaker was last re-armed in ms, triggers only look after it",
+            },
+            &mut connection,
+        )?;
+        insert_config(
+            models::NewConfig {
+                section: "conditional",
+                key: "expiry_min",
+                value_type: ValueType::INT.to_i32(),
+                value: Some("60"),
+                description: "Minutes a conditional order waits for its trigger before it expires",
+            },
+            &mut connection,
+        )?;
 
 
 
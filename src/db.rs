@@ -10,3 +10,34 @@ pub fn establish_connection() -> SqliteConnection {
     SqliteConnection::establish(&database_url)
         .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
 }
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{env, fs, sync::{Mutex, MutexGuard}};
+
+    use diesel::connection::SimpleConnection;
+
+    use super::establish_connection;
+
+    //every test shares the process' DATABASE_URL so they take turns
+    static DATABASE: Mutex<()> = Mutex::new(());
+
+    /// Fresh sqlite database with every migration applied in order, held until the guard drops
+    pub(crate) fn database() -> MutexGuard<'static, ()> {
+        let guard = DATABASE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let path = env::temp_dir().join(format!("trading_bot_test_{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        env::set_var("DATABASE_URL", path.to_str().unwrap());
+        let mut migrations: Vec<_> = fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.is_dir())
+            .collect();
+        migrations.sort();
+        let mut connection = establish_connection();
+        for migration in migrations {
+            connection.batch_execute(&fs::read_to_string(migration.join("up.sql")).unwrap()).unwrap();
+        }
+        guard
+    }
+}
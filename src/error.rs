@@ -1,9 +1,11 @@
 #[derive(thiserror::Error, Debug)]
 pub enum TradingBotError {
     #[error("Serenity Error")]
-    SerenityError(#[from] serenity::Error),
+    SerenityError(#[source] Box<serenity::Error>),
     #[error("Binance Error")]
-    BinanceError(#[from] binance::errors::Error),
+    BinanceError(#[source] Box<binance::errors::Error>),
+    #[error("Exchange Error {0}")]
+    ExchangeError(String),
     #[error("Diesel Result Error")]
     DieselError(#[from] diesel::result::Error),
     #[error("Diesel Connection Error")]
@@ -24,4 +26,30 @@ pub enum TradingBotError {
     ConfigError(String),
     #[error("Make sure Binance account is properly setup")]
     BinanceAccountMissing,
+    #[error("Order Rejected {0}")]
+    OrderRejected(String),
+    #[error("Account Not Found {0}")]
+    AccountNotFound(String),
+    #[error("Rate Limited {0}")]
+    RateLimited(String),
+    #[error("Order State Unknown {0}")]
+    OrderUnknown(String),
+    #[error("Invalid Transition {0}")]
+    InvalidTransition(String),
+    #[error("Risk Limit {0}")]
+    RiskLimit(String),
+    #[error("Trading is halted")]
+    Halted,
+}
+
+//Boxed so every Result carrying the error stays small, `?` still takes them unboxed
+impl From<serenity::Error> for TradingBotError {
+    fn from(err: serenity::Error) -> Self {
+        TradingBotError::SerenityError(Box::new(err))
+    }
+}
+impl From<binance::errors::Error> for TradingBotError {
+    fn from(err: binance::errors::Error) -> Self {
+        TradingBotError::BinanceError(Box::new(err))
+    }
 }
@@ -9,13 +9,12 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::task::JoinHandle;
 
-use binance::market::Market;
 use dotenv::dotenv;
 use serenity::async_trait;
 use serenity::model::application::interaction::{Interaction, InteractionResponseType};
 use serenity::model::gateway::Ready;
 use serenity::model::id::GuildId;
-use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::application_command::{ApplicationCommandInteraction, CommandDataOption};
 use serenity::model::prelude::interaction::autocomplete::AutocompleteInteraction;
 use serenity::prelude::*;
 use tokio::time::timeout;
@@ -24,7 +23,7 @@ use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{fmt, EnvFilter};
 
-use crate::binance_wrapped::BinanceWrapped;
+use crate::account_manager::AccountManager;
 use crate::commands::config::account::AccountCommand;
 use crate::commands::config::create_user::CreateUserCommand;
 use crate::commands::config::list_config::ListConfigCommand;
@@ -38,30 +37,45 @@ use crate::commands::trading::auto_buy::AutoBuyCommand;
 use crate::commands::trading::balance::BalanceCommand;
 use crate::commands::trading::buy::BuyCommand;
 use crate::commands::trading::cancel::CancelCommand;
+use crate::commands::trading::conditional::ConditionalCommand;
 use crate::commands::trading::orders::OrdersCommand;
 use crate::commands::trading::price::PriceCommand;
+use crate::commands::trading::reconcile::ReconcileCommand;
+use crate::commands::trading::halt::HaltCommand;
+use crate::commands::trading::rearm::RearmCommand;
+use crate::commands::trading::amend::AmendCommand;
 use crate::commands::trading::sell::SellCommand;
+use crate::commands::trading::exit::ExitCommand;
+use crate::commands::trading::trail::TrailCommand;
 use crate::config::{Config, ValueType};
 use crate::db::establish_connection;
 use crate::utils::message::send_status;
-use crate::{commands, interval_handler};
+use crate::{account_manager, commands, interval_handler};
+
+//Option the user is currently typing in including ones nested in sub commands
+fn find_focused(options: &[CommandDataOption]) -> Option<&CommandDataOption> {
+    options.iter().find_map(|option| {
+        if option.focused {
+            Some(option)
+        } else {
+            find_focused(&option.options)
+        }
+    })
+}
 
 pub struct Handler {
-    binance: Arc<RwLock<BinanceWrapped>>,
+    accounts: Arc<AccountManager>,
     config: Arc<ArcSwap<Config>>,
-    market: Market,
     is_loop_running: AtomicBool,
 }
 impl Handler {
     pub fn new(
-        binance: Arc<RwLock<BinanceWrapped>>,
+        accounts: Arc<AccountManager>,
         config: Arc<ArcSwap<Config>>,
-        market: Market,
     ) -> Self {
         Self {
-            binance,
+            accounts,
             config,
-            market,
             is_loop_running: AtomicBool::new(false),
         }
     }
@@ -73,12 +87,34 @@ impl Handler {
 
         debug!("Recieved Autocomplete for {}", command.data.name);
         
+        //every command shares the same account option so it's completed here
+        if let Some(focused) = find_focused(&command.data.options) {
+            if focused.name == "account" {
+                let partial = focused.value.as_ref().and_then(|v| v.as_str()).unwrap_or_default().to_string();
+                let names = self.accounts.names().await;
+                if let Err(err) = command
+                    .create_autocomplete_response(&ctx.http, |a| {
+                        names.iter().filter(|n| n.contains(&partial)).take(25).for_each(|n| {
+                            a.add_string_choice(n, n);
+                        });
+                        a
+                    })
+                    .await
+                {
+                    error!("error completing account {err:?}");
+                }
+                return;
+            }
+        }
+
         trace!("Finding command for autocomplete");
         let command_runner: Box<dyn commands::AutoComplete> = match command.data.name.as_str() {
             commands::config::set_config::COMMAND_NAME => Box::from(SetConfigCommand::new()),
-            commands::schedule::reserve::COMMAND_NAME => Box::from(ReserveCommand::new()),
-            commands::config::account::COMMAND_NAME => Box::from(AccountCommand::new(self.binance.clone())),
+            commands::schedule::reserve::COMMAND_NAME => Box::from(ReserveCommand::new(self.accounts.clone())),
+            commands::config::account::COMMAND_NAME => Box::from(AccountCommand::new(self.accounts.clone())),
             commands::schedule::summary::COMMAND_NAME => Box::from(SummaryCommand::new()),
+            commands::trading::buy::COMMAND_NAME => Box::from(BuyCommand::new(self.accounts.clone())),
+            commands::trading::conditional::COMMAND_NAME => Box::from(ConditionalCommand::new(self.accounts.clone())),
 
 
             _ => {
@@ -107,43 +143,62 @@ impl Handler {
 
         //TODO MAKE ALL OF THIS DYNAMICALLY GENERATE MACRO ->
         let command_runner: Box<dyn commands::SlashCommand> = match command.data.name.as_str() {
-            commands::config::status::COMMAND_NAME => Box::from(StatusCommand::new(self.binance.clone())),
+            commands::config::status::COMMAND_NAME => Box::from(StatusCommand::new(self.accounts.clone())),
             commands::trading::balance::COMMAND_NAME => {
-                Box::from(BalanceCommand::new(self.binance.clone()))
+                Box::from(BalanceCommand::new(self.accounts.clone()))
             }
             commands::trading::buy::COMMAND_NAME => {
-                Box::from(BuyCommand::new(self.binance.clone()))
+                Box::from(BuyCommand::new(self.accounts.clone()))
             }
             commands::trading::auto_buy::COMMAND_NAME => {
-                Box::from(AutoBuyCommand::new(self.binance.clone()))
+                Box::from(AutoBuyCommand::new(self.accounts.clone()))
             }
             commands::trading::sell::COMMAND_NAME => {
-                Box::from(SellCommand::new(self.binance.clone()))
+                Box::from(SellCommand::new(self.accounts.clone()))
+            }
+            commands::trading::exit::COMMAND_NAME => {
+                Box::from(ExitCommand::new(self.accounts.clone()))
+            }
+            commands::trading::trail::COMMAND_NAME => {
+                Box::from(TrailCommand::new(self.accounts.clone()))
+            }
+            commands::trading::reconcile::COMMAND_NAME => {
+                Box::from(ReconcileCommand::new(self.accounts.clone()))
+            }
+            commands::trading::halt::COMMAND_NAME => {
+                Box::from(HaltCommand::new(self.accounts.clone()))
+            }
+            commands::trading::rearm::COMMAND_NAME => Box::from(RearmCommand::new()),
+            commands::trading::amend::COMMAND_NAME => {
+                Box::from(AmendCommand::new(self.accounts.clone()))
+            }
+            commands::trading::conditional::COMMAND_NAME => {
+                Box::from(ConditionalCommand::new(self.accounts.clone()))
             }
             commands::config::account::COMMAND_NAME => {
-                Box::from(AccountCommand::new(self.binance.clone()))
+                Box::from(AccountCommand::new(self.accounts.clone()))
             } 
             commands::config::create_user::COMMAND_NAME => Box::from(CreateUserCommand::new()),
             commands::config::set_config::COMMAND_NAME => Box::from(SetConfigCommand::new()),
             commands::config::list_config::COMMAND_NAME => Box::from(ListConfigCommand::new()),
-            commands::schedule::reserve::COMMAND_NAME => Box::from(ReserveCommand::new()),
+            commands::schedule::reserve::COMMAND_NAME => Box::from(ReserveCommand::new(self.accounts.clone())),
 
             commands::trading::price::COMMAND_NAME => {
-                Box::from(PriceCommand::new(self.binance.clone(), self.market.clone()))
+                Box::from(PriceCommand::new(self.accounts.clone()))
             }
             commands::schedule::clock::COMMAND_NAME => {
-                Box::from(ClockCommand::new(self.binance.clone()))
+                Box::from(ClockCommand::new(self.accounts.clone()))
             }
             commands::trading::orders::COMMAND_NAME => {
-                Box::from(OrdersCommand::new(self.binance.clone()))
+                Box::from(OrdersCommand::new(self.accounts.clone()))
             }
             commands::trading::cancel::COMMAND_NAME => {
-                Box::from(CancelCommand::new(self.binance.clone()))
+                Box::from(CancelCommand::new(self.accounts.clone()))
             }
             commands::schedule::summary::COMMAND_NAME => {
                 Box::from(SummaryCommand::new())
             }commands::schedule::unlock::COMMAND_NAME => {
-                Box::from(UnlockCommand::new(self.binance.clone()))
+                Box::from(UnlockCommand::new(self.accounts.clone()))
             }
 
 
@@ -260,14 +315,13 @@ impl Handler {
 
         if command_config.counts_as_activity{
             trace!("Logging activity");
-            let acc = self.binance.read().await;
-            let pot_stub = acc.is_clocked_in();
+            let pot_stub = account_manager::locked_account(command.user.id.0 as i64).map(|a| a.and_then(|a| a.active_clock_stub));
             if let Ok(Some(stub)) = pot_stub{
                 use diesel::query_dsl::methods::FilterDsl;
                 use crate::schema::clock_stubs::dsl;
                 use diesel::ExpressionMethods;
                 let mut connection = establish_connection();
-                if let Err(err) = diesel::update(dsl::clock_stubs.filter(dsl::id.eq(stub.id))).set(dsl::last_interaction.eq(Utc::now())).execute(&mut connection){
+                if let Err(err) = diesel::update(dsl::clock_stubs.filter(dsl::id.eq(stub))).set(dsl::last_interaction.eq(Utc::now())).execute(&mut connection){
                     error!("Error updating last interaction {err}");
                 }else{
                     debug!("Successfully set last ineraction");
@@ -334,12 +388,12 @@ impl EventHandler for Handler {
             // We have to clone the Arc, as it gets moved into the new thread.
             let ctx1 = Arc::clone(&ctx);
             let config1 = Arc::clone(&self.config);
-            let binance1 = Arc::clone(&self.binance);
+            let accounts1 = Arc::clone(&self.accounts);
 
             // tokio::spawn creates a new green thread that can run in parallel with the rest of
             // the application.
             tokio::spawn(async move {
-                interval_handler::run(Arc::clone(&ctx1), config1,binance1).await;
+                interval_handler::run(Arc::clone(&ctx1), config1,accounts1).await;
             });
 
             // Now that the loop is running, we set the bool to true
@@ -357,6 +411,13 @@ impl EventHandler for Handler {
                 .create_application_command(|command| commands::config::status::register(command))
                 .create_application_command(|command| commands::trading::balance::register(command))
                 .create_application_command(|command| commands::trading::sell::register(command))
+                .create_application_command(|command| commands::trading::exit::register(command))
+                .create_application_command(|command| commands::trading::trail::register(command))
+                .create_application_command(|command| commands::trading::reconcile::register(command))
+                .create_application_command(|command| commands::trading::halt::register(command))
+                .create_application_command(|command| commands::trading::rearm::register(command))
+                .create_application_command(|command| commands::trading::amend::register(command))
+                .create_application_command(|command| commands::trading::conditional::register(command))
                 .create_application_command(|command| commands::trading::buy::register(command))
                 .create_application_command(|command| {
                     commands::config::create_user::register(command)
@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+
+use binance::{
+    account::Account,
+    api::{Binance, Spot, API},
+    errors::ErrorKind,
+    general::General,
+    market::Market,
+    model::{AccountInformation, Balance, FillInfo, Order, OrderCanceled, Symbol, TradeHistory, Transaction},
+    userstream::UserStream,
+    util::build_signed_request,
+};
+use serde::Deserialize;
+
+use crate::error::TradingBotError;
+
+use super::{oco_leg_ids, AccountTrade, Exchange, OcoOrder, OcoRequest, OrderQuantity, OrderRequest, OrderSide, UserStreamKey};
+
+const TESTNET_ENDPOINT: &str = "https://testnet.binance.vision";
+const TESTNET_WS_ENDPOINT: &str = "wss://testnet.binance.vision/ws";
+
+//the binance crate has the endpoint but no model for the response
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OcoResponse {
+    order_list_id: i64,
+    order_reports: Vec<OcoReport>,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OcoReport {
+    order_id: u64,
+    #[serde(rename = "type")]
+    type_name: String,
+}
+
+//TradeHistory leaves out the order a trade filled
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MyTrade {
+    id: u64,
+    order_id: u64,
+    price: String,
+    qty: String,
+    commission: String,
+    commission_asset: String,
+    time: u64,
+    is_buyer: bool,
+}
+
+pub struct BinanceExchange {
+    account: Account,
+    general: General,
+    market: Market,
+    user_stream: UserStream,
+    ws_endpoint: String,
+}
+impl BinanceExchange {
+    pub fn new(api_key: String, secret: String, is_paper: bool) -> Self {
+        let config = if is_paper {
+            binance::config::Config::default()
+                .set_rest_api_endpoint(TESTNET_ENDPOINT)
+                .set_ws_endpoint(TESTNET_WS_ENDPOINT)
+        } else {
+            binance::config::Config::default()
+        };
+        Self {
+            account: Binance::new_with_config(Some(api_key.clone()), Some(secret.clone()), &config),
+            general: Binance::new_with_config(Some(api_key.clone()), Some(secret), &config),
+            market: Binance::new_with_config(None, None, &config),
+            user_stream: Binance::new_with_config(Some(api_key), None, &config),
+            ws_endpoint: config.ws_endpoint,
+        }
+    }
+}
+
+impl Exchange for BinanceExchange {
+    fn get_account(&self) -> Result<AccountInformation, TradingBotError> {
+        Ok(self.account.get_account()?)
+    }
+
+    fn get_balance(&self, asset: &str) -> Result<Balance, TradingBotError> {
+        Ok(self.account.get_balance(asset)?)
+    }
+
+    fn get_symbol_info(&self, symbol: &str) -> Result<Symbol, TradingBotError> {
+        Ok(self.general.get_symbol_info(symbol)?)
+    }
+
+    fn get_symbols(&self) -> Result<Vec<String>, TradingBotError> {
+        let info = self.general.exchange_info()?;
+        Ok(info
+            .symbols
+            .into_iter()
+            .filter(|s| s.status == "TRADING")
+            .map(|s| s.symbol)
+            .collect())
+    }
+
+    fn get_price(&self, symbol: &str) -> Result<f64, TradingBotError> {
+        Ok(self.market.get_price(symbol)?.price)
+    }
+
+    //built by hand since the crate's helpers can't set a client order id on quote sized orders
+    fn place_order(&self, order: &OrderRequest) -> Result<Transaction, TradingBotError> {
+        let mut parameters: BTreeMap<String, String> = BTreeMap::new();
+        parameters.insert("symbol".into(), order.symbol.clone());
+        parameters.insert("side".into(), order.side.as_str().into());
+        match (order.quantity, order.price) {
+            (OrderQuantity::Base(qty), Some(price)) => {
+                parameters.insert("type".into(), order.type_name().into());
+                parameters.insert("quantity".into(), qty.to_string());
+                parameters.insert("price".into(), price.to_string());
+                //LIMIT_MAKER is refused if it carries a time in force
+                if let Some(time_in_force) = order.time_in_force() {
+                    parameters.insert("timeInForce".into(), time_in_force.into());
+                }
+            }
+            (OrderQuantity::Base(qty), None) => {
+                parameters.insert("type".into(), "MARKET".into());
+                parameters.insert("quantity".into(), qty.to_string());
+            }
+            (OrderQuantity::Quote(qty), None) => {
+                parameters.insert("type".into(), "MARKET".into());
+                parameters.insert("quoteOrderQty".into(), qty.to_string());
+            }
+            (OrderQuantity::Quote(_), Some(_)) => {
+                return Err(TradingBotError::ExchangeError("Limit orders must be sized in the base asset".into()))
+            }
+        }
+        if let Some(client_order_id) = &order.client_order_id {
+            parameters.insert("newClientOrderId".into(), client_order_id.clone());
+        }
+        let request = build_signed_request(parameters, self.account.recv_window)?;
+        Ok(self.account.client.post_signed(API::Spot(Spot::Order), request)?)
+    }
+
+    fn place_oco(&self, order: &OcoRequest) -> Result<OcoOrder, TradingBotError> {
+        let mut parameters: BTreeMap<String, String> = BTreeMap::new();
+        parameters.insert("symbol".into(), order.symbol.clone());
+        parameters.insert("side".into(), OrderSide::Sell.as_str().into());
+        parameters.insert("quantity".into(), order.quantity.to_string());
+        parameters.insert("price".into(), order.take_profit.to_string());
+        parameters.insert("stopPrice".into(), order.stop_price.to_string());
+        parameters.insert("stopLimitPrice".into(), order.stop_limit_price.to_string());
+        parameters.insert("stopLimitTimeInForce".into(), "GTC".into());
+        if let Some(client_order_id) = &order.client_order_id {
+            let (take_profit_id, stop_loss_id) = oco_leg_ids(client_order_id);
+            parameters.insert("listClientOrderId".into(), client_order_id.clone());
+            parameters.insert("limitClientOrderId".into(), take_profit_id);
+            parameters.insert("stopClientOrderId".into(), stop_loss_id);
+        }
+        let request = build_signed_request(parameters, self.account.recv_window)?;
+        let response: OcoResponse = self.account.client.post_signed(API::Spot(Spot::Oco), request)?;
+
+        let leg = |type_name: &str| {
+            response
+                .order_reports
+                .iter()
+                .find(|report| report.type_name == type_name)
+                .map(|report| report.order_id)
+                .ok_or_else(|| TradingBotError::ExchangeError(format!("OCO response is missing the {type_name} leg")))
+        };
+        Ok(OcoOrder {
+            order_list_id: response.order_list_id,
+            take_profit_id: leg("LIMIT_MAKER")?,
+            stop_loss_id: leg("STOP_LOSS_LIMIT")?,
+        })
+    }
+
+    fn order_status(&self, symbol: &str, order_id: u64) -> Result<Order, TradingBotError> {
+        Ok(self.account.order_status(symbol, order_id)?)
+    }
+
+    fn order_by_client_id(&self, symbol: &str, client_order_id: &str) -> Result<Option<Order>, TradingBotError> {
+        let mut parameters: BTreeMap<String, String> = BTreeMap::new();
+        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("origClientOrderId".into(), client_order_id.into());
+        let request = build_signed_request(parameters, self.account.recv_window)?;
+        match self.account.client.get_signed::<Order>(API::Spot(Spot::Order), Some(request)) {
+            Ok(order) => Ok(Some(order)),
+            //-2013 Order does not exist
+            Err(binance::errors::Error(ErrorKind::BinanceError(response), _)) if response.code == -2013 => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn cancel_order(&self, symbol: &str, order_id: u64) -> Result<OrderCanceled, TradingBotError> {
+        Ok(self.account.cancel_order(symbol, order_id)?)
+    }
+
+    fn open_orders(&self, symbol: &str) -> Result<Vec<Order>, TradingBotError> {
+        Ok(self.account.get_open_orders(symbol)?)
+    }
+
+    fn all_open_orders(&self) -> Result<Vec<Order>, TradingBotError> {
+        Ok(self.account.get_all_open_orders()?)
+    }
+
+    fn recent_trades(&self, symbol: &str, limit: u16) -> Result<Vec<AccountTrade>, TradingBotError> {
+        let mut parameters: BTreeMap<String, String> = BTreeMap::new();
+        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("limit".into(), limit.to_string());
+        let request = build_signed_request(parameters, self.account.recv_window)?;
+        let trades = self
+            .account
+            .client
+            .get_signed::<Vec<MyTrade>>(API::Spot(Spot::MyTrades), Some(request))?;
+        let parse = |value: &str| value.parse::<f64>().unwrap_or(0.0);
+        Ok(trades
+            .into_iter()
+            .map(|trade| AccountTrade {
+                order_id: trade.order_id,
+                side: if trade.is_buyer { OrderSide::Buy } else { OrderSide::Sell },
+                fill: FillInfo {
+                    price: parse(&trade.price),
+                    qty: parse(&trade.qty),
+                    commission: parse(&trade.commission),
+                    commission_asset: trade.commission_asset,
+                    trade_id: Some(trade.id),
+                },
+                time: trade.time,
+            })
+            .collect())
+    }
+
+    fn order_fills(&self, symbol: &str, order_id: u64) -> Result<Vec<FillInfo>, TradingBotError> {
+        let mut parameters: BTreeMap<String, String> = BTreeMap::new();
+        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("orderId".into(), order_id.to_string());
+        let request = build_signed_request(parameters, self.account.recv_window)?;
+        let trades = self
+            .account
+            .client
+            .get_signed::<Vec<TradeHistory>>(API::Spot(Spot::MyTrades), Some(request))?;
+        Ok(trades
+            .into_iter()
+            .map(|trade| FillInfo {
+                price: trade.price,
+                qty: trade.qty,
+                commission: trade.commission.parse().unwrap_or(0.0),
+                commission_asset: trade.commission_asset,
+                trade_id: Some(trade.id),
+            })
+            .collect())
+    }
+
+    fn market_stream_endpoint(&self) -> Option<String> {
+        Some(self.ws_endpoint.clone())
+    }
+
+    fn start_user_stream(&self) -> Result<Option<UserStreamKey>, TradingBotError> {
+        let stream = self.user_stream.start()?;
+        Ok(Some(UserStreamKey {
+            listen_key: stream.listen_key,
+            endpoint: self.ws_endpoint.clone(),
+        }))
+    }
+
+    fn keep_alive_user_stream(&self, listen_key: &str) -> Result<(), TradingBotError> {
+        self.user_stream.keep_alive(listen_key)?;
+        Ok(())
+    }
+}
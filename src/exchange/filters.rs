@@ -0,0 +1,347 @@
+use binance::model::{Filters, Symbol};
+
+use crate::error::TradingBotError;
+
+//Binance sends 0 for limits that are switched off
+fn parse_limit(value: &str) -> Option<f64> {
+    match value.parse::<f64>() {
+        Ok(value) if value > 0.0 => Some(value),
+        _ => None,
+    }
+}
+
+//"0.00100000" -> 3
+fn decimals(step: &str) -> usize {
+    match step.split_once('.') {
+        Some((_, fraction)) => fraction.trim_end_matches('0').len(),
+        None => 0,
+    }
+}
+
+fn to_decimals(value: f64, decimals: usize) -> f64 {
+    format!("{value:.decimals$}").parse::<f64>().unwrap()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Step {
+    size: f64,
+    decimals: usize,
+}
+impl Step {
+    fn parse(step: &str) -> Option<Self> {
+        Some(Self {
+            size: parse_limit(step)?,
+            decimals: decimals(step),
+        })
+    }
+    fn floor(&self, value: f64) -> f64 {
+        //nudge so 0.3/0.1 doesn't floor to 2
+        to_decimals((value / self.size + 1e-9).floor() * self.size, self.decimals)
+    }
+    fn round(&self, value: f64) -> f64 {
+        to_decimals((value / self.size).round() * self.size, self.decimals)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Range {
+    min: Option<f64>,
+    max: Option<f64>,
+    step: Option<Step>,
+}
+
+/// Price, quantity and notional rules for a symbol pulled from its exchange filters.
+///
+/// Sizing goes through here so orders are rounded to what the exchange accepts and anything
+/// that would be rejected for LOT_SIZE, PRICE_FILTER or MIN_NOTIONAL fails before it is sent.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolFilters {
+    pub symbol: String,
+    price: Range,
+    lot: Range,
+    market_lot: Option<Range>,
+    min_notional: Option<f64>,
+    notional_applies_to_market: bool,
+    quote_decimals: usize,
+}
+
+impl SymbolFilters {
+    pub fn from_symbol(info: &Symbol) -> Self {
+        let mut filters = Self {
+            symbol: info.symbol.clone(),
+            quote_decimals: info.quote_precision as usize,
+            notional_applies_to_market: true,
+            ..Default::default()
+        };
+        for filter in info.filters.iter() {
+            match filter {
+                Filters::PriceFilter { min_price, max_price, tick_size } => {
+                    filters.price = Range {
+                        min: parse_limit(min_price),
+                        max: parse_limit(max_price),
+                        step: Step::parse(tick_size),
+                    }
+                }
+                Filters::LotSize { min_qty, max_qty, step_size } => {
+                    filters.lot = Range {
+                        min: parse_limit(min_qty),
+                        max: parse_limit(max_qty),
+                        step: Step::parse(step_size),
+                    }
+                }
+                Filters::MarketLotSize { min_qty, max_qty, step_size } => {
+                    filters.market_lot = Some(Range {
+                        min: parse_limit(min_qty),
+                        max: parse_limit(max_qty),
+                        step: Step::parse(step_size),
+                    })
+                }
+                Filters::MinNotional { min_notional, apply_to_market, .. }
+                | Filters::Notional { min_notional, apply_to_market, .. } => {
+                    if let Some(min_notional) = min_notional.as_deref().and_then(parse_limit) {
+                        filters.min_notional = Some(min_notional);
+                        filters.notional_applies_to_market = apply_to_market.unwrap_or(true);
+                    }
+                }
+                _ => {}
+            }
+        }
+        filters
+    }
+
+    //Market orders use MARKET_LOT_SIZE when binance sets one
+    fn lot(&self, market: bool) -> Range {
+        match self.market_lot {
+            Some(market_lot) if market => Range {
+                min: market_lot.min.or(self.lot.min),
+                max: market_lot.max.or(self.lot.max),
+                step: market_lot.step.or(self.lot.step),
+            },
+            _ => self.lot,
+        }
+    }
+
+    /// Nearest valid price on the tick grid
+    pub fn round_price(&self, price: f64) -> f64 {
+        match self.price.step {
+            Some(tick) => tick.round(price),
+            None => price,
+        }
+    }
+
+    /// Largest valid quantity not above `qty` so the balance is never overspent
+    pub fn floor_qty(&self, qty: f64, market: bool) -> f64 {
+        match self.lot(market).step {
+            Some(step) => step.floor(qty),
+            None => qty,
+        }
+    }
+
+    /// Quote amounts for market buys are limited to the quote asset precision
+    pub fn floor_quote(&self, quote_qty: f64) -> f64 {
+        let scale = 10f64.powi(self.quote_decimals as i32);
+        to_decimals((quote_qty * scale + 1e-9).floor() / scale, self.quote_decimals)
+    }
+
+    /// Check an order against the filters. `price` is the limit price or the current price
+    /// for market orders.
+    pub fn check(&self, qty: f64, price: f64, market: bool) -> Result<(), TradingBotError> {
+        let lot = self.lot(market);
+        if qty <= 0.0 {
+            return Err(TradingBotError::OrderRejected("Insufficient balance for an order".into()));
+        }
+        if let Some(min) = lot.min {
+            if qty < min {
+                return Err(TradingBotError::OrderRejected(format!(
+                    "Quantity {qty} is below the {} minimum of {min}",
+                    self.symbol
+                )));
+            }
+        }
+        if let Some(max) = lot.max {
+            if qty > max {
+                return Err(TradingBotError::OrderRejected(format!(
+                    "Quantity {qty} is above the {} maximum of {max}",
+                    self.symbol
+                )));
+            }
+        }
+        if !market {
+            if let Some(min) = self.price.min {
+                if price < min {
+                    return Err(TradingBotError::OrderRejected(format!(
+                        "Price {price} is below the {} minimum of {min}",
+                        self.symbol
+                    )));
+                }
+            }
+            if let Some(max) = self.price.max {
+                if price > max {
+                    return Err(TradingBotError::OrderRejected(format!(
+                        "Price {price} is above the {} maximum of {max}",
+                        self.symbol
+                    )));
+                }
+            }
+        }
+        self.check_notional(qty * price, market)
+    }
+
+    /// Left over quantity too small to be sold at market for around `price`
+    pub fn is_dust(&self, qty: f64, price: f64) -> bool {
+        self.check(self.floor_qty(qty, true), price, true).is_err()
+    }
+
+    /// Check the value of an order in the quote asset against MIN_NOTIONAL
+    pub fn check_notional(&self, notional: f64, market: bool) -> Result<(), TradingBotError> {
+        if market && !self.notional_applies_to_market {
+            return Ok(());
+        }
+        if let Some(min) = self.min_notional {
+            if notional < min {
+                return Err(TradingBotError::OrderRejected(format!(
+                    "Order worth {notional:.2} is below the {} minimum notional of {min}",
+                    self.symbol
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use binance::model::{Filters, Symbol};
+
+    use super::SymbolFilters;
+
+    //Filters shaped like a low priced altcoin, a whole-coin lot step and a 4 decimal tick
+    fn symbol(filters: Vec<Filters>) -> SymbolFilters {
+        SymbolFilters::from_symbol(&Symbol {
+            symbol: "XYZUSDT".into(),
+            status: "TRADING".into(),
+            base_asset: "XYZ".into(),
+            base_asset_precision: 8,
+            quote_asset: "USDT".into(),
+            quote_precision: 8,
+            order_types: vec!["LIMIT".into(), "MARKET".into()],
+            iceberg_allowed: false,
+            is_spot_trading_allowed: true,
+            is_margin_trading_allowed: false,
+            filters,
+        })
+    }
+
+    fn filters() -> SymbolFilters {
+        symbol(vec![
+            Filters::PriceFilter {
+                min_price: "0.00010000".into(),
+                max_price: "1000.00000000".into(),
+                tick_size: "0.00010000".into(),
+            },
+            Filters::LotSize {
+                min_qty: "1.00000000".into(),
+                max_qty: "9000000.00000000".into(),
+                step_size: "1.00000000".into(),
+            },
+            Filters::MarketLotSize {
+                min_qty: "10.00000000".into(),
+                max_qty: "50000.00000000".into(),
+                step_size: "0.00000000".into(),
+            },
+            Filters::Notional {
+                notional: None,
+                min_notional: Some("5.00000000".into()),
+                apply_to_market: Some(true),
+                avg_price_mins: Some(5.0),
+            },
+        ])
+    }
+
+    #[test]
+    fn quantities_floor_to_the_lot_step() {
+        let filters = filters();
+        assert_eq!(filters.floor_qty(123.999, false), 123.0);
+        assert_eq!(filters.floor_qty(0.5, false), 0.0);
+        //a step of 0 on MARKET_LOT_SIZE falls back to LOT_SIZE
+        assert_eq!(filters.floor_qty(123.999, true), 123.0);
+
+        let fine = symbol(vec![Filters::LotSize {
+            min_qty: "0.00001000".into(),
+            max_qty: "9000.00000000".into(),
+            step_size: "0.00001000".into(),
+        }]);
+        assert_eq!(fine.floor_qty(0.123456789, false), 0.12345);
+        //0.3/0.1 is 2.9999999999999996 and must not lose a step
+        let tenths = symbol(vec![Filters::LotSize {
+            min_qty: "0.10000000".into(),
+            max_qty: "9000.00000000".into(),
+            step_size: "0.10000000".into(),
+        }]);
+        assert_eq!(tenths.floor_qty(0.3, false), 0.3);
+    }
+
+    #[test]
+    fn prices_round_to_the_tick() {
+        let filters = filters();
+        assert_eq!(filters.round_price(0.123456), 0.1235);
+        assert_eq!(filters.round_price(0.12344), 0.1234);
+        assert_eq!(filters.round_price(2.0), 2.0);
+        //without a price filter prices are left alone
+        assert_eq!(symbol(vec![]).round_price(0.123456), 0.123456);
+    }
+
+    #[test]
+    fn quote_amounts_floor_to_the_quote_precision() {
+        let mut filters = filters();
+        filters.quote_decimals = 2;
+        assert_eq!(filters.floor_quote(10.129), 10.12);
+        assert_eq!(filters.floor_quote(0.29), 0.29);
+    }
+
+    #[test]
+    fn check_rejects_what_the_exchange_would() {
+        let filters = filters();
+        assert!(filters.check(100.0, 0.1, false).is_ok());
+        assert!(filters.check(0.0, 0.1, false).is_err());
+        //LOT_SIZE for limits and the tighter MARKET_LOT_SIZE for market orders
+        assert!(filters.check(5.0, 2.0, false).is_ok());
+        assert!(filters.check(5.0, 2.0, true).is_err());
+        assert!(filters.check(60000.0, 0.1, true).is_err());
+        assert!(filters.check(9000001.0, 0.1, false).is_err());
+        //price limits only apply to limit orders
+        assert!(filters.check(100.0, 0.00001, false).is_err());
+        assert!(filters.check(100.0, 1001.0, false).is_err());
+    }
+
+    #[test]
+    fn min_notional_rejects_small_orders() {
+        let filters = filters();
+        assert!(filters.check(49.0, 0.1, false).is_err());
+        assert!(filters.check(50.0, 0.1, false).is_ok());
+        assert!(filters.check_notional(4.99, false).is_err());
+        assert!(filters.check_notional(5.0, true).is_ok());
+
+        let limit_only = symbol(vec![Filters::MinNotional {
+            notional: None,
+            min_notional: Some("5.00000000".into()),
+            apply_to_market: Some(false),
+            avg_price_mins: None,
+        }]);
+        assert!(limit_only.check_notional(1.0, false).is_err());
+        assert!(limit_only.check_notional(1.0, true).is_ok());
+    }
+
+    #[test]
+    fn dust_is_what_cant_be_sold_at_market() {
+        let filters = filters();
+        //under MARKET_LOT_SIZE's minimum
+        assert!(filters.is_dust(9.0, 100.0));
+        //under the minimum notional
+        assert!(filters.is_dust(40.0, 0.1));
+        //the fraction under a whole step doesn't count
+        assert!(filters.is_dust(10.9, 0.49));
+        assert!(!filters.is_dust(10.9, 0.5));
+        assert!(!filters.is_dust(100.0, 0.1));
+    }
+}
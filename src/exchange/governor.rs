@@ -0,0 +1,511 @@
+use std::{
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use arc_swap::ArcSwap;
+use binance::model::{AccountInformation, Balance, FillInfo, Order, OrderCanceled, Symbol, Transaction};
+use tracing::{debug, warn};
+
+use crate::{config::Config, error::TradingBotError};
+
+use super::{http_status, AccountTrade, Exchange, OcoOrder, OcoRequest, OrderRequest, UserStreamKey};
+
+//Request weights from the spot api docs
+const WEIGHT_ACCOUNT: u32 = 10;
+const WEIGHT_EXCHANGE_INFO: u32 = 10;
+const WEIGHT_PRICE: u32 = 1;
+//bookTicker for one symbol, what paper accounts match their orders against
+const WEIGHT_BOOK_TICKER: u32 = 2;
+const WEIGHT_ORDER: u32 = 1;
+const WEIGHT_ORDER_STATUS: u32 = 2;
+const WEIGHT_CANCEL: u32 = 1;
+//myTrades filtered to one order
+const WEIGHT_ORDER_TRADES: u32 = 5;
+//openOrders for one symbol
+const WEIGHT_OPEN_ORDERS: u32 = 6;
+//openOrders without a symbol
+const WEIGHT_ALL_OPEN_ORDERS: u32 = 80;
+//myTrades without an order
+const WEIGHT_TRADES: u32 = 20;
+const WEIGHT_USER_STREAM: u32 = 1;
+
+const MINUTE: Duration = Duration::from_secs(60);
+const ORDER_WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    //Chart refreshes and order views, shed first
+    Low,
+    //Reconciling orders and balances
+    Normal,
+    //Placing and cancelling orders
+    High,
+}
+
+//Fixed window lined up with the clock the same way binance counts
+#[derive(Debug, Default)]
+struct Window {
+    index: u64,
+    used: u32,
+}
+impl Window {
+    //Start a new window if this one is over, returns the time left in it
+    fn roll(&mut self, len: Duration) -> Duration {
+        self.roll_at(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64, len)
+    }
+    fn roll_at(&mut self, now: u64, len: Duration) -> Duration {
+        let len = len.as_millis() as u64;
+        if now / len != self.index {
+            self.index = now / len;
+            self.used = 0;
+        }
+        Duration::from_millis((self.index + 1) * len - now)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+    pub used: u32,
+    pub limit: u32,
+    //calls waiting right now
+    pub waiting: u32,
+    //calls that had to wait for the next minute
+    pub queued: u64,
+    //low priority calls over their share and normal ones refused past `max_waiting`
+    pub shed: u64,
+}
+
+/// Request weight budget shared by every account on the bot's ip.
+///
+/// Low priority calls are shed once usage passes `rate_limit/low_priority_share` of the minute's
+/// budget, normal ones wait for the next minute past `rate_limit/normal_priority_share` and order
+/// placement can use all of it. A waiting call parks the blocking pool thread it runs on, so once
+/// `rate_limit/max_waiting` calls are parked normal ones are refused instead of joining them.
+pub struct RateGovernor {
+    config: Arc<ArcSwap<Config>>,
+    weight: Mutex<Window>,
+    waiting: AtomicU32,
+    queued: AtomicU64,
+    shed: AtomicU64,
+}
+
+//A call parked until there is room, counted for as long as it waits
+struct Waiter<'a>(&'a AtomicU32);
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl RateGovernor {
+    pub fn new(config: Arc<ArcSwap<Config>>) -> Self {
+        Self {
+            config,
+            weight: Mutex::new(Window::default()),
+            waiting: AtomicU32::new(0),
+            queued: AtomicU64::new(0),
+            shed: AtomicU64::new(0),
+        }
+    }
+
+    fn limit(&self) -> Result<u32, TradingBotError> {
+        Ok(self.config.load().get::<u32>("rate_limit", "request_weight_per_minute")?.unwrap_or(1200))
+    }
+
+    fn ceiling(&self, priority: Priority) -> Result<u32, TradingBotError> {
+        let config = self.config.load();
+        let share = match priority {
+            Priority::Low => config.get::<f64>("rate_limit", "low_priority_share")?.unwrap_or(0.5),
+            Priority::Normal => config.get::<f64>("rate_limit", "normal_priority_share")?.unwrap_or(0.8),
+            Priority::High => 1.0,
+        };
+        Ok((self.limit()? as f64 * share) as u32)
+    }
+
+    //Count a call in as waiting, normal ones are turned away once too many already are
+    fn wait(&self, priority: Priority) -> Result<Waiter<'_>, TradingBotError> {
+        let max = self.config.load().get::<u32>("rate_limit", "max_waiting")?.unwrap_or(16);
+        let waiter = Waiter(&self.waiting);
+        if self.waiting.fetch_add(1, Ordering::Relaxed) >= max && priority != Priority::High {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+            return Err(TradingBotError::RateLimited(format!("{max} calls are already waiting on the rate limit")));
+        }
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        Ok(waiter)
+    }
+
+    /// Take `weight` from this minute's budget, blocking until there is room unless the call
+    /// is low priority
+    pub fn acquire(&self, weight: u32, priority: Priority) -> Result<(), TradingBotError> {
+        let ceiling = self.ceiling(priority)?;
+        let mut waiter = None;
+        loop {
+            let wait = {
+                let mut window = self.weight.lock().unwrap();
+                let wait = window.roll(MINUTE);
+                if window.used + weight <= ceiling {
+                    window.used += weight;
+                    return Ok(());
+                }
+                wait
+            };
+            if priority == Priority::Low {
+                self.shed.fetch_add(1, Ordering::Relaxed);
+                return Err(TradingBotError::RateLimited("Request weight is reserved for orders".into()));
+            }
+            if waiter.is_none() {
+                waiter = Some(self.wait(priority)?);
+                debug!("Request weight spent waiting {wait:?}");
+            }
+            thread::sleep(wait);
+        }
+    }
+
+    //Binance answered 429 or 418 so nothing else goes out this minute
+    fn back_off(&self) {
+        let limit = self.limit().unwrap_or(1200);
+        let mut window = self.weight.lock().unwrap();
+        window.roll(MINUTE);
+        window.used = window.used.max(limit);
+        warn!("Binance rate limited the bot backing off until the next minute");
+    }
+
+    pub fn usage(&self) -> Result<Usage, TradingBotError> {
+        let mut window = self.weight.lock().unwrap();
+        window.roll(MINUTE);
+        Ok(Usage {
+            used: window.used,
+            limit: self.limit()?,
+            waiting: self.waiting.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            shed: self.shed.load(Ordering::Relaxed),
+        })
+    }
+}
+
+//Binance answered 429, banned the ip with 418 or sent -1003 too many requests
+fn is_rate_limited(err: &TradingBotError) -> bool {
+    use binance::errors::ErrorKind;
+    let TradingBotError::BinanceError(err) = err else {
+        return false;
+    };
+    match err.kind() {
+        ErrorKind::BinanceError(response) => response.code == -1003,
+        _ => matches!(http_status(err), Some(429 | 418)),
+    }
+}
+
+/// Charges every call to the shared `RateGovernor` and counts orders against the account's
+/// ten second order limit.
+///
+/// Venues that never reach binance have no governor and only pass through. Simulated venues
+/// keep their orders locally so they skip the order limit and are only charged for the market
+/// data they pull from binance. Orders and cancels always go out at high priority, everything
+/// else at the priority this handle was made with.
+#[derive(Clone)]
+pub struct GovernedExchange {
+    inner: Arc<dyn Exchange>,
+    governor: Option<Arc<RateGovernor>>,
+    orders: Arc<Mutex<Window>>,
+    priority: Priority,
+    simulated: bool,
+}
+
+impl GovernedExchange {
+    pub fn new(inner: Arc<dyn Exchange>, governor: Option<Arc<RateGovernor>>) -> Self {
+        Self {
+            inner,
+            governor,
+            orders: Arc::new(Mutex::new(Window::default())),
+            priority: Priority::Normal,
+            simulated: false,
+        }
+    }
+
+    pub fn simulated(inner: Arc<dyn Exchange>, governor: Arc<RateGovernor>) -> Self {
+        Self {
+            simulated: true,
+            ..Self::new(inner, Some(governor))
+        }
+    }
+
+    pub fn with_priority(&self, priority: Priority) -> Self {
+        Self {
+            priority,
+            ..self.clone()
+        }
+    }
+
+    //Orders sent in the current ten second window
+    pub fn orders_used(&self) -> u32 {
+        let mut window = self.orders.lock().unwrap();
+        window.roll(ORDER_WINDOW);
+        window.used
+    }
+
+    //What a call spends on binance, `simulated` is what a paper account's version of it pulls
+    fn weight(&self, live: u32, simulated: u32) -> u32 {
+        if self.simulated {
+            simulated
+        } else {
+            live
+        }
+    }
+
+    fn call<T>(
+        &self,
+        weight: u32,
+        priority: Priority,
+        call: impl FnOnce(&dyn Exchange) -> Result<T, TradingBotError>,
+    ) -> Result<T, TradingBotError> {
+        let Some(governor) = self.governor.as_ref().filter(|_| weight > 0) else {
+            return call(self.inner.as_ref());
+        };
+        governor.acquire(weight, priority)?;
+        let result = call(self.inner.as_ref());
+        if let Err(err) = &result {
+            if is_rate_limited(err) {
+                governor.back_off();
+            }
+        }
+        result
+    }
+
+    //Wait for room under the order count limit
+    fn order(&self, count: u32) -> Result<(), TradingBotError> {
+        if self.governor.is_none() || self.simulated {
+            return Ok(());
+        }
+        let governor = self.governor.as_ref().unwrap();
+        let limit = governor.config.load().get::<u32>("rate_limit", "orders_per_10s")?.unwrap_or(50);
+        let mut waiter = None;
+        loop {
+            let wait = {
+                let mut window = self.orders.lock().unwrap();
+                let wait = window.roll(ORDER_WINDOW);
+                if window.used + count <= limit {
+                    window.used += count;
+                    return Ok(());
+                }
+                wait
+            };
+            if waiter.is_none() {
+                //orders go out at high priority so they wait but still count against the cap
+                waiter = Some(governor.wait(Priority::High)?);
+                debug!("Order limit reached waiting {wait:?}");
+            }
+            thread::sleep(wait);
+        }
+    }
+}
+
+impl Exchange for GovernedExchange {
+    fn get_account(&self) -> Result<AccountInformation, TradingBotError> {
+        self.call(self.weight(WEIGHT_ACCOUNT, 0), self.priority, |exchange| exchange.get_account())
+    }
+
+    fn get_balance(&self, asset: &str) -> Result<Balance, TradingBotError> {
+        self.call(self.weight(WEIGHT_ACCOUNT, 0), self.priority, |exchange| exchange.get_balance(asset))
+    }
+
+    fn get_symbol_info(&self, symbol: &str) -> Result<Symbol, TradingBotError> {
+        self.call(WEIGHT_EXCHANGE_INFO, self.priority, |exchange| exchange.get_symbol_info(symbol))
+    }
+
+    fn get_symbols(&self) -> Result<Vec<String>, TradingBotError> {
+        self.call(WEIGHT_EXCHANGE_INFO, self.priority, |exchange| exchange.get_symbols())
+    }
+
+    fn get_price(&self, symbol: &str) -> Result<f64, TradingBotError> {
+        self.call(WEIGHT_PRICE, self.priority, |exchange| exchange.get_price(symbol))
+    }
+
+    fn place_order(&self, order: &OrderRequest) -> Result<Transaction, TradingBotError> {
+        self.order(1)?;
+        let weight = self.weight(WEIGHT_ORDER, WEIGHT_EXCHANGE_INFO + WEIGHT_BOOK_TICKER);
+        self.call(weight, Priority::High, |exchange| exchange.place_order(order))
+    }
+
+    fn place_oco(&self, order: &OcoRequest) -> Result<OcoOrder, TradingBotError> {
+        //both legs count against the order limit
+        self.order(2)?;
+        let weight = self.weight(WEIGHT_ORDER, WEIGHT_EXCHANGE_INFO + WEIGHT_PRICE);
+        self.call(weight, Priority::High, |exchange| exchange.place_oco(order))
+    }
+
+    fn order_status(&self, symbol: &str, order_id: u64) -> Result<Order, TradingBotError> {
+        self.call(self.weight(WEIGHT_ORDER_STATUS, WEIGHT_BOOK_TICKER), self.priority, |exchange| exchange.order_status(symbol, order_id))
+    }
+
+    fn order_by_client_id(&self, symbol: &str, client_order_id: &str) -> Result<Option<Order>, TradingBotError> {
+        //only used to settle an order whose send failed so it can't wait behind views
+        self.call(self.weight(WEIGHT_ORDER_STATUS, WEIGHT_BOOK_TICKER), Priority::High, |exchange| exchange.order_by_client_id(symbol, client_order_id))
+    }
+
+    fn cancel_order(&self, symbol: &str, order_id: u64) -> Result<OrderCanceled, TradingBotError> {
+        self.call(self.weight(WEIGHT_CANCEL, 0), Priority::High, |exchange| exchange.cancel_order(symbol, order_id))
+    }
+
+    fn open_orders(&self, symbol: &str) -> Result<Vec<Order>, TradingBotError> {
+        self.call(self.weight(WEIGHT_OPEN_ORDERS, WEIGHT_BOOK_TICKER), self.priority, |exchange| exchange.open_orders(symbol))
+    }
+
+    fn all_open_orders(&self) -> Result<Vec<Order>, TradingBotError> {
+        //paper accounts match each symbol they have orders on, the count is unknown up front so charge the live weight
+        self.call(self.weight(WEIGHT_ALL_OPEN_ORDERS, WEIGHT_ALL_OPEN_ORDERS), Priority::High, |exchange| exchange.all_open_orders())
+    }
+
+    fn recent_trades(&self, symbol: &str, limit: u16) -> Result<Vec<AccountTrade>, TradingBotError> {
+        self.call(self.weight(WEIGHT_TRADES, WEIGHT_BOOK_TICKER), self.priority, |exchange| exchange.recent_trades(symbol, limit))
+    }
+
+    fn order_fills(&self, symbol: &str, order_id: u64) -> Result<Vec<FillInfo>, TradingBotError> {
+        self.call(self.weight(WEIGHT_ORDER_TRADES, WEIGHT_BOOK_TICKER), self.priority, |exchange| exchange.order_fills(symbol, order_id))
+    }
+
+    fn market_stream_endpoint(&self) -> Option<String> {
+        self.inner.market_stream_endpoint()
+    }
+
+    fn start_user_stream(&self) -> Result<Option<UserStreamKey>, TradingBotError> {
+        self.call(self.weight(WEIGHT_USER_STREAM, 0), Priority::High, |exchange| exchange.start_user_stream())
+    }
+
+    fn keep_alive_user_stream(&self, listen_key: &str) -> Result<(), TradingBotError> {
+        self.call(self.weight(WEIGHT_USER_STREAM, 0), Priority::High, |exchange| exchange.keep_alive_user_stream(listen_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread, time::Duration};
+
+    use arc_swap::ArcSwap;
+    use binance::errors::{BinanceContentError, Error, ErrorKind};
+    use diesel::{ExpressionMethods, RunQueryDsl};
+
+    use super::{is_rate_limited, GovernedExchange, Priority, RateGovernor, Window, MINUTE, ORDER_WINDOW};
+    use crate::{
+        config::Config,
+        db::{establish_connection, test::database},
+        error::TradingBotError,
+        exchange::{mock::MockExchange, Exchange, OrderQuantity, OrderRequest, OrderSide},
+    };
+
+    //Governor on a fresh database with `rate_limit` settings changed from their defaults
+    fn governor(settings: &[(&str, &str)]) -> Arc<RateGovernor> {
+        Config::first_setup().unwrap();
+        for (setting, value) in settings {
+            use crate::schema::configs::dsl;
+            diesel::update(dsl::configs)
+                .filter(dsl::section.eq("rate_limit"))
+                .filter(dsl::key.eq(setting))
+                .set(dsl::value.eq(Some(*value)))
+                .execute(&mut establish_connection())
+                .unwrap();
+        }
+        Arc::new(RateGovernor::new(Arc::new(ArcSwap::from_pointee(Config::load().unwrap()))))
+    }
+
+    //Windows follow the clock so a test that needs one to itself starts it fresh
+    fn fresh_window(len: Duration) {
+        let left = Window::default().roll(len);
+        if left < Duration::from_secs(3) {
+            thread::sleep(left);
+        }
+    }
+
+    fn binance_error(kind: ErrorKind) -> TradingBotError {
+        Error::from_kind(kind).into()
+    }
+
+    #[test]
+    fn windows_reset_on_the_clock() {
+        let mut window = Window::default();
+        assert_eq!(window.roll_at(10_500, ORDER_WINDOW), Duration::from_millis(9_500));
+        window.used = 5;
+        assert_eq!(window.roll_at(19_999, ORDER_WINDOW), Duration::from_millis(1));
+        assert_eq!(window.used, 5);
+        assert_eq!(window.roll_at(20_000, ORDER_WINDOW), ORDER_WINDOW);
+        assert_eq!(window.used, 0);
+    }
+
+    #[test]
+    fn rate_limits_are_told_apart_by_status_and_code() {
+        assert!(is_rate_limited(&binance_error(ErrorKind::Msg("Received response: 429".into()))));
+        assert!(is_rate_limited(&binance_error(ErrorKind::Msg("Received response: 418".into()))));
+        assert!(is_rate_limited(&binance_error(ErrorKind::BinanceError(BinanceContentError {
+            code: -1003,
+            msg: "Too many requests".into(),
+        }))));
+        assert!(!is_rate_limited(&binance_error(ErrorKind::Msg("Received response: 403".into()))));
+        assert!(!is_rate_limited(&binance_error(ErrorKind::Msg("Internal Server Error".into()))));
+        //digits in the message mean nothing
+        assert!(!is_rate_limited(&binance_error(ErrorKind::BinanceError(BinanceContentError {
+            code: -1013,
+            msg: "Filter failure: PRICE_FILTER 4290.1800 over 418".into(),
+        }))));
+        assert!(!is_rate_limited(&TradingBotError::ExchangeError("Received response: 429".into())));
+    }
+
+    #[test]
+    fn low_priority_is_shed_and_normal_refused_past_max_waiting() {
+        let _database = database();
+        let governor = governor(&[("request_weight_per_minute", "100"), ("max_waiting", "0")]);
+        fresh_window(MINUTE);
+
+        governor.acquire(50, Priority::Low).unwrap();
+        assert!(matches!(governor.acquire(1, Priority::Low), Err(TradingBotError::RateLimited(_))));
+        governor.acquire(30, Priority::Normal).unwrap();
+        //normal calls would wait for the next minute but nothing more may wait
+        assert!(matches!(governor.acquire(1, Priority::Normal), Err(TradingBotError::RateLimited(_))));
+        //orders can use the whole budget
+        governor.acquire(20, Priority::High).unwrap();
+
+        let usage = governor.usage().unwrap();
+        assert_eq!((usage.used, usage.limit), (100, 100));
+        assert_eq!((usage.waiting, usage.queued, usage.shed), (0, 0, 2));
+    }
+
+    #[test]
+    fn backing_off_spends_the_minute() {
+        let _database = database();
+        let governor = governor(&[("request_weight_per_minute", "100")]);
+        fresh_window(MINUTE);
+
+        governor.back_off();
+        assert_eq!(governor.usage().unwrap().used, 100);
+        assert!(matches!(governor.acquire(1, Priority::Low), Err(TradingBotError::RateLimited(_))));
+    }
+
+    #[test]
+    fn orders_count_against_the_window_unless_simulated() {
+        let _database = database();
+        let governor = governor(&[("orders_per_10s", "2")]);
+        fresh_window(ORDER_WINDOW);
+        fresh_window(MINUTE);
+        let buy = OrderRequest::market("BTCUSDT", OrderSide::Buy, OrderQuantity::Quote(100.0));
+
+        let live = GovernedExchange::new(Arc::new(MockExchange::with_defaults()), Some(governor.clone()));
+        live.place_order(&buy).unwrap();
+        live.place_order(&buy).unwrap();
+        assert_eq!(live.orders_used(), 2);
+        assert_eq!(governor.usage().unwrap().used, 2);
+
+        //paper orders stay local and are charged for the market data they pull
+        let simulated = GovernedExchange::simulated(Arc::new(MockExchange::with_defaults()), governor.clone());
+        simulated.place_order(&buy).unwrap();
+        simulated.place_order(&buy).unwrap();
+        simulated.place_order(&buy).unwrap();
+        assert_eq!(simulated.orders_used(), 0);
+        assert_eq!(governor.usage().unwrap().used, 2 + 3 * 12);
+        //account reads never reach binance
+        simulated.get_balance("USDT").unwrap();
+        assert_eq!(governor.usage().unwrap().used, 2 + 3 * 12);
+    }
+}
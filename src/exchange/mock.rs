@@ -0,0 +1,758 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use binance::model::{
+    AccountInformation, Balance, Filters, Order, OrderCanceled, Symbol, Transaction,
+};
+
+use crate::error::TradingBotError;
+
+use super::{oco_leg_ids, Exchange, OcoOrder, OcoRequest, OrderQuantity, OrderRequest, OrderSide, TimeInForce};
+
+#[derive(Debug, Clone)]
+struct MockOrder {
+    id: u64,
+    symbol: String,
+    side: OrderSide,
+    price: Option<f64>,
+    orig_qty: f64,
+    executed_qty: f64,
+    cummulative_quote_qty: f64,
+    status: &'static str,
+    time: u64,
+    //stop-limit legs only rest once the price has passed their stop
+    stop_price: Option<f64>,
+    triggered: bool,
+    list_id: Option<u64>,
+    client_order_id: Option<String>,
+    type_name: &'static str,
+    time_in_force: Option<&'static str>,
+}
+impl MockOrder {
+    //resting on the book with something left to fill
+    fn working(&self) -> bool {
+        matches!(self.status, "NEW" | "PARTIALLY_FILLED")
+    }
+    fn client_order_id(&self) -> String {
+        self.client_order_id.clone().unwrap_or_else(|| format!("mock_{}", self.id))
+    }
+    fn to_order(&self) -> Order {
+        Order {
+            symbol: self.symbol.clone(),
+            order_id: self.id,
+            order_list_id: self.list_id.map(|id| id as i64).unwrap_or(-1),
+            client_order_id: self.client_order_id(),
+            price: self.price.unwrap_or(0.0),
+            orig_qty: self.orig_qty.to_string(),
+            executed_qty: self.executed_qty.to_string(),
+            cummulative_quote_qty: self.cummulative_quote_qty.to_string(),
+            status: self.status.into(),
+            //binance reports GTC for the order types that don't take a time in force
+            time_in_force: self.time_in_force.unwrap_or("GTC").into(),
+            type_name: self.type_name.into(),
+            side: self.side.as_str().into(),
+            stop_price: self.stop_price.unwrap_or(0.0),
+            iceberg_qty: "0".into(),
+            time: self.time,
+            update_time: self.time,
+            is_working: self.working(),
+            orig_quote_order_qty: "0".into(),
+        }
+    }
+    fn to_transaction(&self) -> Transaction {
+        Transaction {
+            symbol: self.symbol.clone(),
+            order_id: self.id,
+            order_list_id: self.list_id.map(|id| id as i64),
+            client_order_id: self.client_order_id(),
+            transact_time: self.time,
+            price: self.price.unwrap_or(0.0),
+            orig_qty: self.orig_qty,
+            executed_qty: self.executed_qty,
+            cummulative_quote_qty: self.cummulative_quote_qty,
+            stop_price: self.stop_price.unwrap_or(0.0),
+            status: self.status.into(),
+            time_in_force: self.time_in_force.unwrap_or("GTC").into(),
+            type_name: self.type_name.into(),
+            side: self.side.as_str().into(),
+            fills: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    //asset -> (free,locked)
+    balances: HashMap<String, (f64, f64)>,
+    symbols: HashMap<String, Symbol>,
+    prices: HashMap<String, f64>,
+    orders: HashMap<u64, MockOrder>,
+    next_id: u64,
+}
+impl MockState {
+    fn balance_mut(&mut self, asset: &str) -> &mut (f64, f64) {
+        self.balances.entry(asset.into()).or_insert((0.0, 0.0))
+    }
+
+    fn assets(&self, symbol: &str) -> Result<(String, String), TradingBotError> {
+        let Some(info) = self.symbols.get(symbol) else {
+            return Err(TradingBotError::ExchangeError(format!("Invalid symbol {symbol}")));
+        };
+        Ok((info.base_asset.clone(), info.quote_asset.clone()))
+    }
+
+    //Fills `qty` of a resting or new order at the given price moving the locked funds across
+    fn fill(&mut self, id: u64, qty: f64, price: f64) -> Result<(), TradingBotError> {
+        let Some(order) = self.orders.get(&id).cloned() else {
+            return Err(TradingBotError::ExchangeError("Order does not exist.".into()));
+        };
+        let (base, quote) = self.assets(&order.symbol)?;
+        let quote_qty = qty * price;
+        match order.side {
+            OrderSide::Buy => {
+                //a buy locked funds at its limit so filling under it hands the difference back
+                let locked = qty * order.price.unwrap_or(price);
+                let balance = self.balance_mut(&quote);
+                balance.1 -= locked;
+                balance.0 += locked - quote_qty;
+                self.balance_mut(&base).0 += qty;
+            }
+            OrderSide::Sell => {
+                self.balance_mut(&base).1 -= qty;
+                self.balance_mut(&quote).0 += quote_qty;
+            }
+        }
+        let order = self.orders.get_mut(&id).unwrap();
+        order.executed_qty += qty;
+        order.cummulative_quote_qty += quote_qty;
+        order.status = if order.executed_qty < order.orig_qty { "PARTIALLY_FILLED" } else { "FILLED" };
+        //the other leg of an OCO shared the locked funds so it just expires
+        if let Some(list_id) = order.list_id {
+            for other in self.orders.values_mut() {
+                if other.list_id == Some(list_id) && other.id != id && other.working() {
+                    other.status = "EXPIRED";
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, mut order: MockOrder) -> u64 {
+        self.next_id += 1;
+        order.id = self.next_id;
+        order.time = self.next_id;
+        self.orders.insert(order.id, order);
+        self.next_id
+    }
+}
+
+/// Deterministic in-memory exchange.
+///
+/// Market orders and limit orders that are marketable when placed fill instantly at the last
+/// price set with `set_price` like a taker would, other limit orders rest until `set_price`
+/// crosses them and then fill at their limit price, or part of them is filled with `fill_order`. Stop-limit legs start
+/// resting once the price reaches their stop. There are no fees.
+#[derive(Default)]
+pub struct MockExchange {
+    state: Mutex<MockState>,
+}
+
+impl MockExchange {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //BTCUSDT @ 20000 with 10000 USDT to spend used when MOCK_EXCHANGE is set
+    pub fn with_defaults() -> Self {
+        let exchange = Self::new();
+        exchange.add_symbol("BTCUSDT", "BTC", "USDT");
+        exchange.set_price("BTCUSDT", 20000.0);
+        exchange.set_balance("USDT", 10000.0);
+        exchange
+    }
+
+    pub fn add_symbol(&self, symbol: &str, base: &str, quote: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.symbols.insert(
+            symbol.into(),
+            Symbol {
+                symbol: symbol.into(),
+                status: "TRADING".into(),
+                base_asset: base.into(),
+                base_asset_precision: 8,
+                quote_asset: quote.into(),
+                quote_precision: 8,
+                order_types: vec!["LIMIT".into(), "LIMIT_MAKER".into(), "MARKET".into(), "STOP_LOSS_LIMIT".into()],
+                iceberg_allowed: false,
+                is_spot_trading_allowed: true,
+                is_margin_trading_allowed: false,
+                filters: vec![
+                    Filters::PriceFilter {
+                        min_price: "0.01".into(),
+                        max_price: "1000000.00".into(),
+                        tick_size: "0.01".into(),
+                    },
+                    Filters::LotSize {
+                        min_qty: "0.00001".into(),
+                        max_qty: "9000.00000".into(),
+                        step_size: "0.00001".into(),
+                    },
+                    Filters::MinNotional {
+                        notional: None,
+                        min_notional: Some("10.00000000".into()),
+                        apply_to_market: Some(true),
+                        avg_price_mins: Some(5.0),
+                    },
+                ],
+            },
+        );
+    }
+
+    pub fn set_balance(&self, asset: &str, free: f64) {
+        let mut state = self.state.lock().unwrap();
+        state.balance_mut(asset).0 = free;
+    }
+
+    /// Fills `qty` of a resting limit order at its limit as if part of the book traded with it
+    #[cfg(test)]
+    pub fn fill_order(&self, order_id: u64, qty: f64) -> Result<(), TradingBotError> {
+        let mut state = self.state.lock().unwrap();
+        let (price, left) = match state.orders.get(&order_id) {
+            Some(order) if order.working() && order.price.is_some() => (order.price.unwrap(), order.orig_qty - order.executed_qty),
+            _ => return Err(TradingBotError::ExchangeError("Unknown order sent.".into())),
+        };
+        state.fill(order_id, qty.min(left), price)
+    }
+
+    /// Moves the market filling any resting limit orders the new price crosses
+    pub fn set_price(&self, symbol: &str, price: f64) {
+        let mut state = self.state.lock().unwrap();
+        state.prices.insert(symbol.into(), price);
+        for order in state.orders.values_mut() {
+            let Some(stop) = order.stop_price else {
+                continue;
+            };
+            let passed = match order.side {
+                OrderSide::Buy => price >= stop,
+                OrderSide::Sell => price <= stop,
+            };
+            if order.symbol == symbol && order.working() && passed {
+                order.triggered = true;
+            }
+        }
+        let mut crossed: Vec<(u64, f64)> = state
+            .orders
+            .values()
+            .filter(|o| o.symbol == symbol && o.working())
+            .filter(|o| o.stop_price.is_none() || o.triggered)
+            .filter_map(|o| {
+                let limit = o.price?;
+                let crosses = match o.side {
+                    OrderSide::Buy => price <= limit,
+                    OrderSide::Sell => price >= limit,
+                };
+                crosses.then_some((o.id, limit))
+            })
+            .collect();
+        crossed.sort_by_key(|(id, _)| *id);
+        for (id, limit) in crossed {
+            //an earlier fill may have expired this order's OCO sibling
+            let order = &state.orders[&id];
+            if !order.working() {
+                continue;
+            }
+            let left = order.orig_qty - order.executed_qty;
+            //orders were validated when they were placed so this can't fail
+            let _ = state.fill(id, left, limit);
+        }
+    }
+}
+
+impl Exchange for MockExchange {
+    fn get_account(&self) -> Result<AccountInformation, TradingBotError> {
+        let state = self.state.lock().unwrap();
+        Ok(AccountInformation {
+            maker_commission: 0.0,
+            taker_commission: 0.0,
+            buyer_commission: 0.0,
+            seller_commission: 0.0,
+            can_trade: true,
+            can_withdraw: false,
+            can_deposit: false,
+            balances: state
+                .balances
+                .iter()
+                .map(|(asset, (free, locked))| Balance {
+                    asset: asset.clone(),
+                    free: free.to_string(),
+                    locked: locked.to_string(),
+                })
+                .collect(),
+        })
+    }
+
+    fn get_balance(&self, asset: &str) -> Result<Balance, TradingBotError> {
+        let state = self.state.lock().unwrap();
+        let (free, locked) = state.balances.get(asset).copied().unwrap_or((0.0, 0.0));
+        Ok(Balance {
+            asset: asset.into(),
+            free: free.to_string(),
+            locked: locked.to_string(),
+        })
+    }
+
+    fn get_symbol_info(&self, symbol: &str) -> Result<Symbol, TradingBotError> {
+        let state = self.state.lock().unwrap();
+        state
+            .symbols
+            .get(symbol)
+            .cloned()
+            .ok_or_else(|| TradingBotError::ExchangeError(format!("Invalid symbol {symbol}")))
+    }
+
+    fn get_symbols(&self) -> Result<Vec<String>, TradingBotError> {
+        let state = self.state.lock().unwrap();
+        Ok(state.symbols.keys().cloned().collect())
+    }
+
+    fn get_price(&self, symbol: &str) -> Result<f64, TradingBotError> {
+        let state = self.state.lock().unwrap();
+        match state.prices.get(symbol) {
+            Some(price) => Ok(*price),
+            None => Err(TradingBotError::ExchangeError(format!("No price for {symbol}"))),
+        }
+    }
+
+    fn place_order(&self, request: &OrderRequest) -> Result<Transaction, TradingBotError> {
+        let mut state = self.state.lock().unwrap();
+        let (base, quote) = state.assets(&request.symbol)?;
+        let Some(last_price) = state.prices.get(&request.symbol).copied() else {
+            return Err(TradingBotError::ExchangeError(format!("No price for {}", request.symbol)));
+        };
+        let qty = match request.quantity {
+            OrderQuantity::Base(qty) => qty,
+            OrderQuantity::Quote(quote_qty) => {
+                if request.price.is_some() {
+                    return Err(TradingBotError::ExchangeError("Limit orders must be sized in the base asset".into()));
+                }
+                quote_qty / last_price
+            }
+        };
+        if qty <= 0.0 {
+            return Err(TradingBotError::ExchangeError("Invalid quantity.".into()));
+        }
+        let reserve_price = request.price.unwrap_or(last_price);
+        let marketable = match (request.side, request.price) {
+            (_, None) => true,
+            (OrderSide::Buy, Some(limit)) => last_price <= limit,
+            (OrderSide::Sell, Some(limit)) => last_price >= limit,
+        };
+        if request.post_only && marketable {
+            return Err(TradingBotError::ExchangeError("Order would immediately match and take.".into()));
+        }
+        //orders fill whole here so IOC and FOK either fill now or expire
+        let expires = request.price.is_some() && !marketable && request.time_in_force != TimeInForce::Gtc;
+        //lock the funds the order needs
+        let (asset, needed) = match request.side {
+            OrderSide::Buy => (quote, qty * reserve_price),
+            OrderSide::Sell => (base, qty),
+        };
+        let balance = state.balance_mut(&asset);
+        if balance.0 < needed {
+            return Err(TradingBotError::ExchangeError(
+                "Account has insufficient balance for requested action.".into(),
+            ));
+        }
+        if !expires {
+            balance.0 -= needed;
+            balance.1 += needed;
+        }
+
+        let id = state.insert(MockOrder {
+            id: 0,
+            symbol: request.symbol.clone(),
+            side: request.side,
+            price: request.price,
+            orig_qty: qty,
+            executed_qty: 0.0,
+            cummulative_quote_qty: 0.0,
+            status: if expires { "EXPIRED" } else { "NEW" },
+            time: 0,
+            stop_price: None,
+            triggered: false,
+            list_id: None,
+            client_order_id: request.client_order_id.clone(),
+            type_name: request.type_name(),
+            time_in_force: request.time_in_force(),
+        });
+        if marketable {
+            state.fill(id, qty, last_price)?;
+        }
+        Ok(state.orders[&id].to_transaction())
+    }
+
+    fn place_oco(&self, request: &OcoRequest) -> Result<OcoOrder, TradingBotError> {
+        let mut state = self.state.lock().unwrap();
+        let (base, _) = state.assets(&request.symbol)?;
+        let Some(last_price) = state.prices.get(&request.symbol).copied() else {
+            return Err(TradingBotError::ExchangeError(format!("No price for {}", request.symbol)));
+        };
+        if !(request.take_profit > last_price && last_price > request.stop_price) {
+            return Err(TradingBotError::ExchangeError(
+                "The relationship of the prices for the orders is not correct.".into(),
+            ));
+        }
+        //both legs sell the same coins so they are only locked once
+        let balance = state.balance_mut(&base);
+        if balance.0 < request.quantity {
+            return Err(TradingBotError::ExchangeError(
+                "Account has insufficient balance for requested action.".into(),
+            ));
+        }
+        balance.0 -= request.quantity;
+        balance.1 += request.quantity;
+
+        let leg = MockOrder {
+            id: 0,
+            symbol: request.symbol.clone(),
+            side: OrderSide::Sell,
+            price: Some(request.take_profit),
+            orig_qty: request.quantity,
+            executed_qty: 0.0,
+            cummulative_quote_qty: 0.0,
+            status: "NEW",
+            time: 0,
+            stop_price: None,
+            triggered: false,
+            list_id: Some(state.next_id + 1),
+            client_order_id: None,
+            type_name: "LIMIT_MAKER",
+            time_in_force: None,
+        };
+        let (take_profit_client_id, stop_loss_client_id) = match &request.client_order_id {
+            Some(client_order_id) => {
+                let (take_profit, stop_loss) = oco_leg_ids(client_order_id);
+                (Some(take_profit), Some(stop_loss))
+            }
+            None => (None, None),
+        };
+        let take_profit_id = state.insert(MockOrder {
+            client_order_id: take_profit_client_id,
+            ..leg.clone()
+        });
+        let stop_loss_id = state.insert(MockOrder {
+            price: Some(request.stop_limit_price),
+            stop_price: Some(request.stop_price),
+            client_order_id: stop_loss_client_id,
+            type_name: "STOP_LOSS_LIMIT",
+            time_in_force: Some("GTC"),
+            ..leg
+        });
+        Ok(OcoOrder {
+            order_list_id: take_profit_id as i64,
+            take_profit_id,
+            stop_loss_id,
+        })
+    }
+
+    fn order_status(&self, symbol: &str, order_id: u64) -> Result<Order, TradingBotError> {
+        let state = self.state.lock().unwrap();
+        match state.orders.get(&order_id) {
+            Some(order) if order.symbol == symbol => Ok(order.to_order()),
+            _ => Err(TradingBotError::ExchangeError("Order does not exist.".into())),
+        }
+    }
+
+    fn order_by_client_id(&self, symbol: &str, client_order_id: &str) -> Result<Option<Order>, TradingBotError> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .orders
+            .values()
+            .find(|order| order.symbol == symbol && order.client_order_id() == client_order_id)
+            .map(|order| order.to_order()))
+    }
+
+    fn open_orders(&self, symbol: &str) -> Result<Vec<Order>, TradingBotError> {
+        let state = self.state.lock().unwrap();
+        let mut orders: Vec<Order> = state
+            .orders
+            .values()
+            .filter(|order| order.symbol == symbol && order.working())
+            .map(|order| order.to_order())
+            .collect();
+        orders.sort_by_key(|order| order.order_id);
+        Ok(orders)
+    }
+
+    fn all_open_orders(&self) -> Result<Vec<Order>, TradingBotError> {
+        let state = self.state.lock().unwrap();
+        let mut orders: Vec<Order> = state
+            .orders
+            .values()
+            .filter(|order| order.working())
+            .map(|order| order.to_order())
+            .collect();
+        orders.sort_by_key(|order| order.order_id);
+        Ok(orders)
+    }
+
+    fn cancel_order(&self, symbol: &str, order_id: u64) -> Result<OrderCanceled, TradingBotError> {
+        let mut state = self.state.lock().unwrap();
+        let (base, quote) = state.assets(symbol)?;
+        let Some(order) = state.orders.get(&order_id).cloned() else {
+            return Err(TradingBotError::ExchangeError("Order does not exist.".into()));
+        };
+        if order.symbol != symbol || !order.working() {
+            return Err(TradingBotError::ExchangeError("Unknown order sent.".into()));
+        }
+        //release whatever is still locked
+        let left = order.orig_qty - order.executed_qty;
+        let (asset, locked) = match order.side {
+            OrderSide::Buy => (quote, left * order.price.unwrap_or(0.0)),
+            OrderSide::Sell => (base, left),
+        };
+        let balance = state.balance_mut(&asset);
+        balance.1 -= locked;
+        balance.0 += locked;
+        //cancelling either leg of an OCO cancels the whole list
+        for other in state.orders.values_mut() {
+            let same_list = order.list_id.is_some() && other.list_id == order.list_id;
+            if other.working() && (other.id == order_id || same_list) {
+                other.status = "CANCELED";
+            }
+        }
+        Ok(OrderCanceled {
+            symbol: symbol.into(),
+            orig_client_order_id: Some(order.client_order_id()),
+            order_id: Some(order_id),
+            client_order_id: Some(format!("mock_{order_id}")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arc_swap::ArcSwap;
+    use diesel::RunQueryDsl;
+
+    use super::{Exchange, MockExchange, OrderRequest, OrderSide};
+    use crate::{
+        binance_wrapped::BinanceWrapped,
+        config::Config,
+        db::{establish_connection, test::database},
+        exchange::{governor::GovernedExchange, OrderOptions},
+        market_data::MarketData,
+        models::{BinanceAccount, NewBinanceAccount, NewUser},
+        sizing::Sizing,
+        transaction_state::{self, TransactionState},
+    };
+
+    const TRADER: i64 = 1;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    //Account on the mock with the trader clocked in
+    fn account(mock: Arc<MockExchange>) -> BinanceWrapped {
+        account_with(mock, Config::first_setup().unwrap())
+    }
+
+    fn account_with(mock: Arc<MockExchange>, config: Config) -> BinanceWrapped {
+        let mut connection = establish_connection();
+        diesel::insert_into(crate::schema::users::table)
+            .values(NewUser { id: TRADER, tag: "trader" })
+            .execute(&mut connection)
+            .unwrap();
+        let account = diesel::insert_into(crate::schema::binance_accounts::table)
+            .values(NewBinanceAccount {
+                name: "mock".into(),
+                is_paper: true,
+                api_key: None,
+                secret: None,
+                is_simulated: false,
+            })
+            .get_result::<BinanceAccount>(&mut connection)
+            .unwrap();
+        let binance = BinanceWrapped::on_exchange(
+            account.id,
+            account.name,
+            GovernedExchange::new(mock, None),
+            Arc::new(ArcSwap::from_pointee(config)),
+            Arc::new(MarketData::new()),
+        );
+        binance.lock(TRADER).unwrap();
+        binance
+    }
+
+    fn state(binance: &BinanceWrapped) -> TransactionState {
+        TransactionState::of(&binance.get_transaction().unwrap().unwrap()).unwrap()
+    }
+
+    fn states(transaction_id: i32) -> Vec<String> {
+        transaction_state::events(transaction_id)
+            .unwrap()
+            .into_iter()
+            .map(|event| event.to_state)
+            .collect()
+    }
+
+    fn balance(mock: &MockExchange, asset: &str) -> (f64, f64) {
+        let balance = mock.get_balance(asset).unwrap();
+        (balance.free.parse().unwrap(), balance.locked.parse().unwrap())
+    }
+
+    #[test]
+    fn round_trip_realizes_pnl() {
+        let _database = database();
+        let mock = Arc::new(MockExchange::with_defaults());
+        let binance = account(mock.clone());
+
+        //a limit over the market takes at the last price and hands back what it locked over it
+        let buy = binance
+            .buy(Some("BTCUSDT".into()), Some(20100.0), Some(Sizing::Base(0.1)), OrderOptions::default())
+            .unwrap();
+        assert_eq!(buy.status, "FILLED");
+        assert!(close(buy.cummulative_quote_qty, 2000.0));
+        let (free, locked) = balance(&mock, "USDT");
+        assert!(close(free, 8000.0) && close(locked, 0.0));
+
+        let transaction = binance.get_transaction().unwrap().unwrap();
+        assert_eq!(state(&binance), TransactionState::BuyPending);
+
+        //settled the way the order handler does once the buy is final
+        binance.refresh_orders(transaction.id).unwrap();
+        let position = binance.position(transaction.id).unwrap();
+        assert!(close(position.qty, 0.1));
+        assert!(close(position.avg_cost, 20000.0));
+        transaction_state::transition(&transaction, TransactionState::Long, "buy filled").unwrap();
+
+        //a limit over the market rests until the price gets there
+        let sell = binance.sell(Some(21000.0), None, OrderOptions::default()).unwrap();
+        assert_eq!(sell.status, "NEW");
+        let transaction = binance.get_transaction().unwrap().unwrap();
+        assert_eq!(state(&binance), TransactionState::SellPending);
+
+        mock.set_price("BTCUSDT", 21500.0);
+        let orders = binance.refresh_orders(transaction.id).unwrap();
+        assert!(orders.iter().all(|order| order.status == "FILLED"));
+        let position = binance.position(transaction.id).unwrap();
+        assert!(close(position.qty, 0.0));
+        assert!(close(position.realized, 100.0));
+        transaction_state::transition(&transaction, TransactionState::Closed, "position sold").unwrap();
+
+        assert_eq!(states(transaction.id), ["IDLE", "BUY_PENDING", "LONG", "SELL_PENDING", "CLOSED"]);
+    }
+
+    #[test]
+    fn partial_fill_then_cancel_keeps_what_filled() {
+        let _database = database();
+        let mock = Arc::new(MockExchange::with_defaults());
+        let binance = account(mock.clone());
+
+        let buy = binance
+            .buy(Some("BTCUSDT".into()), Some(19000.0), Some(Sizing::Base(0.1)), OrderOptions::default())
+            .unwrap();
+        assert_eq!(buy.status, "NEW");
+        mock.fill_order(buy.order_id, 0.04).unwrap();
+
+        //still working so the transaction waits on it
+        let transaction = binance.get_transaction().unwrap().unwrap();
+        let orders = binance.refresh_orders(transaction.id).unwrap();
+        assert_eq!(orders[0].status, "PARTIALLY_FILLED");
+        assert!(close(orders[0].executed_qty, 0.04));
+        assert_eq!(binance.working_orders().unwrap().len(), 1);
+        assert_eq!(state(&binance), TransactionState::BuyPending);
+
+        //cancelling the rest leaves the transaction holding what was bought
+        binance.cancel_orders(&[buy.order_id]).unwrap();
+        assert_eq!(state(&binance), TransactionState::Long);
+        let position = binance.position(transaction.id).unwrap();
+        assert!(close(position.qty, 0.04));
+        assert!(close(position.avg_cost, 19000.0));
+        let (free, locked) = balance(&mock, "USDT");
+        assert!(close(free, 10000.0 - 0.04 * 19000.0) && close(locked, 0.0));
+        assert!(close(balance(&mock, "BTC").0, 0.04));
+    }
+
+    #[test]
+    fn cancelled_buy_goes_back_to_idle() {
+        let _database = database();
+        let mock = Arc::new(MockExchange::with_defaults());
+        let binance = account(mock.clone());
+
+        let buy = binance
+            .buy(Some("BTCUSDT".into()), Some(19000.0), Some(Sizing::Base(0.1)), OrderOptions::default())
+            .unwrap();
+        assert_eq!(balance(&mock, "USDT"), (8100.0, 1900.0));
+        let transaction = binance.get_transaction().unwrap().unwrap();
+
+        binance.cancel_orders(&[buy.order_id]).unwrap();
+        assert_eq!(state(&binance), TransactionState::Idle);
+        assert_eq!(mock.open_orders("BTCUSDT").unwrap().len(), 0);
+        let (free, locked) = balance(&mock, "USDT");
+        assert!(close(free, 10000.0) && close(locked, 0.0));
+        assert_eq!(states(transaction.id), ["IDLE", "BUY_PENDING", "IDLE"]);
+
+        //a cancelled order can't be cancelled again
+        assert!(mock.cancel_order("BTCUSDT", buy.order_id).is_err());
+    }
+
+    #[test]
+    fn rejected_amend_settles_the_cancelled_order() {
+        let _database = database();
+        let mock = Arc::new(MockExchange::with_defaults());
+        let binance = account(mock.clone());
+
+        let options = OrderOptions { post_only: true, ..Default::default() };
+        let buy = binance
+            .buy(Some("BTCUSDT".into()), Some(19000.0), Some(Sizing::Base(0.1)), options)
+            .unwrap();
+        let transaction = binance.get_transaction().unwrap().unwrap();
+
+        //a post-only replacement over the market would take so the exchange turns it away
+        let (cancelled, replacement) = binance.amend(20500.0).unwrap();
+        assert_eq!(cancelled.order_id, Some(buy.order_id as i64));
+        assert!(replacement.is_err());
+        assert_eq!(mock.open_orders("BTCUSDT").unwrap().len(), 0);
+        assert_eq!(states(transaction.id), ["IDLE", "BUY_PENDING", "IDLE"]);
+        let (free, locked) = balance(&mock, "USDT");
+        assert!(close(free, 10000.0) && close(locked, 0.0));
+    }
+
+    #[test]
+    fn halt_cancels_every_symbol_and_sells_at_market_regardless() {
+        let _database = database();
+        let mock = Arc::new(MockExchange::with_defaults());
+        mock.add_symbol("ETHUSDT", "ETH", "USDT");
+        mock.set_price("ETHUSDT", 1000.0);
+        Config::first_setup().unwrap();
+        {
+            use crate::schema::configs::dsl;
+            use diesel::{ExpressionMethods, QueryDsl};
+            diesel::update(dsl::configs.filter(dsl::section.eq("trading")).filter(dsl::key.eq("market_orders")))
+                .set(dsl::value.eq(Some("false")))
+                .execute(&mut establish_connection())
+                .unwrap();
+        }
+        let binance = account_with(mock.clone(), Config::load().unwrap());
+
+        //left behind on a symbol the account is no longer trading
+        mock.place_order(&OrderRequest::limit("ETHUSDT", OrderSide::Buy, 1.0, 900.0)).unwrap();
+        let buy = binance
+            .buy(Some("BTCUSDT".into()), Some(19000.0), Some(Sizing::Base(0.1)), OrderOptions::default())
+            .unwrap();
+        mock.fill_order(buy.order_id, 0.04).unwrap();
+
+        let done = binance.halt(true).unwrap();
+        assert!(done.iter().any(|line| line.contains("on ETHUSDT")));
+        assert!(done.iter().any(|line| line.starts_with("Market selling 0.04 BTCUSDT")));
+        assert!(done.iter().any(|line| line.starts_with("Market orders are off")));
+        assert_eq!(mock.all_open_orders().unwrap().len(), 0);
+        let (free, locked) = balance(&mock, "USDT");
+        assert!(close(free, 10000.0 + 0.04 * (20000.0 - 19000.0)) && close(locked, 0.0));
+        assert!(close(balance(&mock, "BTC").0, 0.0));
+        assert_eq!(state(&binance), TransactionState::SellPending);
+    }
+}
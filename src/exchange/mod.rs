@@ -0,0 +1,307 @@
+use binance::model::{AccountInformation, Balance, FillInfo, Order, OrderCanceled, Symbol, Transaction};
+
+use crate::error::TradingBotError;
+
+pub mod binance_client;
+pub mod filters;
+pub mod governor;
+pub mod mock;
+pub mod simulated;
+pub mod user_stream;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+impl OrderSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderSide::Buy => "BUY",
+            OrderSide::Sell => "SELL",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderQuantity {
+    //Amount of the base asset eg BTC
+    Base(f64),
+    //Amount of the quote asset to spend eg USDT only valid for market orders
+    Quote(f64),
+}
+
+/// How long a limit order stays on the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeInForce {
+    //good till cancelled, rests until it fills or is cancelled
+    #[default]
+    Gtc,
+    //immediate or cancel, fills what it can straight away and expires the rest
+    Ioc,
+    //fill or kill, fills completely straight away or expires
+    Fok,
+}
+impl TimeInForce {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeInForce::Gtc => "GTC",
+            TimeInForce::Ioc => "IOC",
+            TimeInForce::Fok => "FOK",
+        }
+    }
+
+    pub fn parse(time_in_force: &str) -> Result<Self, TradingBotError> {
+        Ok(match time_in_force.to_uppercase().as_str() {
+            "GTC" => TimeInForce::Gtc,
+            "IOC" => TimeInForce::Ioc,
+            "FOK" => TimeInForce::Fok,
+            other => return Err(TradingBotError::ParsingDataError(format!("Unknown time in force {other}"))),
+        })
+    }
+}
+
+//How a trader wants a limit order handled, the default is a plain GTC limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OrderOptions {
+    pub time_in_force: TimeInForce,
+    pub post_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderRequest {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub quantity: OrderQuantity,
+    //None places a market order
+    pub price: Option<f64>,
+    //sent as newClientOrderId so the order can be found again if the request fails
+    pub client_order_id: Option<String>,
+    //only used by limit orders
+    pub time_in_force: TimeInForce,
+    //placed as LIMIT_MAKER which the exchange rejects rather than let it take
+    pub post_only: bool,
+}
+impl OrderRequest {
+    pub fn limit(symbol: &str, side: OrderSide, quantity: f64, price: f64) -> Self {
+        Self {
+            symbol: symbol.into(),
+            side,
+            quantity: OrderQuantity::Base(quantity),
+            price: Some(price),
+            client_order_id: None,
+            time_in_force: TimeInForce::Gtc,
+            post_only: false,
+        }
+    }
+    pub fn market(symbol: &str, side: OrderSide, quantity: OrderQuantity) -> Self {
+        Self {
+            symbol: symbol.into(),
+            side,
+            quantity,
+            price: None,
+            client_order_id: None,
+            time_in_force: TimeInForce::Gtc,
+            post_only: false,
+        }
+    }
+
+    //Order type the exchange knows the request as
+    pub fn type_name(&self) -> &'static str {
+        match (self.price, self.post_only) {
+            (None, _) => "MARKET",
+            (Some(_), true) => "LIMIT_MAKER",
+            (Some(_), false) => "LIMIT",
+        }
+    }
+
+    //Time in force the order is placed with, market and post-only orders don't take one
+    pub fn time_in_force(&self) -> Option<&'static str> {
+        match self.type_name() {
+            "LIMIT" => Some(self.time_in_force.as_str()),
+            _ => None,
+        }
+    }
+}
+
+/// One-cancels-the-other exit for a long position.
+///
+/// A take-profit limit above the market and a stop-limit below it are placed as a single
+/// order list, when either leg fills the other expires.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcoRequest {
+    pub symbol: String,
+    pub quantity: f64,
+    pub take_profit: f64,
+    //price the stop leg triggers at
+    pub stop_price: f64,
+    //limit the stop leg sells at once triggered
+    pub stop_limit_price: f64,
+    //list client id, the legs get ids derived from it with `oco_leg_ids`
+    pub client_order_id: Option<String>,
+}
+
+//Client order ids of the take profit and stop loss legs of an OCO list
+pub fn oco_leg_ids(client_order_id: &str) -> (String, String) {
+    (format!("{client_order_id}-tp"), format!("{client_order_id}-sl"))
+}
+
+//Whether an order is a leg of an OCO list going by its client order id
+pub fn is_oco_leg(client_order_id: &str) -> bool {
+    client_order_id.ends_with("-tp") || client_order_id.ends_with("-sl")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OcoOrder {
+    pub order_list_id: i64,
+    pub take_profit_id: u64,
+    pub stop_loss_id: u64,
+}
+
+/// What a failed send says about whether the order reached the exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendFailure {
+    //the exchange answered and refused the order
+    Rejected,
+    //refused before it was looked at so it is safe to send again
+    Retry,
+    //no usable answer so the order may or may not exist
+    Unknown,
+}
+
+//The binance crate parses 400 responses into their error code and keeps only the status of
+//any other failed response, as "Received response: <status>"
+pub fn http_status(err: &binance::errors::Error) -> Option<u16> {
+    match err.kind() {
+        binance::errors::ErrorKind::Msg(msg) => msg.strip_prefix("Received response: ")?.parse().ok(),
+        _ => None,
+    }
+}
+
+pub fn send_failure(err: &TradingBotError) -> SendFailure {
+    use binance::errors::ErrorKind;
+    match err {
+        TradingBotError::BinanceError(err) => match err.kind() {
+            //-1007 timed out waiting for the matching engine, the order may still go through
+            ErrorKind::BinanceError(response) if response.code == -1007 => SendFailure::Unknown,
+            //-1003 too many requests, -1015 too many new orders
+            ErrorKind::BinanceError(response) if response.code == -1003 || response.code == -1015 => SendFailure::Retry,
+            ErrorKind::BinanceError(_) => SendFailure::Rejected,
+            ErrorKind::Msg(_) if matches!(http_status(err), Some(429 | 418)) => SendFailure::Retry,
+            //timeouts, dropped connections, 5xx and responses that didn't parse
+            _ => SendFailure::Unknown,
+        },
+        TradingBotError::RateLimited(_) => SendFailure::Retry,
+        _ => SendFailure::Rejected,
+    }
+}
+
+//An order looked up after the fact in the shape place_order returns
+pub fn order_to_transaction(order: Order) -> Transaction {
+    let parse = |value: &str| value.parse::<f64>().unwrap_or(0.0);
+    Transaction {
+        order_list_id: if order.order_list_id < 0 { None } else { Some(order.order_list_id) },
+        transact_time: order.time,
+        orig_qty: parse(&order.orig_qty),
+        executed_qty: parse(&order.executed_qty),
+        cummulative_quote_qty: parse(&order.cummulative_quote_qty),
+        fills: None,
+        symbol: order.symbol,
+        order_id: order.order_id,
+        client_order_id: order.client_order_id,
+        price: order.price,
+        stop_price: order.stop_price,
+        status: order.status,
+        time_in_force: order.time_in_force,
+        type_name: order.type_name,
+        side: order.side,
+    }
+}
+
+//The reverse for an order response that has to go through the order tracker
+pub fn transaction_to_order(transaction: &Transaction) -> Order {
+    Order {
+        symbol: transaction.symbol.clone(),
+        order_id: transaction.order_id,
+        order_list_id: transaction.order_list_id.unwrap_or(-1),
+        client_order_id: transaction.client_order_id.clone(),
+        price: transaction.price,
+        orig_qty: transaction.orig_qty.to_string(),
+        executed_qty: transaction.executed_qty.to_string(),
+        cummulative_quote_qty: transaction.cummulative_quote_qty.to_string(),
+        status: transaction.status.clone(),
+        time_in_force: transaction.time_in_force.clone(),
+        type_name: transaction.type_name.clone(),
+        side: transaction.side.clone(),
+        stop_price: transaction.stop_price,
+        iceberg_qty: "0".into(),
+        time: transaction.transact_time,
+        update_time: transaction.transact_time,
+        is_working: transaction.status == "NEW",
+        orig_quote_order_qty: "0".into(),
+    }
+}
+
+//Statuses an order never leaves, REJECTED also covers sends the exchange refused
+pub fn is_final_status(status: &str) -> bool {
+    matches!(status, "FILLED" | "CANCELED" | "EXPIRED" | "EXPIRED_IN_MATCH" | "REJECTED")
+}
+
+//A trade on the account as its trade list reports it
+#[derive(Debug, Clone)]
+pub struct AccountTrade {
+    pub order_id: u64,
+    pub side: OrderSide,
+    pub fill: FillInfo,
+    pub time: u64,
+}
+
+//Listen key and websocket endpoint for an account's user data stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserStreamKey {
+    pub listen_key: String,
+    pub endpoint: String,
+}
+
+/// Everything the bot needs from a trading venue.
+///
+/// `BinanceExchange` talks to the real api while `MockExchange` keeps everything in memory
+/// so the order flow can be exercised without network access or keys.
+pub trait Exchange: Send + Sync {
+    fn get_account(&self) -> Result<AccountInformation, TradingBotError>;
+    fn get_balance(&self, asset: &str) -> Result<Balance, TradingBotError>;
+    fn get_symbol_info(&self, symbol: &str) -> Result<Symbol, TradingBotError>;
+    //Every symbol currently open for trading
+    fn get_symbols(&self) -> Result<Vec<String>, TradingBotError>;
+    //Last traded price used to value market orders
+    fn get_price(&self, symbol: &str) -> Result<f64, TradingBotError>;
+    fn place_order(&self, order: &OrderRequest) -> Result<Transaction, TradingBotError>;
+    fn place_oco(&self, order: &OcoRequest) -> Result<OcoOrder, TradingBotError>;
+    fn order_status(&self, symbol: &str, order_id: u64) -> Result<Order, TradingBotError>;
+    //None when the exchange has never seen the client order id
+    fn order_by_client_id(&self, symbol: &str, client_order_id: &str) -> Result<Option<Order>, TradingBotError>;
+    fn cancel_order(&self, symbol: &str, order_id: u64) -> Result<OrderCanceled, TradingBotError>;
+    //Every order on the symbol still working on the exchange whoever placed it
+    fn open_orders(&self, symbol: &str) -> Result<Vec<Order>, TradingBotError>;
+    //Every working order on the account across all symbols, costly so only for /halt
+    fn all_open_orders(&self) -> Result<Vec<Order>, TradingBotError>;
+    //The account's latest trades on the symbol newest last
+    fn recent_trades(&self, _symbol: &str, _limit: u16) -> Result<Vec<AccountTrade>, TradingBotError> {
+        Ok(Vec::new())
+    }
+    //Trades that filled the order, for orders whose fills were never seen
+    fn order_fills(&self, _symbol: &str, _order_id: u64) -> Result<Vec<FillInfo>, TradingBotError> {
+        Ok(Vec::new())
+    }
+    //Websocket endpoint for public market streams, None to always use get_price
+    fn market_stream_endpoint(&self) -> Option<String> {
+        None
+    }
+    //Venues without a user data stream are only ever polled
+    fn start_user_stream(&self) -> Result<Option<UserStreamKey>, TradingBotError> {
+        Ok(None)
+    }
+    fn keep_alive_user_stream(&self, _listen_key: &str) -> Result<(), TradingBotError> {
+        Ok(())
+    }
+}
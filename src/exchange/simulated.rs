@@ -0,0 +1,573 @@
+use binance::{
+    api::Binance,
+    general::General,
+    market::Market,
+    model::{AccountInformation, Balance, FillInfo, Order, OrderCanceled, Symbol, Transaction},
+};
+use chrono::Utc;
+use diesel::{Connection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SqliteConnection};
+use tracing::{debug, trace};
+
+use crate::{
+    db::establish_connection,
+    error::TradingBotError,
+    models::{NewPaperBalance, NewPaperOrder, PaperBalance, PaperOrder},
+};
+
+use super::{oco_leg_ids, AccountTrade, Exchange, OcoOrder, OcoRequest, OrderQuantity, OrderRequest, OrderSide, TimeInForce};
+
+impl PaperOrder {
+    fn client_order_id(&self) -> String {
+        self.client_order_id.clone().unwrap_or_else(|| format!("paper_{}", self.id))
+    }
+
+    //binance reports GTC for the order types that don't take a time in force
+    fn time_in_force(&self) -> String {
+        self.time_in_force.clone().unwrap_or_else(|| "GTC".into())
+    }
+
+    fn to_order(&self) -> Order {
+        Order {
+            symbol: self.symbol.clone(),
+            order_id: self.id as u64,
+            order_list_id: self.order_list_id.map(|id| id as i64).unwrap_or(-1),
+            client_order_id: self.client_order_id(),
+            price: self.price.unwrap_or(0.0),
+            orig_qty: self.orig_qty.to_string(),
+            executed_qty: self.executed_qty.to_string(),
+            cummulative_quote_qty: self.cummulative_quote_qty.to_string(),
+            status: self.status.clone(),
+            time_in_force: self.time_in_force(),
+            type_name: self.type_name.clone(),
+            side: self.side.clone(),
+            stop_price: self.stop_price.unwrap_or(0.0),
+            iceberg_qty: "0".into(),
+            time: self.order_time.timestamp_millis() as u64,
+            update_time: self.order_time.timestamp_millis() as u64,
+            is_working: self.status == "NEW",
+            orig_quote_order_qty: "0".into(),
+        }
+    }
+
+    fn to_transaction(&self) -> Transaction {
+        let fills = if self.executed_qty > 0.0 {
+            Some(vec![FillInfo {
+                price: self.cummulative_quote_qty / self.executed_qty,
+                qty: self.executed_qty,
+                commission: self.commission,
+                commission_asset: if self.side == "BUY" { self.base_asset.clone() } else { self.quote_asset.clone() },
+                trade_id: None,
+            }])
+        } else {
+            None
+        };
+        Transaction {
+            symbol: self.symbol.clone(),
+            order_id: self.id as u64,
+            order_list_id: self.order_list_id.map(|id| id as i64),
+            client_order_id: self.client_order_id(),
+            transact_time: self.order_time.timestamp_millis() as u64,
+            price: self.price.unwrap_or(0.0),
+            orig_qty: self.orig_qty,
+            executed_qty: self.executed_qty,
+            cummulative_quote_qty: self.cummulative_quote_qty,
+            stop_price: self.stop_price.unwrap_or(0.0),
+            status: self.status.clone(),
+            time_in_force: self.time_in_force(),
+            type_name: self.type_name.clone(),
+            side: self.side.clone(),
+            fills,
+        }
+    }
+}
+
+fn balance(connection: &mut SqliteConnection, account_id: i32, asset: &str) -> Result<(f64, f64), TradingBotError> {
+    use crate::schema::paper_balances::dsl;
+    let balance = dsl::paper_balances
+        .filter(dsl::account_id.eq(account_id))
+        .filter(dsl::asset.eq(asset))
+        .first::<PaperBalance>(connection);
+    match balance {
+        Ok(balance) => Ok((balance.free, balance.locked)),
+        Err(diesel::result::Error::NotFound) => Ok((0.0, 0.0)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+//Moves funds between free and locked or in and out of the account
+fn adjust(
+    connection: &mut SqliteConnection,
+    account_id: i32,
+    asset: &str,
+    free_delta: f64,
+    locked_delta: f64,
+) -> Result<(), TradingBotError> {
+    use crate::schema::paper_balances::dsl;
+    let (free, locked) = balance(connection, account_id, asset)?;
+    let (free, locked) = (free + free_delta, locked + locked_delta);
+    //float dust from fills shouldn't read as a negative balance
+    let (free, locked) = (free.max(0.0), locked.max(0.0));
+    let updated = diesel::update(
+        dsl::paper_balances
+            .filter(dsl::account_id.eq(account_id))
+            .filter(dsl::asset.eq(asset)),
+    )
+    .set((dsl::free.eq(free), dsl::locked.eq(locked)))
+    .execute(connection)?;
+    if updated == 0 {
+        diesel::insert_into(dsl::paper_balances)
+            .values(NewPaperBalance { account_id, asset, free, locked })
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Paper trading account kept entirely in the database.
+///
+/// Prices come from the public mainnet book ticker so fills look like the real market without
+/// needing keys or the testnet. Market orders take the best bid/ask, limit orders rest until the
+/// book crosses them and are matched whenever their status is polled. Stop-limit legs of an OCO
+/// only start resting once the book reaches their stop.
+pub struct SimulatedExchange {
+    account_id: i32,
+    commission_rate: f64,
+    general: General,
+    market: Market,
+}
+
+impl SimulatedExchange {
+    pub fn new(account_id: i32, commission_rate: f64) -> Self {
+        Self {
+            account_id,
+            commission_rate,
+            general: Binance::new(None, None),
+            market: Binance::new(None, None),
+        }
+    }
+
+    /// Credit a starting balance to a freshly created simulated account
+    pub fn seed(account_id: i32, asset: &str, amount: f64) -> Result<(), TradingBotError> {
+        let mut connection = establish_connection();
+        adjust(&mut connection, account_id, asset, amount, 0.0)
+    }
+
+    //(bid, ask)
+    fn book(&self, symbol: &str) -> Result<(f64, f64), TradingBotError> {
+        let ticker = self.market.get_book_ticker(symbol)?;
+        Ok((ticker.bid_price, ticker.ask_price))
+    }
+
+    //Fill an order at price releasing the funds that were locked at lock_price
+    fn fill(
+        &self,
+        connection: &mut SqliteConnection,
+        order: &PaperOrder,
+        price: f64,
+        lock_price: f64,
+    ) -> Result<(), TradingBotError> {
+        let qty = order.orig_qty;
+        let quote_qty = qty * price;
+        let commission;
+        if order.side == "BUY" {
+            commission = qty * self.commission_rate;
+            adjust(connection, self.account_id, &order.quote_asset, qty * (lock_price - price), -(qty * lock_price))?;
+            adjust(connection, self.account_id, &order.base_asset, qty - commission, 0.0)?;
+        } else {
+            commission = quote_qty * self.commission_rate;
+            adjust(connection, self.account_id, &order.base_asset, 0.0, -qty)?;
+            adjust(connection, self.account_id, &order.quote_asset, quote_qty - commission, 0.0)?;
+        }
+        use crate::schema::paper_orders::dsl;
+        diesel::update(dsl::paper_orders.filter(dsl::id.eq(order.id)))
+            .set((
+                dsl::executed_qty.eq(qty),
+                dsl::cummulative_quote_qty.eq(quote_qty),
+                dsl::commission.eq(commission),
+                dsl::status.eq("FILLED"),
+            ))
+            .execute(connection)?;
+        //the other leg of an OCO shared the locked funds so it just expires
+        if let Some(list_id) = order.order_list_id {
+            diesel::update(
+                dsl::paper_orders
+                    .filter(dsl::order_list_id.eq(list_id))
+                    .filter(dsl::status.eq("NEW")),
+            )
+            .set(dsl::status.eq("EXPIRED"))
+            .execute(connection)?;
+        }
+        debug!("Paper order {} filled {qty} @{price}", order.id);
+        Ok(())
+    }
+
+    //Fill any resting orders on the symbol the current book has crossed
+    fn match_orders(&self, symbol: &str) -> Result<(), TradingBotError> {
+        let (bid, ask) = self.book(symbol)?;
+        let mut connection = establish_connection();
+        connection.transaction::<_, TradingBotError, _>(|connection| {
+            use crate::schema::paper_orders::dsl;
+            let resting = dsl::paper_orders
+                .filter(dsl::account_id.eq(self.account_id))
+                .filter(dsl::symbol.eq(symbol))
+                .filter(dsl::status.eq("NEW"))
+                .load::<PaperOrder>(connection)?;
+            for order in resting.iter() {
+                //an earlier fill may have expired this order's OCO sibling
+                let order = &self.get_order(connection, order.id)?;
+                if order.status != "NEW" {
+                    continue;
+                }
+                if let (Some(stop), false) = (order.stop_price, order.triggered) {
+                    let passed = if order.side == "BUY" { ask >= stop } else { bid <= stop };
+                    if !passed {
+                        continue;
+                    }
+                    trace!("Stop triggered for paper order {}", order.id);
+                    diesel::update(dsl::paper_orders.filter(dsl::id.eq(order.id)))
+                        .set(dsl::triggered.eq(true))
+                        .execute(connection)?;
+                }
+                let Some(limit) = order.price else {
+                    continue;
+                };
+                let crossed = if order.side == "BUY" { ask <= limit } else { bid >= limit };
+                if crossed {
+                    trace!("Book crossed paper order {}", order.id);
+                    self.fill(connection, order, limit, limit)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn get_order(&self, connection: &mut SqliteConnection, order_id: i32) -> Result<PaperOrder, TradingBotError> {
+        use crate::schema::paper_orders::dsl;
+        match dsl::paper_orders
+            .filter(dsl::account_id.eq(self.account_id))
+            .filter(dsl::id.eq(order_id))
+            .first::<PaperOrder>(connection)
+        {
+            Ok(order) => Ok(order),
+            Err(diesel::result::Error::NotFound) => Err(TradingBotError::ExchangeError("Order does not exist.".into())),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl Exchange for SimulatedExchange {
+    fn get_account(&self) -> Result<AccountInformation, TradingBotError> {
+        use crate::schema::paper_balances::dsl;
+        let mut connection = establish_connection();
+        let balances = dsl::paper_balances
+            .filter(dsl::account_id.eq(self.account_id))
+            .load::<PaperBalance>(&mut connection)?;
+        Ok(AccountInformation {
+            maker_commission: self.commission_rate as f32,
+            taker_commission: self.commission_rate as f32,
+            buyer_commission: 0.0,
+            seller_commission: 0.0,
+            can_trade: true,
+            can_withdraw: false,
+            can_deposit: false,
+            balances: balances
+                .into_iter()
+                .map(|b| Balance {
+                    asset: b.asset,
+                    free: b.free.to_string(),
+                    locked: b.locked.to_string(),
+                })
+                .collect(),
+        })
+    }
+
+    fn get_balance(&self, asset: &str) -> Result<Balance, TradingBotError> {
+        let mut connection = establish_connection();
+        let (free, locked) = balance(&mut connection, self.account_id, asset)?;
+        Ok(Balance {
+            asset: asset.into(),
+            free: free.to_string(),
+            locked: locked.to_string(),
+        })
+    }
+
+    fn get_symbol_info(&self, symbol: &str) -> Result<Symbol, TradingBotError> {
+        Ok(self.general.get_symbol_info(symbol)?)
+    }
+
+    fn get_symbols(&self) -> Result<Vec<String>, TradingBotError> {
+        let info = self.general.exchange_info()?;
+        Ok(info
+            .symbols
+            .into_iter()
+            .filter(|s| s.status == "TRADING")
+            .map(|s| s.symbol)
+            .collect())
+    }
+
+    fn get_price(&self, symbol: &str) -> Result<f64, TradingBotError> {
+        Ok(self.market.get_price(symbol)?.price)
+    }
+
+    fn place_order(&self, request: &OrderRequest) -> Result<Transaction, TradingBotError> {
+        let info = self.get_symbol_info(&request.symbol)?;
+        let (bid, ask) = self.book(&request.symbol)?;
+        let touch = match request.side {
+            OrderSide::Buy => ask,
+            OrderSide::Sell => bid,
+        };
+        let qty = match request.quantity {
+            OrderQuantity::Base(qty) => qty,
+            OrderQuantity::Quote(quote_qty) => {
+                if request.price.is_some() {
+                    return Err(TradingBotError::ExchangeError("Limit orders must be sized in the base asset".into()));
+                }
+                quote_qty / touch
+            }
+        };
+        if qty <= 0.0 {
+            return Err(TradingBotError::ExchangeError("Invalid quantity.".into()));
+        }
+        let lock_price = request.price.unwrap_or(touch);
+        let marketable = match (request.side, request.price) {
+            (_, None) => true,
+            (OrderSide::Buy, Some(limit)) => ask <= limit,
+            (OrderSide::Sell, Some(limit)) => bid >= limit,
+        };
+        if request.post_only && marketable {
+            return Err(TradingBotError::ExchangeError("Order would immediately match and take.".into()));
+        }
+        //paper orders fill whole so IOC and FOK either fill now or expire
+        let expires = request.price.is_some() && !marketable && request.time_in_force != TimeInForce::Gtc;
+
+        let mut connection = establish_connection();
+        let order = connection.transaction::<_, TradingBotError, _>(|connection| {
+            let (asset, needed) = match request.side {
+                OrderSide::Buy => (&info.quote_asset, qty * lock_price),
+                OrderSide::Sell => (&info.base_asset, qty),
+            };
+            let (free, _) = balance(connection, self.account_id, asset)?;
+            if free < needed {
+                return Err(TradingBotError::ExchangeError(
+                    "Account has insufficient balance for requested action.".into(),
+                ));
+            }
+            if !expires {
+                adjust(connection, self.account_id, asset, -needed, needed)?;
+            }
+
+            use crate::schema::paper_orders::dsl;
+            let order = diesel::insert_into(dsl::paper_orders)
+                .values(NewPaperOrder {
+                    account_id: self.account_id,
+                    symbol: &request.symbol,
+                    base_asset: &info.base_asset,
+                    quote_asset: &info.quote_asset,
+                    side: request.side.as_str(),
+                    price: request.price,
+                    orig_qty: qty,
+                    status: if expires { "EXPIRED" } else { "NEW" },
+                    order_time: Utc::now(),
+                    stop_price: None,
+                    order_list_id: None,
+                    client_order_id: request.client_order_id.as_deref(),
+                    type_name: request.type_name(),
+                    time_in_force: request.time_in_force(),
+                })
+                .get_result::<PaperOrder>(connection)?;
+
+            if marketable {
+                self.fill(connection, &order, touch, lock_price)?;
+            }
+            self.get_order(connection, order.id)
+        })?;
+        Ok(order.to_transaction())
+    }
+
+    fn place_oco(&self, request: &OcoRequest) -> Result<OcoOrder, TradingBotError> {
+        let info = self.get_symbol_info(&request.symbol)?;
+        let last_price = self.get_price(&request.symbol)?;
+        if !(request.take_profit > last_price && last_price > request.stop_price) {
+            return Err(TradingBotError::ExchangeError(
+                "The relationship of the prices for the orders is not correct.".into(),
+            ));
+        }
+        let mut connection = establish_connection();
+        connection.transaction::<_, TradingBotError, _>(|connection| {
+            //both legs sell the same coins so they are only locked once
+            let (free, _) = balance(connection, self.account_id, &info.base_asset)?;
+            if free < request.quantity {
+                return Err(TradingBotError::ExchangeError(
+                    "Account has insufficient balance for requested action.".into(),
+                ));
+            }
+            adjust(connection, self.account_id, &info.base_asset, -request.quantity, request.quantity)?;
+
+            use crate::schema::paper_orders::dsl;
+            let (take_profit_client_id, stop_loss_client_id) = match request.client_order_id.as_deref().map(oco_leg_ids) {
+                Some((take_profit, stop_loss)) => (Some(take_profit), Some(stop_loss)),
+                None => (None, None),
+            };
+            let leg = |price: f64, stop_price: Option<f64>, order_list_id: Option<i32>, type_name, time_in_force| NewPaperOrder {
+                account_id: self.account_id,
+                symbol: &request.symbol,
+                base_asset: &info.base_asset,
+                quote_asset: &info.quote_asset,
+                side: OrderSide::Sell.as_str(),
+                price: Some(price),
+                orig_qty: request.quantity,
+                status: "NEW",
+                order_time: Utc::now(),
+                stop_price,
+                order_list_id,
+                client_order_id: None,
+                type_name,
+                time_in_force,
+            };
+            //the list is named after its take profit leg
+            let take_profit = diesel::insert_into(dsl::paper_orders)
+                .values(NewPaperOrder {
+                    client_order_id: take_profit_client_id.as_deref(),
+                    ..leg(request.take_profit, None, None, "LIMIT_MAKER", None)
+                })
+                .get_result::<PaperOrder>(connection)?;
+            diesel::update(dsl::paper_orders.filter(dsl::id.eq(take_profit.id)))
+                .set(dsl::order_list_id.eq(Some(take_profit.id)))
+                .execute(connection)?;
+            let stop_loss = diesel::insert_into(dsl::paper_orders)
+                .values(NewPaperOrder {
+                    client_order_id: stop_loss_client_id.as_deref(),
+                    ..leg(request.stop_limit_price, Some(request.stop_price), Some(take_profit.id), "STOP_LOSS_LIMIT", Some("GTC"))
+                })
+                .get_result::<PaperOrder>(connection)?;
+            Ok(OcoOrder {
+                order_list_id: take_profit.id as i64,
+                take_profit_id: take_profit.id as u64,
+                stop_loss_id: stop_loss.id as u64,
+            })
+        })
+    }
+
+    fn order_status(&self, symbol: &str, order_id: u64) -> Result<Order, TradingBotError> {
+        self.match_orders(symbol)?;
+        let mut connection = establish_connection();
+        Ok(self.get_order(&mut connection, order_id as i32)?.to_order())
+    }
+
+    fn order_fills(&self, symbol: &str, order_id: u64) -> Result<Vec<FillInfo>, TradingBotError> {
+        self.match_orders(symbol)?;
+        let mut connection = establish_connection();
+        Ok(self.get_order(&mut connection, order_id as i32)?.to_transaction().fills.unwrap_or_default())
+    }
+
+    fn open_orders(&self, symbol: &str) -> Result<Vec<Order>, TradingBotError> {
+        self.match_orders(symbol)?;
+        use crate::schema::paper_orders::dsl;
+        let mut connection = establish_connection();
+        Ok(dsl::paper_orders
+            .filter(dsl::account_id.eq(self.account_id))
+            .filter(dsl::symbol.eq(symbol))
+            .filter(dsl::status.eq("NEW"))
+            .load::<PaperOrder>(&mut connection)?
+            .iter()
+            .map(|order| order.to_order())
+            .collect())
+    }
+
+    fn all_open_orders(&self) -> Result<Vec<Order>, TradingBotError> {
+        use crate::schema::paper_orders::dsl;
+        let symbols = dsl::paper_orders
+            .filter(dsl::account_id.eq(self.account_id))
+            .filter(dsl::status.eq("NEW"))
+            .select(dsl::symbol)
+            .distinct()
+            .load::<String>(&mut establish_connection())?;
+        let mut orders = Vec::new();
+        for symbol in symbols {
+            orders.extend(self.open_orders(&symbol)?);
+        }
+        Ok(orders)
+    }
+
+    //paper orders fill in one go so each filled order stands in for a single trade
+    fn recent_trades(&self, symbol: &str, limit: u16) -> Result<Vec<AccountTrade>, TradingBotError> {
+        self.match_orders(symbol)?;
+        use crate::schema::paper_orders::dsl;
+        let mut connection = establish_connection();
+        let orders = dsl::paper_orders
+            .filter(dsl::account_id.eq(self.account_id))
+            .filter(dsl::symbol.eq(symbol))
+            .filter(dsl::executed_qty.gt(0.0))
+            .order(dsl::order_time.desc())
+            .limit(limit as i64)
+            .load::<PaperOrder>(&mut connection)?;
+        Ok(orders
+            .iter()
+            .rev()
+            .flat_map(|order| {
+                let side = if order.side == "BUY" { OrderSide::Buy } else { OrderSide::Sell };
+                let time = order.order_time.timestamp_millis() as u64;
+                order.to_transaction().fills.unwrap_or_default().into_iter().map(move |fill| AccountTrade {
+                    order_id: order.id as u64,
+                    side,
+                    fill,
+                    time,
+                })
+            })
+            .collect())
+    }
+
+    fn order_by_client_id(&self, symbol: &str, client_order_id: &str) -> Result<Option<Order>, TradingBotError> {
+        self.match_orders(symbol)?;
+        use crate::schema::paper_orders::dsl;
+        let mut connection = establish_connection();
+        let order = dsl::paper_orders
+            .filter(dsl::account_id.eq(self.account_id))
+            .filter(dsl::symbol.eq(symbol))
+            .filter(dsl::client_order_id.eq(client_order_id))
+            .first::<PaperOrder>(&mut connection)
+            .optional()?;
+        Ok(order.map(|order| order.to_order()))
+    }
+
+    fn cancel_order(&self, symbol: &str, order_id: u64) -> Result<OrderCanceled, TradingBotError> {
+        let mut connection = establish_connection();
+        connection.transaction::<_, TradingBotError, _>(|connection| {
+            let order = self.get_order(connection, order_id as i32)?;
+            if order.symbol != symbol || order.status != "NEW" {
+                return Err(TradingBotError::ExchangeError("Unknown order sent.".into()));
+            }
+            match order.side.as_str() {
+                "BUY" => {
+                    let locked = order.orig_qty * order.price.unwrap_or(0.0);
+                    adjust(connection, self.account_id, &order.quote_asset, locked, -locked)?
+                }
+                _ => adjust(connection, self.account_id, &order.base_asset, order.orig_qty, -order.orig_qty)?,
+            }
+            use crate::schema::paper_orders::dsl;
+            diesel::update(dsl::paper_orders.filter(dsl::id.eq(order.id)))
+                .set(dsl::status.eq("CANCELED"))
+                .execute(connection)?;
+            //cancelling either leg of an OCO cancels the whole list
+            if let Some(list_id) = order.order_list_id {
+                diesel::update(
+                    dsl::paper_orders
+                        .filter(dsl::order_list_id.eq(list_id))
+                        .filter(dsl::status.eq("NEW")),
+                )
+                .set(dsl::status.eq("CANCELED"))
+                .execute(connection)?;
+            }
+            Ok(OrderCanceled {
+                symbol: symbol.into(),
+                orig_client_order_id: Some(order.client_order_id()),
+                order_id: Some(order_id),
+                client_order_id: Some(format!("paper_{order_id}")),
+            })
+        })
+    }
+
+    //simulated fills use mainnet prices so the stream does too
+    fn market_stream_endpoint(&self) -> Option<String> {
+        Some(binance::config::Config::default().ws_endpoint)
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use binance::model::{AccountInformation, Balance, FillInfo, Order, OrderCanceled, Symbol, Transaction};
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, trace, warn};
+use tungstenite::{stream::MaybeTlsStream, Message};
+
+use crate::error::TradingBotError;
+
+use super::{AccountTrade, Exchange, OcoOrder, OcoRequest, OrderRequest, UserStreamKey};
+
+//The binance crate only models the futures account update so spot events are parsed here
+#[derive(Debug, Deserialize)]
+struct ExecutionReport {
+    #[serde(rename = "E")]
+    event_time: u64,
+    #[serde(rename = "s")]
+    symbol: String,
+    #[serde(rename = "c")]
+    client_order_id: String,
+    #[serde(rename = "S")]
+    side: String,
+    #[serde(rename = "o")]
+    order_type: String,
+    #[serde(rename = "f")]
+    time_in_force: String,
+    #[serde(rename = "q")]
+    qty: String,
+    #[serde(rename = "p")]
+    price: String,
+    #[serde(rename = "P")]
+    stop_price: String,
+    #[serde(rename = "F")]
+    iceberg_qty: String,
+    #[serde(rename = "g")]
+    order_list_id: i64,
+    #[serde(rename = "X")]
+    status: String,
+    #[serde(rename = "i")]
+    order_id: u64,
+    #[serde(rename = "z")]
+    executed_qty: String,
+    #[serde(rename = "Z")]
+    cummulative_quote_qty: String,
+    #[serde(rename = "O")]
+    order_time: u64,
+    #[serde(rename = "w")]
+    is_working: bool,
+    #[serde(rename = "Q")]
+    quote_order_qty: String,
+    //the trade behind a TRADE execution, -1 otherwise
+    #[serde(rename = "t")]
+    trade_id: i64,
+    #[serde(rename = "l")]
+    last_qty: String,
+    #[serde(rename = "L")]
+    last_price: String,
+    #[serde(rename = "n")]
+    commission: String,
+    #[serde(rename = "N")]
+    commission_asset: Option<String>,
+}
+impl ExecutionReport {
+    fn fill(&self) -> Option<FillInfo> {
+        if self.trade_id < 0 {
+            return None;
+        }
+        let parse = |value: &str| value.parse::<f64>().unwrap_or(0.0);
+        Some(FillInfo {
+            price: parse(&self.last_price),
+            qty: parse(&self.last_qty),
+            commission: parse(&self.commission),
+            commission_asset: self.commission_asset.clone().unwrap_or_default(),
+            trade_id: Some(self.trade_id as u64),
+        })
+    }
+
+    fn to_order(self) -> Order {
+        Order {
+            symbol: self.symbol,
+            order_id: self.order_id,
+            order_list_id: self.order_list_id,
+            client_order_id: self.client_order_id,
+            price: self.price.parse().unwrap_or(0.0),
+            orig_qty: self.qty,
+            executed_qty: self.executed_qty,
+            cummulative_quote_qty: self.cummulative_quote_qty,
+            status: self.status,
+            time_in_force: self.time_in_force,
+            type_name: self.order_type,
+            side: self.side,
+            stop_price: self.stop_price.parse().unwrap_or(0.0),
+            iceberg_qty: self.iceberg_qty,
+            time: self.order_time,
+            update_time: self.event_time,
+            is_working: self.is_working,
+            orig_quote_order_qty: self.quote_order_qty,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountPosition {
+    #[serde(rename = "B")]
+    balances: Vec<PositionBalance>,
+}
+#[derive(Debug, Deserialize)]
+struct PositionBalance {
+    #[serde(rename = "a")]
+    asset: String,
+    #[serde(rename = "f")]
+    free: String,
+    #[serde(rename = "l")]
+    locked: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum UserEvent {
+    //executionReport with the order as it stands after the update and the trade if it filled
+    Execution(Order, Option<FillInfo>),
+    //outboundAccountPosition carries only the assets that changed
+    Balances(Vec<Balance>),
+    //the listen key lapsed and the stream has to be restarted
+    Expired,
+}
+
+fn parse_event(msg: &str) -> Result<Option<UserEvent>, serde_json::Error> {
+    let value: serde_json::Value = serde_json::from_str(msg)?;
+    let event = match value.get("e").and_then(|e| e.as_str()) {
+        Some("executionReport") => {
+            let report = serde_json::from_value::<ExecutionReport>(value)?;
+            let fill = report.fill();
+            UserEvent::Execution(report.to_order(), fill)
+        }
+        Some("outboundAccountPosition") => UserEvent::Balances(
+            serde_json::from_value::<AccountPosition>(value)?
+                .balances
+                .into_iter()
+                .map(|b| Balance {
+                    asset: b.asset,
+                    free: b.free,
+                    locked: b.locked,
+                })
+                .collect(),
+        ),
+        Some("listenKeyExpired") => UserEvent::Expired,
+        _ => return Ok(None),
+    };
+    Ok(Some(event))
+}
+
+/// Order and balance state pushed by an account's user data stream.
+///
+/// Only trusted while the stream is connected, every disconnect clears it so reads fall back
+/// to the rest api until the stream is back.
+#[derive(Default)]
+pub struct StreamCache {
+    connected: AtomicBool,
+    orders: Mutex<HashMap<u64, Order>>,
+    balances: Mutex<HashMap<String, Balance>>,
+    last_reconcile: Mutex<Option<Instant>>,
+}
+
+impl StreamCache {
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Acquire)
+    }
+
+    fn connect(&self) {
+        self.orders.lock().unwrap().clear();
+        self.balances.lock().unwrap().clear();
+        self.connected.store(true, Ordering::Release);
+    }
+
+    pub fn disconnect(&self) {
+        self.connected.store(false, Ordering::Release);
+        self.orders.lock().unwrap().clear();
+        self.balances.lock().unwrap().clear();
+    }
+
+    fn apply(&self, event: &UserEvent) {
+        match event {
+            UserEvent::Execution(order, _) => {
+                self.orders.lock().unwrap().insert(order.order_id, order.clone());
+            }
+            UserEvent::Balances(balances) => {
+                let mut cached = self.balances.lock().unwrap();
+                for balance in balances {
+                    cached.insert(balance.asset.clone(), balance.clone());
+                }
+            }
+            UserEvent::Expired => {}
+        }
+    }
+
+    /// Polling only has to run every `every` while the stream is up, otherwise on every tick
+    pub fn reconcile_due(&self, every: Duration) -> bool {
+        if !self.is_connected() {
+            return true;
+        }
+        let mut last = self.last_reconcile.lock().unwrap();
+        match *last {
+            Some(at) if at.elapsed() < every => false,
+            _ => {
+                *last = Some(Instant::now());
+                true
+            }
+        }
+    }
+}
+
+//How long a read waits before checking whether the stream is still wanted
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Blocking read loop for a user data stream.
+///
+/// Every event updates `cache` and is forwarded on `events`. Returns when the socket closes,
+/// the listen key expires, `events` is dropped or `stop` is set.
+pub fn listen(
+    key: &UserStreamKey,
+    cache: &StreamCache,
+    events: UnboundedSender<UserEvent>,
+    stop: &AtomicBool,
+) -> Result<(), TradingBotError> {
+    let url = format!("{}/{}", key.endpoint, key.listen_key);
+    let (mut socket, _) = tungstenite::connect(url.as_str())
+        .map_err(|err| TradingBotError::ExchangeError(format!("User stream handshake failed {err}")))?;
+    let timeout = match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(READ_TIMEOUT)),
+        MaybeTlsStream::NativeTls(stream) => stream.get_ref().set_read_timeout(Some(READ_TIMEOUT)),
+        _ => Ok(()),
+    };
+    timeout.map_err(|err| TradingBotError::ExchangeError(format!("User stream read timeout not set {err}")))?;
+    cache.connect();
+    debug!("User stream connected");
+    let result = loop {
+        if stop.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+        let msg = match socket.read_message() {
+            Ok(msg) => msg,
+            //nothing arrived before the read timed out
+            Err(tungstenite::Error::Io(err)) if matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+            Err(err) => break Err(TradingBotError::ExchangeError(format!("User stream closed {err}"))),
+        };
+        let text = match msg {
+            Message::Text(text) => text,
+            Message::Close(_) => break Ok(()),
+            //pings are answered by tungstenite
+            _ => continue,
+        };
+        let event = match parse_event(&text) {
+            Ok(Some(event)) => event,
+            Ok(None) => continue,
+            Err(err) => {
+                warn!("Unparseable user stream event {err} {text}");
+                continue;
+            }
+        };
+        trace!("User stream event {event:?}");
+        cache.apply(&event);
+        let expired = matches!(event, UserEvent::Expired);
+        if events.send(event).is_err() || expired {
+            break Ok(());
+        }
+    };
+    cache.disconnect();
+    let _ = socket.close(None);
+    result
+}
+
+/// Serves order status and balances from the user data stream while it is connected.
+///
+/// Everything else, and everything while the stream is down, goes straight to the wrapped
+/// exchange.
+pub struct StreamedExchange {
+    inner: Arc<dyn Exchange>,
+    cache: Arc<StreamCache>,
+}
+
+impl StreamedExchange {
+    pub fn new(inner: Arc<dyn Exchange>, cache: Arc<StreamCache>) -> Self {
+        Self { inner, cache }
+    }
+}
+
+impl Exchange for StreamedExchange {
+    fn get_account(&self) -> Result<AccountInformation, TradingBotError> {
+        self.inner.get_account()
+    }
+
+    fn get_balance(&self, asset: &str) -> Result<Balance, TradingBotError> {
+        if self.cache.is_connected() {
+            if let Some(balance) = self.cache.balances.lock().unwrap().get(asset) {
+                return Ok(balance.clone());
+            }
+        }
+        let balance = self.inner.get_balance(asset)?;
+        //the stream only reports assets that change so seed it with the first lookup
+        if self.cache.is_connected() {
+            self.cache
+                .balances
+                .lock()
+                .unwrap()
+                .entry(asset.into())
+                .or_insert_with(|| balance.clone());
+        }
+        Ok(balance)
+    }
+
+    fn get_symbol_info(&self, symbol: &str) -> Result<Symbol, TradingBotError> {
+        self.inner.get_symbol_info(symbol)
+    }
+
+    fn get_symbols(&self) -> Result<Vec<String>, TradingBotError> {
+        self.inner.get_symbols()
+    }
+
+    fn get_price(&self, symbol: &str) -> Result<f64, TradingBotError> {
+        self.inner.get_price(symbol)
+    }
+
+    fn place_order(&self, order: &OrderRequest) -> Result<Transaction, TradingBotError> {
+        self.inner.place_order(order)
+    }
+
+    fn place_oco(&self, order: &OcoRequest) -> Result<OcoOrder, TradingBotError> {
+        self.inner.place_oco(order)
+    }
+
+    fn order_status(&self, symbol: &str, order_id: u64) -> Result<Order, TradingBotError> {
+        if self.cache.is_connected() {
+            if let Some(order) = self.cache.orders.lock().unwrap().get(&order_id) {
+                if order.symbol == symbol {
+                    return Ok(order.clone());
+                }
+            }
+        }
+        self.inner.order_status(symbol, order_id)
+    }
+
+    fn order_by_client_id(&self, symbol: &str, client_order_id: &str) -> Result<Option<Order>, TradingBotError> {
+        self.inner.order_by_client_id(symbol, client_order_id)
+    }
+
+    fn cancel_order(&self, symbol: &str, order_id: u64) -> Result<OrderCanceled, TradingBotError> {
+        self.inner.cancel_order(symbol, order_id)
+    }
+
+    fn open_orders(&self, symbol: &str) -> Result<Vec<Order>, TradingBotError> {
+        self.inner.open_orders(symbol)
+    }
+
+    fn all_open_orders(&self) -> Result<Vec<Order>, TradingBotError> {
+        self.inner.all_open_orders()
+    }
+
+    fn recent_trades(&self, symbol: &str, limit: u16) -> Result<Vec<AccountTrade>, TradingBotError> {
+        self.inner.recent_trades(symbol, limit)
+    }
+
+    fn order_fills(&self, symbol: &str, order_id: u64) -> Result<Vec<FillInfo>, TradingBotError> {
+        self.inner.order_fills(symbol, order_id)
+    }
+
+    fn market_stream_endpoint(&self) -> Option<String> {
+        self.inner.market_stream_endpoint()
+    }
+
+    fn start_user_stream(&self) -> Result<Option<UserStreamKey>, TradingBotError> {
+        self.inner.start_user_stream()
+    }
+
+    fn keep_alive_user_stream(&self, listen_key: &str) -> Result<(), TradingBotError> {
+        self.inner.keep_alive_user_stream(listen_key)
+    }
+}
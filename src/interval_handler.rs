@@ -1,10 +1,14 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::future::Future;
-use std::sync::{Arc};
+use std::time::Duration as StdDuration;
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
 use binance::account::{OrderSide, Account};
-use binance::model::Order;
+use binance::model::Balance;
 use serenity::builder::CreateComponents;
-use tokio::sync::RwLock;
+use serenity::futures::future::join_all;
+use tokio::sync::mpsc::unbounded_channel;
+use tokio::task::JoinHandle;
 
 use arc_swap::ArcSwap;
 use chrono::{Duration, Utc};
@@ -15,29 +19,82 @@ use diesel::{QueryDsl, RunQueryDsl};
 use serenity::model::prelude::ChannelId;
 use serenity::prelude::Context;
 
-use tracing::{debug, instrument, trace, warn, error};
+use tracing::{debug, instrument, trace, warn};
 
-use crate::binance_wrapped::BinanceWrapped;
+use crate::account_manager::AccountManager;
+use crate::binance_wrapped::{BinanceWrapped, Discrepancy, Position};
+use crate::circuit_breaker::{self, EquityHistory};
+use crate::conditional::{self, Outcome};
 use crate::config::{Config};
+use crate::error::TradingBotError;
 use crate::db::{establish_connection, self};
-use crate::models::{Reservation, BinanceAccount, ClockStub, DBTransaction};
-pub async fn run(ctx: Arc<Context>, config: Arc<ArcSwap<Config>>, binance: Arc<RwLock<BinanceWrapped>>) {
+use crate::exchange::filters::SymbolFilters;
+use crate::exchange::is_final_status;
+use crate::exchange::governor::Priority;
+use crate::transaction_state::{self, TransactionState};
+use crate::exchange::user_stream::{self, UserEvent};
+use crate::models::{Reservation, BinanceAccount, ClockStub, DBOrder, DBTransaction};
+
+pub async fn run(ctx: Arc<Context>, config: Arc<ArcSwap<Config>>, accounts: Arc<AccountManager>) {
     let mut scheduler = AsyncScheduler::new();
     debug!("We running");
+    tokio::spawn(watch_user_streams(ctx.clone(), config.clone(), accounts.clone()));
+    //whatever happened while the bot was down is settled before the handlers start
+    for_each_account(accounts.clone(), |account| handle_reconcile(ctx.clone(), config.clone(), account)).await;
     let ctx_clone = ctx.clone();
     let con_clone = config.clone();
+    let accounts1 = accounts.clone();
     scheduler.every(1.minute()).run(move || {
-        return handle_errors(handle_reservations(ctx.clone(), config.clone()));
+        let (ctx, config, accounts) = (ctx.clone(), config.clone(), accounts1.clone());
+        async move {
+            for_each_account(accounts, |account| handle_reservations(ctx.clone(), config.clone(), account)).await
+        }
     });
     let ctx_clone2 = ctx_clone.clone();
     let con_clone2 = con_clone.clone();
-    let binance2 = binance.clone();
+    let accounts2 = accounts.clone();
     scheduler.every(2.seconds()).run(move || {
-        return handle_errors(handle_orders(ctx_clone.clone(), con_clone.clone(),binance2.clone()));
+        let (ctx, config, accounts) = (ctx_clone.clone(), con_clone.clone(), accounts2.clone());
+        async move {
+            for_each_account(accounts, |account| poll_orders(ctx.clone(), config.clone(), account)).await
+        }
+    });
+    let ctx_clone3 = ctx_clone2.clone();
+    let con_clone3 = con_clone2.clone();
+    let accounts3 = accounts.clone();
+    scheduler.every(5.seconds()).run(move || {
+        let (ctx, config, accounts) = (ctx_clone3.clone(), con_clone3.clone(), accounts3.clone());
+        async move {
+            for_each_account(accounts, |account| handle_trailing_stop(ctx.clone(), config.clone(), account)).await
+        }
+    });
+
+    let ctx_clone5 = ctx_clone2.clone();
+    let con_clone5 = con_clone2.clone();
+    let accounts5 = accounts.clone();
+    scheduler.every(2.seconds()).run(move || {
+        let (ctx, config, accounts) = (ctx_clone5.clone(), con_clone5.clone(), accounts5.clone());
+        async move {
+            for_each_account(accounts, |account| handle_conditionals(ctx.clone(), config.clone(), account)).await
+        }
+    });
+
+    let ctx_clone4 = ctx_clone2.clone();
+    let con_clone4 = con_clone2.clone();
+    let accounts4 = accounts.clone();
+    let equity = Arc::new(EquityHistory::default());
+    scheduler.every(5.seconds()).run(move || {
+        let (ctx, config, accounts, equity) = (ctx_clone4.clone(), con_clone4.clone(), accounts4.clone(), equity.clone());
+        async move {
+            for_each_account(accounts, |account| handle_breaker(ctx.clone(), config.clone(), account, equity.clone())).await
+        }
     });
-     
+
     scheduler.every(1.minute()).run(move || {
-        return handle_errors(handle_afk(ctx_clone2.clone(), con_clone2.clone(),binance.clone()));
+        let (ctx, config, accounts) = (ctx_clone2.clone(), con_clone2.clone(), accounts.clone());
+        async move {
+            for_each_account(accounts, |account| handle_afk(ctx.clone(), config.clone(), account)).await
+        }
     });
 
     loop {
@@ -45,6 +102,14 @@ pub async fn run(ctx: Arc<Context>, config: Arc<ArcSwap<Config>>, binance: Arc<R
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
     }
 }
+//Run a job for every active account at once so a slow account doesn't hold up the others
+async fn for_each_account<F, Fut>(accounts: Arc<AccountManager>, job: F)
+where
+    F: Fn(BinanceWrapped) -> Fut,
+    Fut: Future<Output = Result<(), Box<dyn Error>>>,
+{
+    join_all(accounts.all().await.into_iter().map(|account| handle_errors(job(account)))).await;
+}
 async fn handle_errors(fun: impl Future<Output = Result<(), Box<dyn Error>>>) {
     if let Err(err) = fun.await {
         warn!("error occured {:?} from {:?}", err, err.source());
@@ -55,10 +120,9 @@ async fn handle_errors(fun: impl Future<Output = Result<(), Box<dyn Error>>>) {
 async fn handle_afk(
     ctx: Arc<Context>,
     config: Arc<ArcSwap<Config>>,
-    binance_w: Arc<RwLock<BinanceWrapped>>) -> Result<(), Box<dyn Error>> {
+    dbinance: BinanceWrapped) -> Result<(), Box<dyn Error>> {
     let mut connection = establish_connection();
     let config = config.load();
-    let dbinance = binance_w.read().await;
     let Some(afk_channel) = config.get::<u64>("channels", "afk_channel")?else {
         warn!(
             "AFK OFF"
@@ -143,20 +207,174 @@ async fn handle_afk(
 }
 
 
+//Keep a user data stream open for every active account that has one
+async fn watch_user_streams(ctx: Arc<Context>, config: Arc<ArcSwap<Config>>, accounts: Arc<AccountManager>) {
+    let mut streams: HashMap<i32, JoinHandle<()>> = HashMap::new();
+    loop {
+        let mut active = Vec::new();
+        for account in accounts.all().await {
+            let id = account.id;
+            active.push(id);
+            if streams.get(&id).map_or(true, |stream| stream.is_finished()) {
+                streams.insert(id, tokio::spawn(user_stream(ctx.clone(), config.clone(), account)));
+            }
+        }
+        streams.retain(|id, stream| {
+            if !active.contains(id) {
+                stream.abort();
+            }
+            active.contains(id)
+        });
+        tokio::time::sleep(StdDuration::from_secs(30)).await;
+    }
+}
+
+#[instrument(name = "User Stream", skip_all)]
+async fn user_stream(ctx: Arc<Context>, config: Arc<ArcSwap<Config>>, dbinance: BinanceWrapped) {
+    loop {
+        let streamed = stream_orders(ctx.clone(), config.clone(), dbinance.clone()).await.map_err(|err| err.to_string());
+        match streamed {
+            //stay parked so the watcher doesn't start it again, it is aborted with the account
+            Ok(false) => return std::future::pending().await,
+            Ok(true) => debug!("User stream ended restarting"),
+            Err(err) => warn!("User stream error {err}"),
+        }
+        tokio::time::sleep(StdDuration::from_secs(5)).await;
+    }
+}
+
+//Tells the stream reader to stop however the task reading from it ends
+struct StopOnDrop(Arc<AtomicBool>);
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+//Settle orders as soon as the stream reports them, false if the account has no stream
+async fn stream_orders(
+    ctx: Arc<Context>,
+    config: Arc<ArcSwap<Config>>,
+    dbinance: BinanceWrapped
+) -> Result<bool, Box<dyn Error>> {
+    let (exchange, cache) = (dbinance.get()?, dbinance.stream.clone());
+    let starter = exchange.clone();
+    let Some(key) = tokio::task::spawn_blocking(move || starter.start_user_stream()).await?? else {
+        return Ok(false);
+    };
+    let (events, mut received) = unbounded_channel();
+    let listen_key = key.clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let reader_stop = stop.clone();
+    let reader = tokio::task::spawn_blocking(move || user_stream::listen(&listen_key, &cache, events, &reader_stop));
+    //an error or abort below leaves the reader on its own so it has to be told to stop
+    let _stop = StopOnDrop(stop);
+
+    //listen keys lapse after an hour without a keep alive
+    let mut keep_alive = tokio::time::interval(StdDuration::from_secs(30 * 60));
+    keep_alive.tick().await;
+    loop {
+        tokio::select! {
+            event = received.recv() => match event {
+                Some(UserEvent::Execution(order, fill)) => {
+                    trace!("Order {} is {}", order.order_id, order.status);
+                    dbinance.blocking(move |b| {
+                        b.track_order(&order)?;
+                        if let Some(fill) = fill {
+                            b.record_fill(order.order_id, &fill, order.update_time)?;
+                        }
+                        Ok(())
+                    }).await?;
+                    handle_errors(handle_orders(ctx.clone(), config.clone(), dbinance.clone())).await;
+                }
+                Some(_) => {}
+                None => break,
+            },
+            _ = keep_alive.tick() => {
+                let (exchange, listen_key) = (exchange.clone(), key.listen_key.clone());
+                tokio::task::spawn_blocking(move || exchange.keep_alive_user_stream(&listen_key)).await??;
+                trace!("User stream kept alive");
+            }
+        }
+    }
+    reader.await??;
+    Ok(true)
+}
+
+#[instrument(name = "Reconcile Handler", skip_all)]
+async fn handle_reconcile(
+    ctx: Arc<Context>,
+    config: Arc<ArcSwap<Config>>,
+    dbinance: BinanceWrapped
+) -> Result<(), Box<dyn Error>> {
+    reconcile(&ctx, &config, &dbinance).await?;
+    Ok(())
+}
+
+/// Reconcile an account with the exchange and report anything found to the admin channel
+pub async fn reconcile(
+    ctx: &Context,
+    config: &ArcSwap<Config>,
+    dbinance: &BinanceWrapped
+) -> Result<Vec<Discrepancy>, TradingBotError> {
+    let found = dbinance.blocking(|b| b.reconcile()).await?;
+    debug!("Reconciled {} with {} discrepancies", dbinance.name, found.len());
+    let Some(channel_id) = config.load().get::<u64>("channels", "admin")? else {
+        return Ok(found);
+    };
+    if found.is_empty() {
+        return Ok(found);
+    }
+    ChannelId(channel_id)
+        .send_message(ctx, |m| {
+            m.embed(|embed| {
+                embed
+                    .title(format!("Reconciled {}", dbinance.name))
+                    .description(discrepancy_lines(&found))
+            })
+        }).await?;
+    Ok(found)
+}
+
+//One line per discrepancy cut to fit an embed description
+pub fn discrepancy_lines(found: &[Discrepancy]) -> String {
+    let mut lines = String::new();
+    for discrepancy in found {
+        let line = format!("**{}** {}\n", discrepancy.action, discrepancy.detail);
+        if lines.len() + line.len() > 4000 {
+            lines.push_str("...");
+            break;
+        }
+        lines.push_str(&line);
+    }
+    lines
+}
+
+//Polling every tick is only needed while the user data stream is down
+async fn poll_orders(
+    ctx: Arc<Context>,
+    config: Arc<ArcSwap<Config>>,
+    dbinance: BinanceWrapped
+) -> Result<(), Box<dyn Error>> {
+    let reconcile_interval_s = match config.load().get::<u64>("trading", "reconcile_interval_s")? {
+        Some(secs) => secs,
+        None => 60,
+    };
+    if !dbinance.stream.reconcile_due(StdDuration::from_secs(reconcile_interval_s)) {
+        return Ok(());
+    }
+    handle_orders(ctx, config, dbinance).await
+}
+
 use diesel::ExpressionMethods;
 #[instrument(name = "Order Handler", skip_all)]
 async fn handle_orders(
     ctx: Arc<Context>,
     config: Arc<ArcSwap<Config>>,
-    binance_w: Arc<RwLock<BinanceWrapped>>
+    dbinance: BinanceWrapped
 ) -> Result<(), Box<dyn Error>> {
     let mut connection = establish_connection();
     let config = config.load();
-    let dbinance = binance_w.read().await;
-    let Ok(account) = dbinance.get() else {
-        trace!("No Account");
-        return Ok(())
-    };
     let order_status = config.get::<u64>("channels", "order_status")?;
     
 
@@ -165,7 +383,7 @@ async fn handle_orders(
         let transaction_id: Option<i32>;
         {
             use crate::schema::binance_accounts::dsl;
-            transaction_id = dsl::binance_accounts.filter(dsl::selected.eq(true)).select(dsl::active_transaction).get_result::<Option<i32>>(&mut connection)?;
+            transaction_id = dsl::binance_accounts.filter(dsl::id.eq(dbinance.id)).select(dsl::active_transaction).get_result::<Option<i32>>(&mut connection)?;
         }
         let Some(transaction_id) = transaction_id else {
             return Ok(());
@@ -178,219 +396,293 @@ async fn handle_orders(
 
     }
     
-    let Some(symbol) = config.get::<String>("trading", "symbol")? else {
-        trace!("No symbol Set");
-        return Ok(())
-    };
-    if transaction.buyAvgPrice.is_none(){// Check buy Status
+    let symbol = transaction.symbol.clone();
+    //only orders that haven't settled are fetched
+    let transaction_id = transaction.id;
+    let orders = dbinance.blocking(move |b| b.refresh_orders(transaction_id)).await?;
+    let state = TransactionState::of(&transaction)?;
+    if state == TransactionState::Failed{
+        trace!("Transaction {} is waiting to be reconciled",transaction.id);
+        return Ok(());
+    }
+    if matches!(state, TransactionState::Idle | TransactionState::BuyPending){// Check buy Status
         //trace!("Pulling buy order");
-        let balance = dbinance.get_balance()?;
-        let mut ids = transaction.buyOrderIds.split(',');
-
-        
-        let id = transaction.buyOrderIds.split(',').last().unwrap();
-        if id ==""{
+        let buy_orders: Vec<DBOrder> = orders.into_iter().filter(|order| order.side == "BUY").collect();
+        let Some(order) = buy_orders.last() else {
             return Ok(());
-        }
-        trace!("Last Buy Order {}",id);
-        let Some(quote_balance) = config.get::<String>("trading", "quote_asset_threshold")? else {
-            trace!("No Balance Set");
-            return Ok(())
         };
-        let quote_balance = quote_balance.parse::<f32>().unwrap();
-        let last_order = id.parse::<u64>().unwrap();
-        let order = match account.order_status(&symbol, last_order){
-            Ok(status) => status,
-            Err(err) => {
-                use crate::schema::transactions::dsl;
-                //remove last id 
-                let new_order_ids:String = transaction.buyOrderIds.split(',').filter(|a| !a.contains(&last_order.to_string())).intersperse(",").collect();
-                trace!("New order ids {new_order_ids}");
-
-                diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set((dsl::buyOrderIds.eq(new_order_ids),dsl::buyReady.eq(true))).execute(&mut connection)?;
-                return Err(err.into());
-            }
+        trace!("Last Buy Order {}",order.client_order_id);
+        if let Some(working) = buy_orders.iter().find(|order| !is_final_status(&order.status)){
+            trace!("Buy order still {}",working.status);
+            return Ok(());
+        }
+        //an idle transaction only has a position if something put it back to idle after buying
+        if state == TransactionState::Idle && buy_orders.iter().all(|order| order.executed_qty <= 0.0){
+            return Ok(());
+        }
+        debug!("Buy Order done");
+        let position = position(&dbinance, transaction.id).await?;
+        let symbol_info = {
+            let symbol = symbol.clone();
+            dbinance.blocking(move |b| b.get()?.get_symbol_info(&symbol)).await?
         };
-        match order.status.as_str(){
-            x if x =="FILLED" || x == "CANCELED"  => {
-                debug!("Buy Order filled");
-                use crate::schema::transactions::dsl;
-                if balance.1.free.parse::<f32>()? + balance.1.locked.parse::<f32>()? <= quote_balance{//MAKE CONFIG
-                    //Close buy out
-                    use crate::schema::transactions::dsl;
-                    trace!("Buy Completed");
-                    let mut orders = Vec::new();
-                    while let Some(order_str) = ids.next(){
-                        let order_id = order_str.parse::<u64>().unwrap();
-                        let order = account.order_status(&symbol, order_id)?;
-                        let ct = order.cummulative_quote_qty.parse::<f64>().unwrap();
-                        let eq = order.executed_qty.parse::<f64>().unwrap();
-                        let price = ct/eq;
-                        let quantity = eq;
-                        if quantity <= 0.0{
-                            continue;
-                        }
-                        if price.is_nan(){
-                            continue;
-                        }
-                        orders.push((price,quantity));
-                    }
-                    trace!("Buy Orders:{:?}",orders);
-                    if orders.len() == 0{
-                        trace!("No Orders");
-                        return Ok(());
-                    }
-                    let total_qty = orders.iter().fold(0.0,|n,(_,q)| n+q);
-                    let avgPrice = orders.iter().fold(0.0,|t,(p,q)| 
-                        t+p * (q/total_qty)
-                    );
-                    
-                    debug!("Buy Completed with price {}",avgPrice);
-        
-                    diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set((dsl::buyReady.eq(false),dsl::sellReady.eq(true),dsl::buyAvgPrice.eq(Some(avgPrice)))).execute(&mut connection)?;
-                    if let Some(channel_id) = order_status{
-                        if let Some(stub) = dbinance.is_clocked_in()?{
-                            ChannelId(channel_id)
-                                .send_message(&ctx, |m| {
-                                    m.content(format!("<@{}> Buy order Cleared@{avgPrice} Ready to sell",
-                                    stub.user_id
-                                ))
-                            }).await?;
-                        }
-                    }
-                }else if !transaction.buyReady{
-                    diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set(dsl::buyReady.eq(true)).execute(&mut connection)?;
-                    if let Some(channel_id) = order_status{
-                        if let Some(stub) = dbinance.is_clocked_in()?{
-                            ChannelId(channel_id)
-                                .send_message(&ctx, |m| {
-                                    m.content(format!("<@{}> Buy order Cleared Ready to buy again",
-                                    stub.user_id
-                                ))
-                            }).await?;
-                        }
-                    }
+        if position.qty > 0.0 && !SymbolFilters::from_symbol(&symbol_info).is_dust(position.qty, position.avg_cost){
+            //Close buy out
+            use crate::schema::transactions::dsl;
+            trace!("Buy Completed");
+            let avg_price = position.avg_cost;
+
+            debug!("Buy Completed holding {} at {}",position.qty,avg_price);
+            if let Some(threshold) = config.get::<String>("trading", "quote_asset_threshold")?{
+                let balance = get_balance(&dbinance, &symbol).await?;
+                let quote = balance.1.free.parse::<f64>()? + balance.1.locked.parse::<f64>()?;
+                if quote > threshold.parse::<f64>()?{
+                    debug!("Buy filled with {quote} {} left over the account holds other funds",balance.1.asset);
                 }
             }
 
-            a => {
-                error!("Unknown status {a}");
+            diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set(dsl::buyAvgPrice.eq(Some(avg_price))).execute(&mut connection)?;
+            transaction_state::transition(&transaction, TransactionState::Long, "buy filled")?;
+            if let Some(channel_id) = order_status{
+                if let Some(stub) = dbinance.is_clocked_in()?{
+                    ChannelId(channel_id)
+                        .send_message(&ctx, |m| {
+                            m.content(format!("<@{}> Buy order Cleared holding {} {} avg cost {avg_price} Ready to sell",
+                            stub.user_id,position.qty,symbol_info.base_asset
+                        ))
+                    }).await?;
+                }
+            }
+        }else if state == TransactionState::BuyPending{
+            transaction_state::transition(&transaction, TransactionState::Idle, "buy order done before the position filled")?;
+            if let Some(channel_id) = order_status{
+                if let Some(stub) = dbinance.is_clocked_in()?{
+                    ChannelId(channel_id)
+                        .send_message(&ctx, |m| {
+                            m.content(format!("<@{}> Buy order Cleared Ready to buy again",
+                            stub.user_id
+                        ))
+                    }).await?;
+                }
             }
         }
-    }else{
-        let balance = dbinance.get_balance()?;
-        let mut ids = transaction.sellOrderIds.split(',');
-
-        let id = ids.clone().last().unwrap();
-        if id ==""{
+    }else if state == TransactionState::SellPending{
+        let sell_orders: Vec<DBOrder> = orders.iter().filter(|order| order.side == "SELL").cloned().collect();
+        let Some(order) = sell_orders.last() else {
+            return Ok(());
+        };
+        trace!("Last Sell Order {}",order.client_order_id);
+        //the losing leg of an OCO exit expires once the other fills
+        if let Some(working) = sell_orders.iter().find(|order| !is_final_status(&order.status)){
+            trace!("Sell order still {}",working.status);
             return Ok(());
         }
-
-        trace!("Last Sell Order {}",id);
-        let Some(base_balance) = config.get::<String>("trading", "base_asset_threshold")? else {
-            trace!("No Balance Set");
-            return Ok(())
+        debug!("Sell order done");
+        let symbol_info = {
+            let symbol = symbol.clone();
+            dbinance.blocking(move |b| b.get()?.get_symbol_info(&symbol)).await?
         };
-        let base_balance = base_balance.parse::<f32>().unwrap();
-        let last_order = id.parse::<u64>().unwrap();
-        let order = match account.order_status(&symbol, last_order){
-            Ok(status) => status,
-            Err(err) => {
-                use crate::schema::transactions::dsl;
-                //remove last id 
-                let new_order_ids:String = transaction.sellOrderIds.split(',').filter(|a| !a.contains(&last_order.to_string())).intersperse(",").collect();
-                trace!("New order ids {new_order_ids}");
-                diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set((dsl::sellOrderIds.eq(new_order_ids),dsl::sellReady.eq(true))).execute(&mut connection)?;
-                return Err(err.into());
+        let position = position(&dbinance, transaction.id).await?;
+        let (total_qty,total_quote) = filled(&sell_orders);
+        let avg_price = if total_qty > 0.0 {total_quote / total_qty} else {0.0};
+        let sold_out = position.qty <= 0.0
+            || SymbolFilters::from_symbol(&symbol_info).is_dust(position.qty, position.avg_cost);
+        if let Some(threshold) = config.get::<String>("trading", "base_asset_threshold")?{
+            let balance = get_balance(&dbinance, &symbol).await?;
+            let base = balance.0.free.parse::<f64>()? + balance.0.locked.parse::<f64>()?;
+            if !sold_out && base <= threshold.parse::<f64>()?{
+                warn!("Transaction {} still holds {} {} by its orders but the balance is {base}",transaction.id,position.qty,balance.0.asset);
             }
-        };
-        match order.status.as_str(){
-            x if x == "FILLED" || x ==  "CANCELED" => {
-                debug!("Sell order filled");
-                use crate::schema::transactions::dsl;
-                if balance.0.free.parse::<f32>()? + balance.0.locked.parse::<f32>()? <= base_balance{//MAKE CONFIG
-                    //Close buy out
-                    use crate::schema::transactions::dsl;
-                    trace!("Sell Completed");
-                    let mut orders = Vec::new();
-                    while let Some(order_str) = ids.next(){
-                        let order_id = order_str.parse::<u64>().unwrap();
-                        let order = account.order_status(&symbol, order_id)?;
-                        let ct = order.cummulative_quote_qty.parse::<f64>().unwrap();
-                        let eq = order.executed_qty.parse::<f64>().unwrap();
-                        let price = ct/eq;
-                        let quantity = eq;
-                        if quantity <= 0.0{
-                            continue;
-                        }
-                        if price.is_nan(){
-                            continue;
-                        }
-                        orders.push((price,quantity));
-                    }
-                    trace!("Sell Orders:{:?}",orders);
-
-                    let total_qty = orders.iter().fold(0.0,|n,(_,q)| n+q);
-                    let avgPrice = orders.iter().fold(0.0,|t,(p,q)| 
-                        t+p * (q/total_qty)
-                    );
-        
-                    debug!("Sell Completed with price {}",avgPrice);
-        
-        
-                    diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set((dsl::sellReady.eq(false),dsl::sellAvgPrice.eq(Some(avgPrice)))).execute(&mut connection)?;
-                
-                    
-                //      DISCONNECT FROM ACTIVE TRANSACTION
-                    trace!("Closing order");
-                    {
-                        use crate::schema::binance_accounts::dsl;
-        
-                        diesel::update(dsl::binance_accounts.filter(dsl::active_transaction.eq(Some(transaction.id)))).set(dsl::active_transaction.eq::<Option<i32>>(None)).execute(&mut connection)?;
-                        debug!("Order Closed");
-                    }
-                    if let Some(channel_id) = order_status{
-                        if let Some(stub) = dbinance.is_clocked_in()?{
-                            ChannelId(channel_id)
-                                .send_message(&ctx, |m| {
-                                    m.content(format!("<@{}> Sell order Cleared@{avgPrice} Ready to buy",
-                                    stub.user_id
-                                ))
-                            }).await?;
-                        }
-                    }
-                }else if !transaction.sellReady{
-                    diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set(dsl::sellReady.eq(true)).execute(&mut connection)?;
-                    if let Some(channel_id) = order_status{
-                        if let Some(stub) = dbinance.is_clocked_in()?{
-                            ChannelId(channel_id)
-                                .send_message(&ctx, |m| {
-                                    m.content(format!("<@{}> Sell order Cleared ready to sell again",
-                                    stub.user_id
-                                ))
-                            }).await?;
-                        }
-                    }
+        }
+        //the sells placed since the position last settled make up this exit
+        let since = transaction_state::events(transaction.id)?
+            .into_iter()
+            .filter(|event| event.to_state == TransactionState::Long.as_str())
+            .last()
+            .map(|event| event.created_at);
+        let exit_pnl: f64 = orders
+            .iter()
+            .filter(|order| order.side == "SELL" && since.map_or(true, |since| order.created_at >= since))
+            .filter_map(|order| order.realized_pnl)
+            .sum();
+        let quote = &position.quote_asset;
+        if sold_out{
+            //Close buy out
+            use crate::schema::transactions::dsl;
+            trace!("Sell Completed");
+
+            debug!("Sell Completed with price {} pnl {}",avg_price,position.realized);
+
+
+            diesel::update(dsl::transactions.filter(dsl::id.eq(transaction.id))).set(dsl::sellAvgPrice.eq(Some(avg_price))).execute(&mut connection)?;
+
+
+        //      DISCONNECT FROM ACTIVE TRANSACTION
+            trace!("Closing order");
+            transaction_state::transition(&transaction, TransactionState::Closed, "position sold")?;
+            debug!("Order Closed");
+            if let Some(channel_id) = order_status{
+                if let Some(stub) = dbinance.is_clocked_in()?{
+                    ChannelId(channel_id)
+                        .send_message(&ctx, |m| {
+                            m.content(format!("<@{}> Sell order Cleared@{avg_price} PnL {exit_pnl:.2} {quote} total {:.2} {quote} Ready to buy",
+                            stub.user_id,position.realized
+                        ))
+                    }).await?;
                 }
             }
-
-            a => {
-                trace!("Unknown status {a}");
+        }else{
+            transaction_state::transition(&transaction, TransactionState::Long, "sell order done with part of the position left")?;
+            if let Some(channel_id) = order_status{
+                if let Some(stub) = dbinance.is_clocked_in()?{
+                    ChannelId(channel_id)
+                        .send_message(&ctx, |m| {
+                            m.content(format!("<@{}> Sell order Cleared PnL {exit_pnl:.2} {quote} total so far {:.2} {quote} holding {} {} ready to sell again",
+                            stub.user_id,position.realized,position.qty,symbol_info.base_asset
+                        ))
+                    }).await?;
+                }
             }
-            
         }
-        
     }
     return Ok(());
 
 }
 
+//Exchange lookups for the order handler run on the blocking pool
+async fn get_balance(dbinance: &BinanceWrapped, symbol: &str) -> Result<(Balance, Balance), TradingBotError> {
+    let symbol = symbol.to_string();
+    dbinance.blocking(move |b| b.get_balance(&symbol)).await
+}
+
+async fn position(dbinance: &BinanceWrapped, transaction_id: i32) -> Result<Position, TradingBotError> {
+    dbinance.blocking(move |b| b.position(transaction_id)).await
+}
+
+//Quantity and quote traded across the orders
+fn filled(orders: &[DBOrder]) -> (f64, f64) {
+    orders.iter().fold((0.0, 0.0), |(qty, quote), order| (qty + order.executed_qty, quote + order.cummulative_quote_qty))
+}
+
+#[instrument(name = "Circuit Breaker", skip_all)]
+async fn handle_breaker(
+    ctx: Arc<Context>,
+    config: Arc<ArcSwap<Config>>,
+    dbinance: BinanceWrapped,
+    equity: Arc<EquityHistory>,
+) -> Result<(), Box<dyn Error>> {
+    let loaded = config.load();
+    if circuit_breaker::tripped(&loaded)?.is_some(){
+        return Ok(());
+    }
+    //balances are only worth the weight when the drawdown trigger is on
+    let current = if loaded.get::<f64>("breaker", "drawdown_pct")?.is_some(){
+        match dbinance.blocking_at(Priority::Low, |b| b.equity()).await {
+            Ok(current) => Some(current),
+            Err(TradingBotError::RateLimited(_)) => None,
+            Err(err) => return Err(err.into()),
+        }
+    }else{
+        None
+    };
+    let mut symbols = vec![dbinance.default_symbol()?];
+    if let Some(transaction) = dbinance.get_transaction()?{
+        if !symbols.contains(&transaction.symbol){
+            symbols.push(transaction.symbol);
+        }
+    }
+    let feeds: Vec<_> = symbols.iter().filter_map(|symbol| dbinance.price_feed(symbol)).collect();
+    let Some(reason) = circuit_breaker::check(&loaded, &equity, dbinance.id, current, &feeds)? else {
+        return Ok(());
+    };
+    let reason = format!("{} {reason}", dbinance.name);
+    //another account may have tripped it first
+    if !circuit_breaker::trip(&config, &reason)?{
+        return Ok(());
+    }
+    if let Some(channel_id) = loaded.get::<u64>("channels", "order_status")?{
+        ChannelId(channel_id)
+            .send_message(&ctx, |m| {
+                m.content(format!("Circuit breaker tripped on {reason}. New entries and market orders are off until an admin runs /rearm"))
+            }).await?;
+    }
+    Ok(())
+}
+
+#[instrument(name = "Trailing Stop Handler", skip_all)]
+async fn handle_trailing_stop(
+    ctx: Arc<Context>,
+    config: Arc<ArcSwap<Config>>,
+    dbinance: BinanceWrapped
+) -> Result<(), Box<dyn Error>> {
+    let config = config.load();
+    let Some((level, order)) = dbinance.blocking(|b| b.check_trailing_stop()).await? else {
+        return Ok(());
+    };
+    debug!("Trailing stop fired sell order {}", order.order_id);
+    let Some(channel_id) = config.get::<u64>("channels", "order_status")? else {
+        return Ok(());
+    };
+    //the trader who opened the position may have been clocked out since
+    let user_id = match dbinance.get_transaction()? {
+        Some(transaction) => {
+            use crate::schema::clock_stubs::dsl;
+            let mut connection = establish_connection();
+            Some(dsl::clock_stubs.filter(dsl::id.eq(transaction.clock_stub_id)).select(dsl::user_id).first::<i64>(&mut connection)?)
+        }
+        None => None,
+    };
+    ChannelId(channel_id)
+        .send_message(&ctx, |m| {
+            m.content(format!("{}Trailing stop @{level} hit on {} {} sell sent",
+                user_id.map(|id| format!("<@{id}> ")).unwrap_or_default(),
+                dbinance.name,
+                order.type_name.to_lowercase()
+            ))
+        }).await?;
+    Ok(())
+}
+
+#[instrument(name = "Conditional Order Handler", skip_all)]
+async fn handle_conditionals(
+    ctx: Arc<Context>,
+    config: Arc<ArcSwap<Config>>,
+    dbinance: BinanceWrapped
+) -> Result<(), Box<dyn Error>> {
+    let config = config.load();
+    let done = dbinance.blocking(|b| b.check_conditionals()).await?;
+    if done.is_empty() {
+        return Ok(());
+    }
+    let Some(channel_id) = config.get::<u64>("channels", "order_status")? else {
+        return Ok(());
+    };
+    for (order, outcome) in done {
+        let result = match outcome {
+            Outcome::Sent(sent) => format!("triggered, order {} {}", sent.order_id, sent.status),
+            Outcome::Failed(err) => format!("triggered but failed {err}"),
+            Outcome::Expired => "expired".to_string(),
+        };
+        ChannelId(channel_id)
+            .send_message(&ctx, |m| {
+                m.content(format!("<@{}> Conditional {} on {} {result}",
+                    order.user_id,
+                    conditional::describe(&order),
+                    dbinance.name
+                ))
+            }).await?;
+    }
+    Ok(())
+}
+
 #[instrument(name = "Reservation Handler", skip_all)]
 async fn handle_reservations(
     ctx: Arc<Context>,
     config: Arc<ArcSwap<Config>>,
+    dbinance: BinanceWrapped
 ) -> Result<(), Box<dyn Error>> {
     let config = config.load();
+    let account_id = dbinance.id;
     use crate::schema::reservations::dsl;
     use diesel::ExpressionMethods;
     let mut connection = establish_connection();
@@ -412,6 +704,7 @@ async fn handle_reservations(
     };
 
     let next_reservation = dsl::reservations
+        .filter(dsl::account_id.eq(account_id))
         .order(dsl::start_time.asc())
         .first::<Reservation>(&mut connection)? as Reservation;
 
@@ -446,14 +739,14 @@ async fn handle_reservations(
         
             use crate::schema::binance_accounts::dsl;
 
-            if let Some(reservation) = dsl::binance_accounts.filter(dsl::selected.eq(true)).select(dsl::active_reservation).first::<Option<i32>>(&mut connection)?{
+            if let Some(reservation) = dsl::binance_accounts.filter(dsl::id.eq(account_id)).select(dsl::active_reservation).first::<Option<i32>>(&mut connection)?{
                 if reservation == next_reservation.id{
                     return Ok(());
                 }
             }
 
 
-            diesel::update(dsl::binance_accounts.filter(dsl::selected.eq(true))).set(dsl::active_reservation.eq(Some(next_reservation.id))).execute(&mut connection)?;
+            diesel::update(dsl::binance_accounts.filter(dsl::id.eq(account_id))).set(dsl::active_reservation.eq(Some(next_reservation.id))).execute(&mut connection)?;
         }
     
 
@@ -461,7 +754,7 @@ async fn handle_reservations(
     } else if time_to_lock < now {
         {
             use crate::schema::binance_accounts::dsl;
-            diesel::update(dsl::binance_accounts.filter(dsl::selected.eq(true))).set(dsl::active_reservation.eq::<Option<i32>>(None)).execute(&mut connection)?;
+            diesel::update(dsl::binance_accounts.filter(dsl::id.eq(account_id))).set(dsl::active_reservation.eq::<Option<i32>>(None)).execute(&mut connection)?;
 
         }
         diesel::delete(dsl::reservations.filter(dsl::id.eq(next_reservation.id)))
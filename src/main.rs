@@ -1,22 +1,27 @@
-#![feature(iter_intersperse)]
 #![feature(iter_advance_by)]
+mod account_manager;
 mod binance_wrapped;
+mod circuit_breaker;
 mod commands;
+mod conditional;
 mod config;
 mod db;
 mod error;
 mod event_handler;
+mod exchange;
 mod interval_handler;
+mod market_data;
 mod models;
 mod ops;
 mod schedule;
 mod schema;
+mod sizing;
+mod transaction_state;
 mod utils;
 use arc_swap::ArcSwap;
 use binance::account::Account;
-use binance::api::Binance;
-use binance::market::Market;
-use binance_wrapped::BinanceWrapped;
+use account_manager::AccountManager;
+use market_data::MarketData;
 use command_macros::command_path;
 use commands::config::status::StatusCommand;
 use config::Config;
@@ -62,20 +67,16 @@ async fn main() {
     // Configure the client with your Discord bot token in the environment.
     let token = env::var("TOKEN").expect("Expected a token in the environment");
 
-    //Connect to binance accounts
-    let mut acc = BinanceWrapped::new(config.clone());
-    acc.load_account();
-
-    let binance = Arc::new(RwLock::from(acc));
-    let market: Market = Binance::new_with_config(
-        None,
-        None,
-        &binance::config::Config::default().set_rest_api_endpoint("https://testnet.binance.vision"),
-    );
+    //Connect to every active binance account
+    let accounts = AccountManager::new(config.clone(), Arc::new(MarketData::new()));
+    if let Err(err) = accounts.load().await {
+        error!("Failed to load accounts {err}");
+    }
+    let accounts = Arc::new(accounts);
 
     // Build our client.
     let mut client = Client::builder(token, GatewayIntents::empty())
-        .event_handler(Handler::new(binance, config, market))
+        .event_handler(Handler::new(accounts, config))
         .await
         .expect("Error creating client");
 
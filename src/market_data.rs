@@ -0,0 +1,226 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use binance::websockets::{WebSockets, WebsocketEvent};
+use chrono::{DateTime, TimeZone, Utc};
+use tokio::sync::watch;
+use tracing::{debug, trace, warn};
+
+use crate::{error::TradingBotError, exchange::Exchange};
+
+//About an hour of one second ticker updates
+const HISTORY_LEN: usize = 3600;
+//A feed nobody is subscribed to or has asked for in this long is stopped
+const FEED_IDLE: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quote {
+    pub price: f64,
+    pub bid: f64,
+    pub ask: f64,
+    pub time: DateTime<Utc>,
+}
+
+/// Live ticker for one symbol on one environment.
+///
+/// Subscribers wait on `subscribe` for updates, everything else reads `latest` or `history`.
+pub struct Feed {
+    pub symbol: String,
+    quote: watch::Sender<Option<Quote>>,
+    history: Mutex<VecDeque<Quote>>,
+    //cleared to close the socket, checked between ticker updates
+    running: AtomicBool,
+    last_used: Mutex<Instant>,
+}
+
+impl Feed {
+    fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.into(),
+            quote: watch::channel(None).0,
+            history: Mutex::new(VecDeque::with_capacity(HISTORY_LEN)),
+            running: AtomicBool::new(true),
+            last_used: Mutex::new(Instant::now()),
+        }
+    }
+
+    //Feed that already received `quotes`, never connected
+    #[cfg(test)]
+    pub(crate) fn with_quotes(symbol: &str, quotes: &[Quote]) -> Self {
+        let feed = Self::new(symbol);
+        for quote in quotes {
+            feed.push(*quote);
+        }
+        feed
+    }
+
+    //Nobody is subscribed and it hasn't been asked for in `idle`
+    fn unused(&self, idle: Duration) -> bool {
+        self.quote.receiver_count() == 0 && self.last_used.lock().unwrap().elapsed() >= idle
+    }
+
+    pub fn latest(&self) -> Option<Quote> {
+        *self.quote.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Option<Quote>> {
+        self.quote.subscribe()
+    }
+
+    //Last `len` prices oldest first
+    pub fn history(&self, len: usize) -> Vec<f64> {
+        let history = self.history.lock().unwrap();
+        history.iter().skip(history.len().saturating_sub(len)).map(|q| q.price).collect()
+    }
+
+    //Quotes received since `since` oldest first
+    pub fn quotes_since(&self, since: DateTime<Utc>) -> Vec<Quote> {
+        let history = self.history.lock().unwrap();
+        history.iter().filter(|q| q.time >= since).copied().collect()
+    }
+
+    fn push(&self, quote: Quote) {
+        {
+            let mut history = self.history.lock().unwrap();
+            if history.len() == HISTORY_LEN {
+                history.pop_front();
+            }
+            history.push_back(quote);
+        }
+        self.quote.send_replace(Some(quote));
+    }
+
+    //Blocking ticker loop reconnecting whenever the socket drops until the feed is stopped
+    fn run(&self, endpoint: &str) {
+        let config = binance::config::Config::default().set_ws_endpoint(endpoint);
+        let subscription = format!("{}@ticker", self.symbol.to_lowercase());
+        while self.running.load(Ordering::Relaxed) {
+            let mut socket = WebSockets::new(|event| {
+                if let WebsocketEvent::DayTicker(ticker) = event {
+                    let parse = |value: &str| value.parse::<f64>().unwrap_or(0.0);
+                    self.push(Quote {
+                        price: parse(&ticker.current_close),
+                        bid: parse(&ticker.best_bid),
+                        ask: parse(&ticker.best_ask),
+                        time: Utc.timestamp_millis_opt(ticker.event_time as i64).unwrap(),
+                    });
+                }
+                Ok(())
+            });
+            match socket.connect_with_config(&subscription, &config) {
+                Ok(()) => {
+                    debug!("Market data connected {} {endpoint}", self.symbol);
+                    if let Err(err) = socket.event_loop(&self.running) {
+                        warn!("Market data for {} dropped {err}", self.symbol);
+                    }
+                    let _ = socket.disconnect();
+                }
+                Err(err) => warn!("Market data for {} failed to connect {err}", self.symbol),
+            }
+            if self.running.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_secs(5));
+            }
+        }
+        debug!("Market data for {} on {endpoint} stopped", self.symbol);
+    }
+}
+
+/// One websocket ticker per symbol and environment shared by every command and job.
+///
+/// Accounts on the live exchange and the testnet see different books so feeds are keyed by the
+/// account's websocket endpoint as well as the symbol. Feeds nobody has subscribed to or asked
+/// for in a while are stopped whenever another feed is asked for.
+#[derive(Default)]
+pub struct MarketData {
+    feeds: Mutex<HashMap<(String, String), Arc<Feed>>>,
+}
+
+impl MarketData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed for the symbol on the exchange's environment, started on first use.
+    /// None when the exchange has no market stream.
+    pub fn feed(&self, exchange: &dyn Exchange, symbol: &str) -> Option<Arc<Feed>> {
+        let endpoint = exchange.market_stream_endpoint()?;
+        let mut feeds = self.feeds.lock().unwrap();
+        prune(&mut feeds, FEED_IDLE);
+        let feed = feeds
+            .entry((endpoint.clone(), symbol.into()))
+            .or_insert_with(|| {
+                trace!("Starting market data for {symbol} on {endpoint}");
+                let feed = Arc::new(Feed::new(symbol));
+                let runner = feed.clone();
+                thread::spawn(move || runner.run(&endpoint));
+                feed
+            });
+        *feed.last_used.lock().unwrap() = Instant::now();
+        Some(feed.clone())
+    }
+
+    /// Stop every feed nobody is subscribed to, one asked for again starts over
+    pub fn stop_unused(&self) {
+        prune(&mut self.feeds.lock().unwrap(), Duration::ZERO);
+    }
+
+    /// Latest price from the stream falling back to the rest api until it has ticked
+    pub fn price(&self, exchange: &dyn Exchange, symbol: &str) -> Result<f64, TradingBotError> {
+        if let Some(quote) = self.feed(exchange, symbol).and_then(|feed| feed.latest()) {
+            return Ok(quote.price);
+        }
+        exchange.get_price(symbol)
+    }
+}
+
+//Stop and drop the feeds nobody has used in `idle`
+fn prune(feeds: &mut HashMap<(String, String), Arc<Feed>>, idle: Duration) {
+    feeds.retain(|(endpoint, symbol), feed| {
+        if !feed.unused(idle) {
+            return true;
+        }
+        debug!("Stopping market data for {symbol} on {endpoint}");
+        feed.running.store(false, Ordering::Relaxed);
+        false
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::{atomic::Ordering, Arc}, time::Duration};
+
+    use super::{prune, Feed, FEED_IDLE};
+
+    #[test]
+    fn feeds_stop_once_nobody_uses_them() {
+        let watched = Arc::new(Feed::new("BTCUSDT"));
+        let asked_for = Arc::new(Feed::new("ETHUSDT"));
+        let mut feeds = HashMap::from([
+            (("wss://stream".to_string(), "BTCUSDT".to_string()), watched.clone()),
+            (("wss://stream".to_string(), "ETHUSDT".to_string()), asked_for.clone()),
+        ]);
+        let updates = watched.subscribe();
+
+        //both were just used
+        prune(&mut feeds, FEED_IDLE);
+        assert_eq!(feeds.len(), 2);
+
+        //a subscriber keeps its feed however long ago it was asked for
+        prune(&mut feeds, Duration::ZERO);
+        assert_eq!(feeds.len(), 1);
+        assert!(watched.running.load(Ordering::Relaxed));
+        assert!(!asked_for.running.load(Ordering::Relaxed));
+
+        drop(updates);
+        prune(&mut feeds, Duration::ZERO);
+        assert!(feeds.is_empty());
+        assert!(!watched.running.load(Ordering::Relaxed));
+    }
+}
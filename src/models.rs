@@ -3,11 +3,17 @@ use chrono::Utc;
 use diesel::prelude::*;
 
 use crate::schema::binance_accounts;
+use crate::schema::conditional_orders;
 use crate::schema::configs;
+use crate::schema::fills;
+use crate::schema::orders;
+use crate::schema::paper_balances;
+use crate::schema::paper_orders;
 use crate::schema::reservations;
 use crate::schema::users;
 use crate::schema::clock_stubs;
 use crate::schema::transactions;
+use crate::schema::transaction_events;
 
 
 #[derive(Insertable)]
@@ -56,6 +62,7 @@ pub struct NewReservation {
     pub start_time: DateTime<Utc>,
     pub end_time: DateTime<Utc>,
     pub user_id: i64,
+    pub account_id: i32,
 }
 
 #[derive(Identifiable, Clone, Copy, Queryable, PartialEq, Selectable, Debug, Associations)]
@@ -67,6 +74,7 @@ pub struct Reservation {
     pub end_time: DateTime<Utc>,
     pub alerted: bool,
     pub user_id: i64,
+    pub account_id: i32,
 }
 
 #[derive(Insertable)]
@@ -74,8 +82,9 @@ pub struct Reservation {
 pub struct NewBinanceAccount {
     pub name: String,
     pub is_paper: bool,
-    pub api_key: String,
-    pub secret: String,
+    pub api_key: Option<String>,
+    pub secret: Option<String>,
+    pub is_simulated: bool,
 }
 
 #[derive(Identifiable, Clone, Queryable, PartialEq, Selectable, Debug)]
@@ -83,14 +92,14 @@ pub struct NewBinanceAccount {
 pub struct BinanceAccount {
     pub id: i32,
     pub name: String,
-    pub selected: bool,
+    pub active: bool,
     pub is_paper: bool,
-    pub api_key: String,
-    pub secret: String,
+    pub api_key: Option<String>,
+    pub secret: Option<String>,
     pub active_clock_stub: Option<i32>,
     pub active_reservation: Option<i32>,
-    pub active_transaction: Option<i32>
-
+    pub active_transaction: Option<i32>,
+    pub is_simulated: bool,
 }
 
 
@@ -101,6 +110,7 @@ pub struct NewClockStub {
     pub start_time: DateTime<Utc>,
     pub user_id: i64,
     pub last_interaction: DateTime<Utc>,
+    pub account_id: i32,
 }
 
 
@@ -112,7 +122,8 @@ pub struct ClockStub {
     pub end_time: Option<DateTime<Utc>>,
     pub user_id: i64,
     pub last_interaction: DateTime<Utc>,
-    pub afk_warn_flag: bool
+    pub afk_warn_flag: bool,
+    pub account_id: Option<i32>,
 }
 
 #[allow(non_snake_case)]
@@ -121,8 +132,7 @@ pub struct ClockStub {
 pub struct NewTransaction {
     pub clock_stub_id: i32,
     pub buyOrderTime: DateTime<Utc>,
-    pub buyOrderIds: String,
-    pub sellOrderIds: String,
+    pub symbol: String,
 
 }
 
@@ -133,10 +143,211 @@ pub struct DBTransaction {
     pub id:i32,
     pub clock_stub_id: i32,
     pub buyOrderTime: DateTime<Utc>,
-    pub buyOrderIds: String,
-    pub buyReady: bool,
     pub buyAvgPrice:  Option<f64>,
-    pub sellOrderIds: String,
-    pub sellReady: bool,
     pub sellAvgPrice:  Option<f64>,
+    pub symbol: String,
+    pub trailingStopOffset: Option<f64>,
+    pub trailingStopPercent: bool,
+    pub trailingStopLevel: Option<f64>,
+    //quote asset after commissions, covers whatever has been sold so far
+    pub realizedPnl: Option<f64>,
+    pub commission: Option<f64>,
+    //see `TransactionState`
+    pub state: String,
+    //base asset still held net of commission
+    pub positionQty: Option<f64>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = transaction_events)]
+pub struct NewTransactionEvent<'a> {
+    pub transaction_id: i32,
+    pub from_state: Option<&'a str>,
+    pub to_state: &'a str,
+    pub reason: &'a str,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Identifiable, Clone, Queryable, PartialEq, Selectable, Debug)]
+#[diesel(table_name = transaction_events)]
+pub struct TransactionEvent {
+    pub id: i32,
+    pub transaction_id: i32,
+    pub from_state: Option<String>,
+    pub to_state: String,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = paper_balances)]
+pub struct NewPaperBalance<'a> {
+    pub account_id: i32,
+    pub asset: &'a str,
+    pub free: f64,
+    pub locked: f64,
+}
+
+#[derive(Identifiable, Clone, Queryable, PartialEq, Selectable, Debug)]
+#[diesel(primary_key(account_id, asset))]
+#[diesel(table_name = paper_balances)]
+pub struct PaperBalance {
+    pub account_id: i32,
+    pub asset: String,
+    pub free: f64,
+    pub locked: f64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = paper_orders)]
+pub struct NewPaperOrder<'a> {
+    pub account_id: i32,
+    pub symbol: &'a str,
+    pub base_asset: &'a str,
+    pub quote_asset: &'a str,
+    pub side: &'a str,
+    pub price: Option<f64>,
+    pub orig_qty: f64,
+    pub status: &'a str,
+    pub order_time: DateTime<Utc>,
+    pub stop_price: Option<f64>,
+    pub order_list_id: Option<i32>,
+    pub client_order_id: Option<&'a str>,
+    pub type_name: &'a str,
+    pub time_in_force: Option<&'a str>,
+}
+
+#[derive(Identifiable, Clone, Queryable, PartialEq, Selectable, Debug)]
+#[diesel(table_name = paper_orders)]
+pub struct PaperOrder {
+    pub id: i32,
+    pub account_id: i32,
+    pub symbol: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub side: String,
+    pub price: Option<f64>,
+    pub orig_qty: f64,
+    pub executed_qty: f64,
+    pub cummulative_quote_qty: f64,
+    pub commission: f64,
+    pub status: String,
+    pub order_time: DateTime<Utc>,
+    pub stop_price: Option<f64>,
+    pub order_list_id: Option<i32>,
+    pub triggered: bool,
+    pub client_order_id: Option<String>,
+    pub type_name: String,
+    pub time_in_force: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = orders)]
+pub struct NewOrder<'a> {
+    pub account_id: i32,
+    pub transaction_id: Option<i32>,
+    pub client_order_id: &'a str,
+    pub symbol: &'a str,
+    pub side: &'a str,
+    pub created_at: DateTime<Utc>,
+    pub type_name: &'a str,
+    pub price: Option<f64>,
+    pub orig_qty: Option<f64>,
+    pub quote_qty: Option<f64>,
+    pub stop_price: Option<f64>,
+    pub time_in_force: Option<&'a str>,
+}
+
+//PENDING until the exchange answers then its status, REJECTED if it never made it and UNKNOWN
+//until the tracker first looks at orders migrated from before they were recorded
+#[derive(Identifiable, Clone, Queryable, PartialEq, Selectable, Debug)]
+#[diesel(table_name = orders)]
+pub struct DBOrder {
+    pub id: i32,
+    pub account_id: i32,
+    pub transaction_id: Option<i32>,
+    pub client_order_id: String,
+    pub symbol: String,
+    pub side: String,
+    pub order_id: Option<i64>,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub type_name: Option<String>,
+    pub price: Option<f64>,
+    pub orig_qty: Option<f64>,
+    pub quote_qty: Option<f64>,
+    pub stop_price: Option<f64>,
+    pub executed_qty: f64,
+    pub cummulative_quote_qty: f64,
+    pub commission: f64,
+    pub commission_asset: Option<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+    //what a sell made against the average cost of the position it came out of
+    pub realized_pnl: Option<f64>,
+    pub time_in_force: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = fills)]
+pub struct NewFill<'a> {
+    pub order_id: i32,
+    pub trade_id: Option<i64>,
+    pub price: f64,
+    pub qty: f64,
+    pub commission: f64,
+    pub commission_asset: &'a str,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Identifiable, Clone, Queryable, PartialEq, Selectable, Debug)]
+#[diesel(table_name = fills)]
+pub struct Fill {
+    pub id: i32,
+    pub order_id: i32,
+    pub trade_id: Option<i64>,
+    pub price: f64,
+    pub qty: f64,
+    pub commission: f64,
+    pub commission_asset: String,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = conditional_orders)]
+pub struct NewConditionalOrder<'a> {
+    pub account_id: i32,
+    pub user_id: i64,
+    pub symbol: &'a str,
+    pub side: &'a str,
+    pub condition: &'a str,
+    pub trigger_price: f64,
+    pub limit_price: Option<f64>,
+    pub quantity: Option<f64>,
+    pub unit: Option<&'a str>,
+    pub last_price: Option<f64>,
+    pub status: &'a str,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Identifiable, Clone, Queryable, PartialEq, Selectable, Debug)]
+#[diesel(table_name = conditional_orders)]
+pub struct ConditionalOrder {
+    pub id: i32,
+    pub account_id: i32,
+    pub user_id: i64,
+    pub symbol: String,
+    pub side: String,
+    pub condition: String,
+    pub trigger_price: f64,
+    pub limit_price: Option<f64>,
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+    pub last_price: Option<f64>,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub triggered_at: Option<DateTime<Utc>>,
+    pub client_order_id: Option<String>,
+    pub detail: Option<String>,
 }
@@ -21,13 +21,14 @@ pub struct Schedule {
 }
 
 impl Schedule {
-    pub fn intitalize() -> Result<Self, diesel::result::Error> {
+    pub fn intitalize(account_id: i32) -> Result<Self, diesel::result::Error> {
         Ok(Self {
-            reservations: Self::pull_reservations()?,
+            reservations: Self::pull_reservations(account_id)?,
         })
     }
 
     pub fn open_time_slots(
+        account_id: i32,
         set_start_time: Option<DateTime<Utc>>,
         config: &Arc<Config>,
     ) -> Result<Vec<TimeSlot>, TradingBotError> {
@@ -35,7 +36,7 @@ impl Schedule {
             Some(num) => num,
             None => 15,
         };
-        let reservations = Self::pull_reservations()?;
+        let reservations = Self::pull_reservations(account_id)?;
         let mut right_now = set_start_time.unwrap_or(
             Utc::now()
                 .duration_trunc(Duration::minutes(interval as i64))
@@ -80,7 +81,7 @@ impl Schedule {
         let mut connection: SqliteConnection = establish_connection();
 
         use crate::schema::reservations::dsl;
-        let reservations = Self::pull_reservations()?;
+        let reservations = Self::pull_reservations(new_reservation.account_id)?;
 
         //       |-------|
         //  |----|       |-----||---------|
@@ -108,10 +109,11 @@ impl Schedule {
         return Ok(pulled_reser);
     }
 
-    pub fn pull_reservations() -> Result<Vec<Reservation>, diesel::result::Error> {
+    pub fn pull_reservations(account: i32) -> Result<Vec<Reservation>, diesel::result::Error> {
         let mut connection: SqliteConnection = establish_connection();
         use crate::schema::reservations::dsl::*;
         let pulled_reser = reservations
+            .filter(account_id.eq(account))
             .order(start_time.asc())
             .load::<Reservation>(&mut connection)?;
         return Ok(pulled_reser);
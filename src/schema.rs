@@ -4,13 +4,14 @@ diesel::table! {
     binance_accounts (id) {
         id -> Integer,
         name -> Text,
-        selected -> Bool,
+        active -> Bool,
         is_paper -> Bool,
-        api_key -> Text,
-        secret -> Text,
+        api_key -> Nullable<Text>,
+        secret -> Nullable<Text>,
         active_clock_stub -> Nullable<Integer>,
         active_reservation -> Nullable<Integer>,
         active_transaction -> Nullable<Integer>,
+        is_simulated -> Bool,
     }
 }
 
@@ -22,6 +23,29 @@ diesel::table! {
         user_id -> BigInt,
         last_interaction -> TimestamptzSqlite,
         afk_warn_flag -> Bool,
+        account_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    conditional_orders (id) {
+        id -> Integer,
+        account_id -> Integer,
+        user_id -> BigInt,
+        symbol -> Text,
+        side -> Text,
+        condition -> Text,
+        trigger_price -> Double,
+        limit_price -> Nullable<Double>,
+        quantity -> Nullable<Double>,
+        unit -> Nullable<Text>,
+        last_price -> Nullable<Double>,
+        status -> Text,
+        created_at -> TimestamptzSqlite,
+        expires_at -> TimestamptzSqlite,
+        triggered_at -> Nullable<TimestamptzSqlite>,
+        client_order_id -> Nullable<Text>,
+        detail -> Nullable<Text>,
     }
 }
 
@@ -35,6 +59,78 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    fills (id) {
+        id -> Integer,
+        order_id -> Integer,
+        trade_id -> Nullable<BigInt>,
+        price -> Double,
+        qty -> Double,
+        commission -> Double,
+        commission_asset -> Text,
+        time -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    orders (id) {
+        id -> Integer,
+        account_id -> Integer,
+        transaction_id -> Nullable<Integer>,
+        client_order_id -> Text,
+        symbol -> Text,
+        side -> Text,
+        order_id -> Nullable<BigInt>,
+        status -> Text,
+        created_at -> TimestamptzSqlite,
+        type_name -> Nullable<Text>,
+        price -> Nullable<Double>,
+        orig_qty -> Nullable<Double>,
+        quote_qty -> Nullable<Double>,
+        stop_price -> Nullable<Double>,
+        executed_qty -> Double,
+        cummulative_quote_qty -> Double,
+        commission -> Double,
+        commission_asset -> Nullable<Text>,
+        updated_at -> Nullable<TimestamptzSqlite>,
+        realized_pnl -> Nullable<Double>,
+        time_in_force -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    paper_balances (account_id, asset) {
+        account_id -> Integer,
+        asset -> Text,
+        free -> Double,
+        locked -> Double,
+    }
+}
+
+diesel::table! {
+    paper_orders (id) {
+        id -> Integer,
+        account_id -> Integer,
+        symbol -> Text,
+        base_asset -> Text,
+        quote_asset -> Text,
+        side -> Text,
+        price -> Nullable<Double>,
+        orig_qty -> Double,
+        executed_qty -> Double,
+        cummulative_quote_qty -> Double,
+        commission -> Double,
+        status -> Text,
+        order_time -> TimestamptzSqlite,
+        stop_price -> Nullable<Double>,
+        order_list_id -> Nullable<Integer>,
+        triggered -> Bool,
+        client_order_id -> Nullable<Text>,
+        type_name -> Text,
+        time_in_force -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     reservations (id) {
         id -> Integer,
@@ -42,6 +138,18 @@ diesel::table! {
         end_time -> TimestamptzSqlite,
         alerted -> Bool,
         user_id -> BigInt,
+        account_id -> Integer,
+    }
+}
+
+diesel::table! {
+    transaction_events (id) {
+        id -> Integer,
+        transaction_id -> Integer,
+        from_state -> Nullable<Text>,
+        to_state -> Text,
+        reason -> Text,
+        created_at -> TimestamptzSqlite,
     }
 }
 
@@ -50,12 +158,16 @@ diesel::table! {
         id -> Integer,
         clock_stub_id -> Integer,
         buyOrderTime -> TimestamptzSqlite,
-        buyOrderIds -> Text,
-        buyReady -> Bool,
         buyAvgPrice -> Nullable<Double>,
-        sellOrderIds -> Text,
-        sellReady -> Bool,
         sellAvgPrice -> Nullable<Double>,
+        symbol -> Text,
+        trailingStopOffset -> Nullable<Double>,
+        trailingStopPercent -> Bool,
+        trailingStopLevel -> Nullable<Double>,
+        realizedPnl -> Nullable<Double>,
+        commission -> Nullable<Double>,
+        state -> Text,
+        positionQty -> Nullable<Double>,
     }
 }
 
@@ -70,14 +182,27 @@ diesel::joinable!(binance_accounts -> clock_stubs (active_clock_stub));
 diesel::joinable!(binance_accounts -> reservations (active_reservation));
 diesel::joinable!(binance_accounts -> transactions (active_transaction));
 diesel::joinable!(clock_stubs -> users (user_id));
+diesel::joinable!(conditional_orders -> binance_accounts (account_id));
+diesel::joinable!(fills -> orders (order_id));
+diesel::joinable!(orders -> binance_accounts (account_id));
+diesel::joinable!(orders -> transactions (transaction_id));
+diesel::joinable!(paper_balances -> binance_accounts (account_id));
+diesel::joinable!(paper_orders -> binance_accounts (account_id));
 diesel::joinable!(reservations -> users (user_id));
+diesel::joinable!(transaction_events -> transactions (transaction_id));
 diesel::joinable!(transactions -> clock_stubs (clock_stub_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     binance_accounts,
     clock_stubs,
+    conditional_orders,
     configs,
+    fills,
+    orders,
+    paper_balances,
+    paper_orders,
     reservations,
+    transaction_events,
     transactions,
     users,
 );
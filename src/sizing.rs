@@ -0,0 +1,132 @@
+use std::fmt;
+
+use crate::{error::TradingBotError, exchange::{OrderQuantity, OrderRequest}};
+
+/// How much a trader asked to buy or sell.
+///
+/// Percentages are of the free balance the order spends, the quote asset for buys and the base
+/// asset for sells. A quote amount on a sell sells whatever is worth that much at the price.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sizing {
+    //amount of the base asset eg 0.01 BTC
+    Base(f64),
+    //amount of the quote asset eg 250 USDT
+    Quote(f64),
+    //share of the free balance 0-100
+    Percent(f64),
+}
+
+impl Sizing {
+    //The whole free balance
+    pub const ALL: Sizing = Sizing::Percent(100.0);
+
+    /// Size from the `quantity` and `unit` command options, None leaves it to the caller's default
+    pub fn from_options(quantity: Option<f64>, unit: Option<&str>) -> Result<Option<Self>, TradingBotError> {
+        let Some(quantity) = quantity else {
+            return Ok(None);
+        };
+        if quantity <= 0.0 {
+            return Err(TradingBotError::OrderRejected("Quantity must be positive".into()));
+        }
+        Ok(Some(match unit.unwrap_or("percent") {
+            "percent" if quantity > 100.0 => {
+                return Err(TradingBotError::OrderRejected("Can't use more than 100% of the balance".into()))
+            }
+            "percent" => Sizing::Percent(quantity),
+            "base" => Sizing::Base(quantity),
+            "quote" => Sizing::Quote(quantity),
+            unit => return Err(TradingBotError::ParsingDataError(format!("Unknown unit {unit}"))),
+        }))
+    }
+
+    //Back to the quantity and unit options so it can be stored and read with `from_options`
+    pub fn to_options(self) -> (f64, &'static str) {
+        match self {
+            Sizing::Percent(quantity) => (quantity, "percent"),
+            Sizing::Base(quantity) => (quantity, "base"),
+            Sizing::Quote(quantity) => (quantity, "quote"),
+        }
+    }
+}
+
+/// An order worked out from a `Sizing` ready to send with what it comes to in both assets
+#[derive(Debug, Clone)]
+pub struct SizedOrder {
+    pub request: OrderRequest,
+    //market orders are estimated at the last price
+    pub base_qty: f64,
+    pub quote_qty: f64,
+    pub base_asset: String,
+    pub quote_asset: String,
+}
+
+impl fmt::Display for SizedOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //market orders fix one side and the other depends on the fill
+        let (base, quote) = match (self.request.price, self.request.quantity) {
+            (Some(_), _) => ("", ""),
+            (None, OrderQuantity::Base(_)) => ("", "~"),
+            (None, OrderQuantity::Quote(_)) => ("~", ""),
+        };
+        write!(
+            f,
+            "{} {base}{} {} for {quote}{:.2} {} on {}",
+            self.request.side.as_str(),
+            self.base_qty,
+            self.base_asset,
+            self.quote_qty,
+            self.quote_asset,
+            self.request.symbol
+        )?;
+        //plain GTC limits and market orders say nothing extra
+        match (self.request.type_name(), self.request.time_in_force()) {
+            ("LIMIT_MAKER", _) => write!(f, " post-only"),
+            (_, Some(time_in_force)) if time_in_force != "GTC" => write!(f, " {time_in_force}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sizing;
+    use crate::error::TradingBotError;
+
+    #[test]
+    fn quantity_is_a_percent_of_the_balance_unless_a_unit_is_given() {
+        assert_eq!(Sizing::from_options(Some(50.0), None).unwrap(), Some(Sizing::Percent(50.0)));
+        assert_eq!(Sizing::from_options(Some(50.0), Some("percent")).unwrap(), Some(Sizing::Percent(50.0)));
+        assert_eq!(Sizing::from_options(Some(0.01), Some("base")).unwrap(), Some(Sizing::Base(0.01)));
+        assert_eq!(Sizing::from_options(Some(250.0), Some("quote")).unwrap(), Some(Sizing::Quote(250.0)));
+    }
+
+    #[test]
+    fn no_quantity_is_left_to_the_caller() {
+        assert_eq!(Sizing::from_options(None, None).unwrap(), None);
+        assert_eq!(Sizing::from_options(None, Some("base")).unwrap(), None);
+    }
+
+    #[test]
+    fn percentages_stay_within_the_balance() {
+        assert_eq!(Sizing::from_options(Some(100.0), None).unwrap(), Some(Sizing::ALL));
+        assert!(matches!(Sizing::from_options(Some(100.5), None), Err(TradingBotError::OrderRejected(_))));
+        assert!(matches!(Sizing::from_options(Some(0.0), None), Err(TradingBotError::OrderRejected(_))));
+        assert!(matches!(Sizing::from_options(Some(-5.0), Some("percent")), Err(TradingBotError::OrderRejected(_))));
+        //only percentages are capped
+        assert_eq!(Sizing::from_options(Some(150.0), Some("base")).unwrap(), Some(Sizing::Base(150.0)));
+        assert!(matches!(Sizing::from_options(Some(-1.0), Some("quote")), Err(TradingBotError::OrderRejected(_))));
+    }
+
+    #[test]
+    fn unknown_units_are_refused() {
+        assert!(matches!(Sizing::from_options(Some(1.0), Some("lots")), Err(TradingBotError::ParsingDataError(_))));
+    }
+
+    #[test]
+    fn options_round_trip() {
+        for size in [Sizing::Percent(25.0), Sizing::Base(0.5), Sizing::Quote(100.0)] {
+            let (quantity, unit) = size.to_options();
+            assert_eq!(Sizing::from_options(Some(quantity), Some(unit)).unwrap(), Some(size));
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use chrono::Utc;
+use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection};
+use tracing::debug;
+
+use crate::{
+    db::establish_connection,
+    error::TradingBotError,
+    models::{DBTransaction, NewTransaction, NewTransactionEvent, TransactionEvent},
+};
+
+/// Where a transaction is in its life.
+///
+/// Stored as text on `transactions.state`, every change goes through `transition` so it is
+/// checked against the current state and written to `transaction_events`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionState {
+    //open with nothing working, a buy can be placed
+    Idle,
+    //a buy opening or adding to the position is working on the exchange
+    BuyPending,
+    //part of the position is bought, it can be sold or added to
+    Long,
+    //a sell or exit is working on the exchange
+    SellPending,
+    //sold off or abandoned before anything was bought
+    Closed,
+    //an order was lost track of and the transaction needs reconciling
+    Failed,
+}
+
+impl TransactionState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TransactionState::Idle => "IDLE",
+            TransactionState::BuyPending => "BUY_PENDING",
+            TransactionState::Long => "LONG",
+            TransactionState::SellPending => "SELL_PENDING",
+            TransactionState::Closed => "CLOSED",
+            TransactionState::Failed => "FAILED",
+        }
+    }
+
+    pub fn parse(state: &str) -> Result<Self, TradingBotError> {
+        Ok(match state {
+            "IDLE" => TransactionState::Idle,
+            "BUY_PENDING" => TransactionState::BuyPending,
+            "LONG" => TransactionState::Long,
+            "SELL_PENDING" => TransactionState::SellPending,
+            "CLOSED" => TransactionState::Closed,
+            "FAILED" => TransactionState::Failed,
+            state => return Err(TradingBotError::ParsingDataError(format!("Unknown transaction state {state}"))),
+        })
+    }
+
+    pub fn of(transaction: &DBTransaction) -> Result<Self, TradingBotError> {
+        Self::parse(&transaction.state)
+    }
+
+    pub fn can_become(self, next: TransactionState) -> bool {
+        use TransactionState::*;
+        match (self, next) {
+            (Idle, BuyPending | Long | Closed | Failed) => true,
+            //a buy that finished without anything bought leaves it idle
+            (BuyPending, Idle | Long | Failed) => true,
+            //the position can also be sold off outside the bot
+            (Long, BuyPending | SellPending | Closed | Failed) => true,
+            (SellPending, Long | Closed | Failed) => true,
+            //reconciling puts a failed transaction wherever the exchange says it is
+            (Failed, next) => next != Failed,
+            _ => false,
+        }
+    }
+
+    //A buy can open or add to the position while nothing else is working
+    pub fn can_buy(self) -> bool {
+        matches!(self, TransactionState::Idle | TransactionState::Long)
+    }
+
+    pub fn can_sell(self) -> bool {
+        self == TransactionState::Long
+    }
+}
+
+fn record_event(
+    connection: &mut SqliteConnection,
+    transaction_id: i32,
+    from: Option<TransactionState>,
+    to: TransactionState,
+    reason: &str,
+) -> Result<(), TradingBotError> {
+    use crate::schema::transaction_events::dsl;
+    diesel::insert_into(dsl::transaction_events)
+        .values(NewTransactionEvent {
+            transaction_id,
+            from_state: from.map(|state| state.as_str()),
+            to_state: to.as_str(),
+            reason,
+            created_at: Utc::now(),
+        })
+        .execute(connection)?;
+    Ok(())
+}
+
+/// File a new transaction in `state` and make it the account's active one
+pub fn open(
+    account_id: i32,
+    transaction: NewTransaction,
+    state: TransactionState,
+    reason: &str,
+) -> Result<DBTransaction, TradingBotError> {
+    let mut connection = establish_connection();
+    connection.transaction::<_, TradingBotError, _>(|connection| {
+        let transaction = {
+            use crate::schema::transactions::dsl;
+            diesel::insert_into(dsl::transactions)
+                .values((transaction, dsl::state.eq(state.as_str())))
+                .get_result::<DBTransaction>(connection)?
+        };
+        record_event(connection, transaction.id, None, state, reason)?;
+        {
+            use crate::schema::binance_accounts::dsl;
+            diesel::update(dsl::binance_accounts.filter(dsl::id.eq(account_id)))
+                .set(dsl::active_transaction.eq(Some(transaction.id)))
+                .execute(connection)?;
+        }
+        debug!("Transaction {} opened {}", transaction.id, state.as_str());
+        Ok(transaction)
+    })
+}
+
+/// Move a transaction to `next` recording why.
+///
+/// Fails if the move isn't allowed or the transaction has moved on since it was read. Closing
+/// a transaction detaches it from its account.
+pub fn transition(
+    transaction: &DBTransaction,
+    next: TransactionState,
+    reason: &str,
+) -> Result<DBTransaction, TradingBotError> {
+    let current = TransactionState::of(transaction)?;
+    if !current.can_become(next) {
+        return Err(TradingBotError::InvalidTransition(format!(
+            "{} can't go from {} to {}",
+            transaction.id,
+            current.as_str(),
+            next.as_str()
+        )));
+    }
+    let mut connection = establish_connection();
+    connection.transaction::<_, TradingBotError, _>(|connection| {
+        let updated = {
+            use crate::schema::transactions::dsl;
+            diesel::update(
+                dsl::transactions
+                    .filter(dsl::id.eq(transaction.id))
+                    .filter(dsl::state.eq(current.as_str())),
+            )
+            .set(dsl::state.eq(next.as_str()))
+            .get_results::<DBTransaction>(connection)?
+        };
+        let Some(updated) = updated.into_iter().next() else {
+            return Err(TradingBotError::InvalidTransition(format!(
+                "{} is no longer {}",
+                transaction.id,
+                current.as_str()
+            )));
+        };
+        record_event(connection, transaction.id, Some(current), next, reason)?;
+        if next == TransactionState::Closed {
+            use crate::schema::binance_accounts::dsl;
+            diesel::update(dsl::binance_accounts.filter(dsl::active_transaction.eq(Some(transaction.id))))
+                .set(dsl::active_transaction.eq::<Option<i32>>(None))
+                .execute(connection)?;
+        }
+        debug!("Transaction {} {} -> {} {reason}", transaction.id, current.as_str(), next.as_str());
+        Ok(updated)
+    })
+}
+
+/// A transaction's history oldest first
+pub fn events(transaction_id: i32) -> Result<Vec<TransactionEvent>, TradingBotError> {
+    use crate::schema::transaction_events::dsl;
+    let mut connection = establish_connection();
+    Ok(dsl::transaction_events
+        .filter(dsl::transaction_id.eq(transaction_id))
+        .order(dsl::id.asc())
+        .load::<TransactionEvent>(&mut connection)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+
+    use super::{events, open, transition, TransactionState};
+    use crate::{
+        db::{establish_connection, test::database},
+        error::TradingBotError,
+        models::{BinanceAccount, DBTransaction, NewBinanceAccount, NewTransaction},
+    };
+    use TransactionState::*;
+
+    const STATES: [TransactionState; 6] = [Idle, BuyPending, Long, SellPending, Closed, Failed];
+
+    //Account with a fresh transaction open on it
+    fn opened(state: TransactionState) -> (i32, DBTransaction) {
+        let mut connection = establish_connection();
+        let account = diesel::insert_into(crate::schema::binance_accounts::table)
+            .values(NewBinanceAccount {
+                name: "test".into(),
+                is_paper: true,
+                api_key: None,
+                secret: None,
+                is_simulated: true,
+            })
+            .get_result::<BinanceAccount>(&mut connection)
+            .unwrap();
+        let transaction = open(
+            account.id,
+            NewTransaction { clock_stub_id: 1, buyOrderTime: Utc::now(), symbol: "BTCUSDT".into() },
+            state,
+            "opened",
+        )
+        .unwrap();
+        (account.id, transaction)
+    }
+
+    fn active_transaction(account_id: i32) -> Option<i32> {
+        use crate::schema::binance_accounts::dsl;
+        dsl::binance_accounts
+            .filter(dsl::id.eq(account_id))
+            .select(dsl::active_transaction)
+            .get_result(&mut establish_connection())
+            .unwrap()
+    }
+
+    #[test]
+    fn states_round_trip_through_text() {
+        for state in STATES {
+            assert_eq!(TransactionState::parse(state.as_str()).unwrap(), state);
+        }
+        assert!(TransactionState::parse("OPEN").is_err());
+    }
+
+    #[test]
+    fn only_allowed_moves_can_happen() {
+        let allowed = [
+            (Idle, BuyPending),
+            (Idle, Long),
+            (Idle, Closed),
+            (Idle, Failed),
+            (BuyPending, Idle),
+            (BuyPending, Long),
+            (BuyPending, Failed),
+            (Long, BuyPending),
+            (Long, SellPending),
+            (Long, Closed),
+            (Long, Failed),
+            (SellPending, Long),
+            (SellPending, Closed),
+            (SellPending, Failed),
+            (Failed, Idle),
+            (Failed, BuyPending),
+            (Failed, Long),
+            (Failed, SellPending),
+            (Failed, Closed),
+        ];
+        for from in STATES {
+            for to in STATES {
+                assert_eq!(from.can_become(to), allowed.contains(&(from, to)), "{} -> {}", from.as_str(), to.as_str());
+            }
+        }
+    }
+
+    #[test]
+    fn buying_and_selling_follow_the_state() {
+        let buys: Vec<_> = STATES.into_iter().filter(|state| state.can_buy()).collect();
+        let sells: Vec<_> = STATES.into_iter().filter(|state| state.can_sell()).collect();
+        assert_eq!(buys, [Idle, Long]);
+        assert_eq!(sells, [Long]);
+    }
+
+    #[test]
+    fn transitions_are_recorded_and_closing_detaches() {
+        let _database = database();
+        let (account_id, transaction) = opened(Idle);
+        assert_eq!(active_transaction(account_id), Some(transaction.id));
+
+        let transaction = transition(&transaction, BuyPending, "buy order sent").unwrap();
+        let transaction = transition(&transaction, Long, "buy filled").unwrap();
+        assert_eq!(TransactionState::of(&transaction).unwrap(), Long);
+        transition(&transaction, Closed, "position sold").unwrap();
+        assert_eq!(active_transaction(account_id), None);
+
+        let history: Vec<_> = events(transaction.id)
+            .unwrap()
+            .into_iter()
+            .map(|event| (event.from_state, event.to_state, event.reason))
+            .collect();
+        assert_eq!(
+            history,
+            [
+                (None, "IDLE".into(), "opened".into()),
+                (Some("IDLE".into()), "BUY_PENDING".into(), "buy order sent".into()),
+                (Some("BUY_PENDING".into()), "LONG".into(), "buy filled".into()),
+                (Some("LONG".into()), "CLOSED".into(), "position sold".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn refused_moves_change_nothing() {
+        let _database = database();
+        let (account_id, transaction) = opened(Idle);
+        assert!(matches!(transition(&transaction, SellPending, "sell"), Err(TradingBotError::InvalidTransition(_))));
+        assert!(matches!(transition(&transaction, Idle, "again"), Err(TradingBotError::InvalidTransition(_))));
+        assert_eq!(events(transaction.id).unwrap().len(), 1);
+        assert_eq!(active_transaction(account_id), Some(transaction.id));
+    }
+
+    #[test]
+    fn stale_transaction_is_rejected() {
+        let _database = database();
+        let (account_id, stale) = opened(Idle);
+        transition(&stale, BuyPending, "buy order sent").unwrap();
+
+        //Idle -> Closed is allowed but the transaction isn't idle any more
+        assert!(matches!(transition(&stale, Closed, "abandoned"), Err(TradingBotError::InvalidTransition(_))));
+        let history = events(stale.id).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].to_state, "BUY_PENDING");
+        assert_eq!(active_transaction(account_id), Some(stale.id));
+    }
+}
@@ -15,3 +15,25 @@ pub async fn send_status(
         .await?;
     Ok(())
 }
+
+//Cut text down to at most `max` characters, embed limits count characters not bytes
+pub fn truncate(text: &mut String, max: usize) {
+    if let Some((end, _)) = text.char_indices().nth(max) {
+        text.truncate(end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::truncate;
+
+    #[test]
+    fn truncates_on_a_character_boundary() {
+        let mut text = "✅".repeat(3);
+        truncate(&mut text, 2);
+        assert_eq!(text, "✅✅");
+        let mut short = "abc".to_string();
+        truncate(&mut short, 4000);
+        assert_eq!(short, "abc");
+    }
+}